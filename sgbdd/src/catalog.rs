@@ -0,0 +1,104 @@
+//! SHOW and DESCRIBE statements, answered from the schema without asking
+//! any database. Their rows are shown like the result of a SELECT.
+
+use std::collections::BTreeMap;
+
+use crate::{Catalog, Config, Value, config::Table, databases};
+
+/// Headers and rows answering `catalog`, whose table was already checked.
+pub fn show_catalog(catalog: &Catalog, config: &Config) -> (Vec<String>, Vec<Vec<Value>>) {
+    let (headers, rows): (&[&str], _) = match catalog {
+        Catalog::Tables => (
+            &["table", "columns", "fragments"],
+            config
+                .tables
+                .iter()
+                .map(|table| {
+                    vec![
+                        Value::Str(table.name.clone()),
+                        Value::Int(table.fields.len() as i64),
+                        Value::Int(table.fragments.len() as i64),
+                    ]
+                })
+                .collect(),
+        ),
+        Catalog::Columns { table } => (
+            &["column", "type"],
+            config
+                .tables
+                .iter()
+                .filter(|t| t.name == *table)
+                .flat_map(|t| &t.fields)
+                .map(|field| {
+                    vec![
+                        Value::Str(field.name.clone()),
+                        Value::Str(field.r#type.clone()),
+                    ]
+                })
+                .collect(),
+        ),
+        Catalog::Fragments { table } => (
+            &[
+                "table",
+                "fragment",
+                "manager",
+                "zone",
+                "column",
+                "stored as",
+                "type",
+            ],
+            config
+                .tables
+                .iter()
+                .filter(|t| table.as_ref().is_none_or(|table| t.name == *table))
+                .flat_map(fragment_rows)
+                .collect(),
+        ),
+        // The databases are spawned one per connection, so these are the
+        // zones queries can reach.
+        Catalog::Zones => {
+            let mut zones: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+            for (manager, _, zone) in databases::connections(config) {
+                zones.insert((zone, manager), 0);
+            }
+            for fragment in config.tables.iter().flat_map(|table| &table.fragments) {
+                *zones
+                    .get_mut(&(&*fragment.zone, &*fragment.manager))
+                    .unwrap() += 1;
+            }
+            (
+                &["zone", "manager", "fragments"],
+                zones
+                    .into_iter()
+                    .map(|((zone, manager), fragments)| {
+                        vec![
+                            Value::Str(zone.to_string()),
+                            Value::Str(manager.to_string()),
+                            Value::Int(fragments as i64),
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+    };
+
+    (headers.iter().map(ToString::to_string).collect(), rows)
+}
+
+/// One row per column of every fragment of `table`, with the name the
+/// fragment stores it under.
+fn fragment_rows(table: &Table) -> impl Iterator<Item = Vec<Value>> + '_ {
+    table.fragments.iter().flat_map(move |fragment| {
+        fragment.fields.iter().map(move |field| {
+            vec![
+                Value::Str(table.name.clone()),
+                Value::Str(fragment.name.clone()),
+                Value::Str(fragment.manager.clone()),
+                Value::Str(fragment.zone.clone()),
+                Value::Str(field.reference.clone()),
+                Value::Str(field.name.clone()),
+                Value::Str(field.r#type.clone()),
+            ]
+        })
+    })
+}
@@ -1,11 +1,32 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub(crate) tables: Vec<Table>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl Config {
+    /// Whether the database at `connection` holds a fragment of `table`.
+    pub fn has_fragment(&self, table: &str, connection: &str) -> bool {
+        self.tables
+            .iter()
+            .filter(|t| t.name == table)
+            .flat_map(|t| &t.fragments)
+            .any(|fragment| fragment.connection == connection)
+    }
+
+    /// Name and manager of the fragment of `table` in the database at `connection`.
+    pub fn fragment_of(&self, table: &str, connection: &str) -> Option<(&str, &str)> {
+        self.tables
+            .iter()
+            .filter(|t| t.name == table)
+            .flat_map(|t| &t.fragments)
+            .find(|fragment| fragment.connection == connection)
+            .map(|fragment| (fragment.name.as_str(), fragment.manager.as_str()))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct Table {
     pub(crate) name: String,
     pub(crate) fields: Vec<Field>,
@@ -13,7 +34,7 @@ pub(crate) struct Table {
     pub(crate) fragments: Vec<Fragment>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct Fragment {
     pub(crate) name: String,
     pub(crate) connection: String,
@@ -22,13 +43,13 @@ pub(crate) struct Fragment {
     pub(crate) fields: Vec<FragmentField>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct Field {
     pub(crate) name: String,
     pub(crate) r#type: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub(crate) struct FragmentField {
     pub(crate) name: String,
     pub(crate) reference: String,
@@ -1,6 +1,11 @@
-use std::sync::{
-    Arc,
-    mpsc::{Receiver, Sender},
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    time::Duration,
 };
 
 use super::Query;
@@ -8,20 +13,23 @@ use super::Query;
 pub trait DistributedConnection {
     fn execute_query(&self, query: QueryMessage);
     fn zone(&self) -> &str;
+    fn connection(&self) -> &str;
+    /// The database manager behind the connection, like "postgres".
+    fn manager(&self) -> &str;
 }
 
 #[derive(Debug)]
 pub struct QueryMessage {
     pub(crate) query: Arc<Query>,
     pub(crate) tx_result: Sender<QueryResult>,
-    pub(crate) rx_commit: Receiver<()>,
+    pub(crate) rx_commit: Receiver<Phase>,
 }
 
 impl QueryMessage {
     pub fn new(
         query: Arc<Query>,
         tx_result: Sender<QueryResult>,
-        rx_commit: Receiver<()>,
+        rx_commit: Receiver<Phase>,
     ) -> QueryMessage {
         QueryMessage {
             query,
@@ -31,7 +39,80 @@ impl QueryMessage {
     }
 }
 
+/// Two-phase commit, sent on the commit channel of a database once it
+/// returned its result. Dropping the sender aborts at any point.
 #[derive(Debug)]
+pub enum Phase {
+    /// Make the transaction durable without ending it, then vote on the
+    /// sender whether it can commit.
+    Prepare(Sender<bool>),
+    /// Every database voted yes, answered with whether the commit went through.
+    Commit(Sender<bool>),
+    Abort,
+}
+
+/// How a two-phase commit ended for all the databases in it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Committed,
+    /// Some database voted no or didn't vote in time, every one rolled back.
+    Aborted,
+    /// Every database prepared but the ones at positions `failed` of
+    /// `txs_commit` didn't confirm the commit, they have to be repaired by hand.
+    InDoubt {
+        failed: Vec<usize>,
+    },
+}
+
+/// How long the coordinator waits for a vote or a confirmation.
+const VOTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Commits the transactions behind `txs_commit` everywhere or nowhere: all
+/// of them prepare and vote, and they commit only when every vote is yes.
+pub fn two_phase_commit(txs_commit: Vec<Sender<Phase>>) -> Outcome {
+    // A database that is gone never votes.
+    let votes: Vec<_> = txs_commit
+        .iter()
+        .map(|tx_commit| {
+            let (tx_vote, rx_vote) = mpsc::channel();
+            let _ = tx_commit.send(Phase::Prepare(tx_vote));
+            rx_vote
+        })
+        .collect();
+    let prepared = votes
+        .iter()
+        .all(|rx_vote| rx_vote.recv_timeout(VOTE_TIMEOUT).unwrap_or(false));
+
+    if !prepared {
+        for tx_commit in &txs_commit {
+            let _ = tx_commit.send(Phase::Abort);
+        }
+        return Outcome::Aborted;
+    }
+
+    let confirmations: Vec<_> = txs_commit
+        .iter()
+        .map(|tx_commit| {
+            let (tx_done, rx_done) = mpsc::channel();
+            let _ = tx_commit.send(Phase::Commit(tx_done));
+            rx_done
+        })
+        .collect();
+    let failed: Vec<_> = confirmations
+        .iter()
+        .enumerate()
+        .filter(|(_, rx_done)| !rx_done.recv_timeout(VOTE_TIMEOUT).unwrap_or(false))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    if failed.is_empty() {
+        Outcome::Committed
+    } else {
+        Outcome::InDoubt { failed }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Value {
     Int(i64),
     Float(f64),
@@ -40,6 +121,94 @@ pub enum Value {
     Null,
 }
 
+/// Values are totally ordered so fragment results can be merged and deduplicated.
+///
+/// NULL sorts before everything and equals itself. Ints and floats compare
+/// by their exact numeric value, `-0.0` equals `0.0` and every NaN equals
+/// every other NaN and sorts after all numbers. Values of different types are ordered
+/// NULL < bool < number < string.
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Value::Int(l), Value::Int(r)) => l.cmp(r),
+            (Value::Int(l), Value::Float(r)) => int_float_cmp(*l, *r),
+            (Value::Float(l), Value::Int(r)) => int_float_cmp(*r, *l).reverse(),
+            (Value::Float(l), Value::Float(r)) => canonical(*l).total_cmp(&canonical(*r)),
+            (Value::Str(l), Value::Str(r)) => l.cmp(r),
+            (Value::Bool(l), Value::Bool(r)) => l.cmp(r),
+            (left, right) => left.rank().cmp(&right.rank()),
+        }
+    }
+}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rank().hash(state);
+        match self {
+            Value::Int(v) => v.hash(state),
+            // Equal to an int, so it has to hash like it.
+            Value::Float(v) if v.fract() == 0.0 && (-I64_END..I64_END).contains(v) => {
+                (*v as i64).hash(state)
+            }
+            Value::Float(v) => canonical(*v).to_bits().hash(state),
+            Value::Str(v) => v.hash(state),
+            Value::Bool(v) => v.hash(state),
+            Value::Null => (),
+        }
+    }
+}
+
+impl Value {
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Bool(_) => 1,
+            Value::Int(_) | Value::Float(_) => 2,
+            Value::Str(_) => 3,
+        }
+    }
+}
+
+/// 2^63, the first float past `i64::MAX`.
+const I64_END: f64 = 9_223_372_036_854_775_808.0;
+
+/// Compares without rounding the int to a float, which would make
+/// `2^53 + 1` equal to `2^53` as a float but not to `2^53` as an int.
+fn int_float_cmp(int: i64, float: f64) -> Ordering {
+    if float.is_nan() {
+        return Ordering::Less;
+    }
+    // The cast saturates, floats beyond i128 are still past every i64.
+    let whole = float.trunc();
+    (int as i128)
+        .cmp(&(whole as i128))
+        .then(whole.total_cmp(&float))
+}
+
+fn canonical(value: f64) -> f64 {
+    if value.is_nan() {
+        f64::NAN
+    } else if value == 0.0 {
+        0.0
+    } else {
+        value
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -58,10 +227,18 @@ pub enum QueryResult {
     Insert(u64),
     Update(u64),
     Delete(u64),
+    CreateTable,
+    AlterTable,
+    DropTable,
+    /// The native statement a fragment runs for the query of an EXPLAIN.
+    Explain(String),
+    Begin,
+    Commit,
+    Rollback,
 }
 
 macro_rules! distributed_connection {
-    ($name:ident) => {
+    ($name:ident, $manager:literal) => {
         pub(crate) struct $name {
             tx_query: Sender<QueryMessage>,
             conn: String,
@@ -86,10 +263,99 @@ macro_rules! distributed_connection {
             fn zone(&self) -> &str {
                 &self.zone
             }
+
+            fn connection(&self) -> &str {
+                &self.conn
+            }
+
+            fn manager(&self) -> &str {
+                $manager
+            }
         }
     };
 }
 
-distributed_connection!(DistributedPgConnection);
-distributed_connection!(DistributedMongoConnection);
-distributed_connection!(DistributedNeo4jConnection);
+distributed_connection!(DistributedPgConnection, "postgres");
+distributed_connection!(DistributedMongoConnection, "mongo");
+distributed_connection!(DistributedNeo4jConnection, "neo4j");
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn ints_and_floats_compare_exactly() {
+        let big = 1_i64 << 53;
+        let values = [
+            Value::Int(big),
+            Value::Float(big as f64),
+            Value::Int(big + 1),
+        ];
+        // Through f64 the two ints would both equal the float.
+        assert_eq!(values[0], values[1]);
+        assert!(values[1] < values[2]);
+        assert!(values[0] < values[2]);
+
+        assert!(Value::Int(i64::MAX) < Value::Float(I64_END));
+        assert!(Value::Int(i64::MIN) == Value::Float(-I64_END));
+        assert!(Value::Int(0) > Value::Float(-0.5));
+        assert!(Value::Int(0) == Value::Float(-0.0));
+        assert!(Value::Int(i64::MAX) < Value::Float(f64::INFINITY));
+        assert!(Value::Int(i64::MIN) > Value::Float(f64::NEG_INFINITY));
+        assert!(Value::Int(i64::MAX) < Value::Float(f64::NAN));
+    }
+
+    /// A database that votes `vote` and answers a commit with `confirm`.
+    fn participant(vote: bool, confirm: bool) -> Sender<Phase> {
+        let (tx_commit, rx_commit) = mpsc::channel();
+        std::thread::spawn(move || {
+            while let Ok(phase) = rx_commit.recv() {
+                match phase {
+                    Phase::Prepare(tx_vote) => {
+                        let _ = tx_vote.send(vote);
+                    }
+                    Phase::Commit(tx_done) => {
+                        let _ = tx_done.send(confirm);
+                        break;
+                    }
+                    Phase::Abort => break,
+                }
+            }
+        });
+        tx_commit
+    }
+
+    #[test]
+    fn two_phase_commit_names_the_databases_in_doubt() {
+        let outcome = two_phase_commit(vec![participant(true, true), participant(true, true)]);
+        assert_eq!(outcome, Outcome::Committed);
+
+        let outcome = two_phase_commit(vec![participant(true, true), participant(false, true)]);
+        assert_eq!(outcome, Outcome::Aborted);
+
+        let outcome = two_phase_commit(vec![
+            participant(true, false),
+            participant(true, true),
+            participant(true, false),
+        ]);
+        assert_eq!(outcome, Outcome::InDoubt { failed: vec![0, 2] });
+    }
+
+    #[test]
+    fn equal_values_hash_alike() {
+        let set: HashSet<Value> = [
+            Value::Int(3),
+            Value::Float(3.0),
+            Value::Int(0),
+            Value::Float(-0.0),
+            Value::Int(i64::MIN),
+            Value::Float(-I64_END),
+            Value::Float(I64_END),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(set.len(), 4);
+    }
+}
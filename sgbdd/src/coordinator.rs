@@ -0,0 +1,448 @@
+//! Runs checked statements on the fragments: each query is routed to the
+//! databases of the zones that can hold its rows, and their results are
+//! merged into one.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    sync::{
+        Arc, LazyLock,
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    Config, DistributedConnection, JoinPlan, Literal, Operand, Outcome, Phase, Query, QueryMessage,
+    QueryResult, Where, fragment_query, merge_select, subquery, two_phase_commit,
+};
+
+static NORTH: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    [
+        "Baja California",
+        "Baja California Sur",
+        "Sonora",
+        "Chihuahua",
+        "Coahuila de Zaragoza",
+        "Nuevo León",
+        "Tamaulipas",
+        "Durango",
+        "Sinaloa",
+    ]
+    .into_iter()
+    .collect()
+});
+
+static CENTER: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    [
+        "Aguascalientes",
+        "Zacatecas",
+        "San Luis Potosí",
+        "Nayarit",
+        "Jalisco",
+        "Colima",
+        "Michoacán de Ocampo",
+        "Guanajuato",
+        "Querétaro",
+        "Hidalgo",
+        "México",
+        "Ciudad de México",
+        "Tlaxcala",
+        "Puebla",
+        "Morelos",
+    ]
+    .into_iter()
+    .collect()
+});
+
+static SOUTH: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
+    [
+        "Guerrero",
+        "Oaxaca",
+        "Chiapas",
+        "Veracruz de Ignacio de la Llave",
+        "Tabasco",
+        "Campeche",
+        "Yucatán",
+        "Quintana Roo",
+    ]
+    .into_iter()
+    .collect()
+});
+
+/// Runs a checked SELECT, INSERT, UPDATE or DELETE. The rows changed in
+/// every fragment are added up, and outside a `transaction` they are
+/// committed before returning.
+pub fn execute(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    query: &Query,
+    transaction: bool,
+) -> anyhow::Result<QueryResult> {
+    // Subqueries run first, the fragments only see their results.
+    let Some(query) = run_subqueries(databases, config, query)? else {
+        return empty_result(query, config);
+    };
+    let query = Arc::new(query);
+
+    if let Query::Select { .. } = *query {
+        return execute_select(databases, config, query);
+    }
+
+    let mut rxs_result = Vec::new();
+    let mut txs_commit = Vec::new();
+    execute_query(
+        databases,
+        config,
+        query.clone(),
+        &mut rxs_result,
+        &mut txs_commit,
+    )?;
+    let results = collect_results(rxs_result)?;
+
+    if !transaction {
+        commit(txs_commit)?;
+    }
+    let rows = results
+        .iter()
+        .map(|result| match result {
+            QueryResult::Insert(n) | QueryResult::Update(n) | QueryResult::Delete(n) => *n,
+            _ => 0,
+        })
+        .sum();
+    Ok(match *query {
+        Query::Insert { .. } => QueryResult::Insert(rows),
+        Query::Update { .. } => QueryResult::Update(rows),
+        _ => QueryResult::Delete(rows),
+    })
+}
+
+/// `query` with its subqueries replaced by the rows they fetch from the
+/// fragments, `None` when its filter can never match.
+pub fn run_subqueries(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    query: &Query,
+) -> anyhow::Result<Option<Query>> {
+    subquery::resolve_subqueries(query, |inner| {
+        let result = match run_subqueries(databases, config, inner)? {
+            Some(inner) => execute_select(databases, config, Arc::new(inner))?,
+            None => empty_result(inner, config)?,
+        };
+        match result {
+            QueryResult::Select(rows) => Ok(rows),
+            result => Err(anyhow!("expected rows, found {result:?}")),
+        }
+    })
+}
+
+/// Result of a query whose filter can never match, no fragment has to run it.
+fn empty_result(query: &Query, config: &Config) -> anyhow::Result<QueryResult> {
+    match query {
+        Query::Select { .. } => merge_select(query, config, Vec::new()),
+        Query::Update { .. } => Ok(QueryResult::Update(0)),
+        Query::Delete { .. } => Ok(QueryResult::Delete(0)),
+        Query::Insert { .. } => unreachable!("INSERT has no filter"),
+        Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } => {
+            unreachable!("DDL has no filter")
+        }
+        Query::Explain { .. } => unreachable!("EXPLAIN runs on its own"),
+        Query::Catalog(_) => unreachable!("SHOW and DESCRIBE are answered from the schema"),
+        Query::Begin | Query::Commit | Query::Rollback => {
+            unreachable!("transactions are handled by the caller")
+        }
+    }
+}
+
+fn execute_select(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    query: Arc<Query>,
+) -> anyhow::Result<QueryResult> {
+    if matches!(&*query, Query::Select { joins, .. } if !joins.is_empty()) {
+        return execute_join(databases, &query, config);
+    }
+
+    let mut rxs_result = Vec::new();
+    let mut txs_commit = Vec::new();
+    execute_query(
+        databases,
+        config,
+        query.clone(),
+        &mut rxs_result,
+        &mut txs_commit,
+    )?;
+
+    let results = collect_results(rxs_result)?;
+    merge_select(&query, config, results)
+}
+
+/// Fetches every table of a join from its fragments, one after the other so
+/// the keys already joined can restrict the next scan.
+fn execute_join(
+    databases: &[Box<dyn DistributedConnection>],
+    query: &Query,
+    config: &Config,
+) -> anyhow::Result<QueryResult> {
+    let mut plan = JoinPlan::new(query, config)?;
+    while let Some(scan) = plan.next_scan() {
+        match execute_select(databases, config, scan)? {
+            QueryResult::Select(rows) => plan.push_rows(rows),
+            result => return Err(anyhow!("expected rows, found {result:?}")),
+        }
+    }
+
+    plan.finish()
+}
+
+pub fn collect_results(rxs_result: Vec<Receiver<QueryResult>>) -> anyhow::Result<Vec<QueryResult>> {
+    rxs_result.iter().map(recv_result).collect()
+}
+
+pub fn recv_result(rx_result: &Receiver<QueryResult>) -> anyhow::Result<QueryResult> {
+    match rx_result.recv_timeout(Duration::from_secs(5)) {
+        Ok(res) => Ok(res),
+        Err(RecvTimeoutError::Timeout) => Err(anyhow!("timeout, query canceled")),
+        Err(RecvTimeoutError::Disconnected) => Err(anyhow!("failed to execute, query canceled")),
+    }
+}
+
+fn execute_query<'a>(
+    databases: &'a [Box<dyn DistributedConnection>],
+    config: &Config,
+    query: Arc<Query>,
+    rxs_result: &mut Vec<Receiver<QueryResult>>,
+    txs_commit: &mut Vec<(&'a dyn DistributedConnection, Sender<Phase>)>,
+) -> anyhow::Result<()> {
+    for (db, query, _) in route(databases, config, query)? {
+        let (tx_result, rx_result) = std::sync::mpsc::channel();
+        rxs_result.push(rx_result);
+        let (tx_commit, rx_commit) = std::sync::mpsc::channel();
+        txs_commit.push((&**db, tx_commit));
+
+        let query_message = QueryMessage::new(query, tx_result, rx_commit);
+        db.execute_query(query_message);
+    }
+
+    Ok(())
+}
+
+/// Why a database is sent a query.
+#[derive(Debug, Clone, Copy)]
+pub enum Route {
+    /// The ZONE clause names its zone.
+    Zone,
+    /// The filter only matches states of its zone.
+    Estado,
+    /// Some of the inserted rows are from a state of its zone.
+    Rows,
+    /// Nothing rules its zone out.
+    Broadcast,
+}
+
+impl Display for Route {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Route::Zone => write!(f, "ZONE clause"),
+            Route::Estado => write!(f, "Estado pruning"),
+            Route::Rows => write!(f, "rows by Estado"),
+            Route::Broadcast => write!(f, "broadcast"),
+        }
+    }
+}
+
+/// The databases `query` is sent to, each with the query it runs and why.
+pub fn route<'a>(
+    databases: &'a [Box<dyn DistributedConnection>],
+    config: &Config,
+    query: Arc<Query>,
+) -> anyhow::Result<Vec<(&'a Box<dyn DistributedConnection>, Arc<Query>, Route)>> {
+    match &*query {
+        Query::Insert {
+            table,
+            columns,
+            values,
+        } => {
+            let state_idx = columns.iter().position(|c| c == "Estado").unwrap();
+
+            let mut north = Vec::new();
+            let mut center = Vec::new();
+            let mut south = Vec::new();
+
+            for row in values {
+                let Literal::Str(state) = &row[state_idx] else {
+                    return Err(anyhow!("'Estado' must be a state name"));
+                };
+                match state_zone(state)? {
+                    "Norte" => north.push(row.clone()),
+                    "Centro" => center.push(row.clone()),
+                    _ => south.push(row.clone()),
+                }
+            }
+            // A zone without a fragment of the table has nowhere to store its rows.
+            let databases: Vec<_> = databases
+                .iter()
+                .filter(|db| config.has_fragment(table, db.connection()))
+                .collect();
+            for (zone, rows) in [("Norte", &north), ("Centro", &center), ("Sur", &south)] {
+                if !rows.is_empty() && !databases.iter().any(|db| db.zone() == zone) {
+                    return Err(anyhow!("no fragment of '{table}' in zone '{zone}'"));
+                }
+            }
+
+            let mut routes = Vec::new();
+            for db in databases {
+                let rows = match db.zone() {
+                    "Norte" => &north,
+                    "Centro" => &center,
+                    "Sur" => &south,
+                    zone => return Err(anyhow!("unknown zone '{zone}'")),
+                };
+                // A zone none of the rows belong to has nothing to insert.
+                if rows.is_empty() {
+                    continue;
+                }
+                let query = Query::Insert {
+                    table: table.clone(),
+                    columns: columns.clone(),
+                    values: rows.clone(),
+                };
+                routes.push((db, Arc::new(query), Route::Rows));
+            }
+
+            Ok(routes)
+        }
+        Query::Select {
+            table,
+            zones,
+            filter,
+            ..
+        }
+        | Query::Update {
+            table,
+            zones,
+            filter,
+            ..
+        }
+        | Query::Delete {
+            table,
+            zones,
+            filter,
+            ..
+        } => {
+            let databases: Vec<_> = databases
+                .iter()
+                .filter(|db| config.has_fragment(table, db.connection()))
+                .collect();
+            let (dbs, route) = select_databases(&databases, zones, filter)?;
+            let query = fragment_query(query.clone());
+
+            Ok(dbs
+                .into_iter()
+                .map(|db| (db, Arc::clone(&query), route))
+                .collect())
+        }
+        Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } => {
+            unreachable!("DDL runs on its own")
+        }
+        Query::Explain { .. } => unreachable!("EXPLAIN runs on its own"),
+        Query::Catalog(_) => unreachable!("SHOW and DESCRIBE are answered from the schema"),
+        Query::Begin | Query::Commit | Query::Rollback => {
+            unreachable!("transactions are handled by the caller")
+        }
+    }
+}
+
+fn select_databases<'a>(
+    databases: &[&'a Box<dyn DistributedConnection>],
+    zones: &Option<Vec<String>>,
+    filter: &Option<Where>,
+) -> anyhow::Result<(Vec<&'a Box<dyn DistributedConnection>>, Route)> {
+    if let Some(zones) = zones {
+        let dbs = databases
+            .iter()
+            .copied()
+            .filter(|db| zones.contains(&db.zone().to_string()))
+            .collect();
+        Ok((dbs, Route::Zone))
+    } else if let Some(filter) = filter
+        && let Some(zones) = filter_zones(filter)?
+    {
+        let dbs = databases
+            .iter()
+            .copied()
+            .filter(|db| zones.contains(db.zone()))
+            .collect();
+        Ok((dbs, Route::Estado))
+    } else {
+        Ok((databases.to_vec(), Route::Broadcast))
+    }
+}
+
+/// Zones that can hold rows matching `filter`, `None` when every zone must be queried.
+fn filter_zones(filter: &Where) -> anyhow::Result<Option<HashSet<&'static str>>> {
+    match filter {
+        Where::Comparison {
+            column,
+            op,
+            value: Operand::Value(Literal::Str(state)),
+        } if column == "Estado" && op == "=" => Ok(Some(HashSet::from([state_zone(state)?]))),
+        Where::In { column, values } if column == "Estado" => Ok(Some(
+            values
+                .iter()
+                .map(|state| match state {
+                    Literal::Str(state) => state_zone(state),
+                    state => Err(anyhow!("unknown state {state}")),
+                })
+                .collect::<anyhow::Result<_>>()?,
+        )),
+        Where::And(left, right) => match (filter_zones(left)?, filter_zones(right)?) {
+            (Some(left), Some(right)) => Ok(Some(&left & &right)),
+            (Some(zones), None) | (None, Some(zones)) => Ok(Some(zones)),
+            (None, None) => Ok(None),
+        },
+        Where::Or(left, right) => match (filter_zones(left)?, filter_zones(right)?) {
+            (Some(left), Some(right)) => Ok(Some(&left | &right)),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+fn state_zone(state: &str) -> anyhow::Result<&'static str> {
+    if NORTH.contains(state) {
+        Ok("Norte")
+    } else if CENTER.contains(state) {
+        Ok("Centro")
+    } else if SOUTH.contains(state) {
+        Ok("Sur")
+    } else {
+        Err(anyhow!("unknown state '{state}'"))
+    }
+}
+
+/// Two-phase commit of the databases that ran a statement, each with the
+/// sender of its commit channel. An error tells what the outcome was when
+/// it didn't commit everywhere, naming the databases left to repair.
+pub fn commit(txs_commit: Vec<(&dyn DistributedConnection, Sender<Phase>)>) -> anyhow::Result<()> {
+    let (databases, txs_commit): (Vec<_>, Vec<_>) = txs_commit.into_iter().unzip();
+    match two_phase_commit(txs_commit) {
+        Outcome::Committed => Ok(()),
+        Outcome::Aborted => Err(anyhow!(
+            "a database couldn't prepare, transaction rolled back"
+        )),
+        Outcome::InDoubt { failed } => {
+            let failed: Vec<_> = failed
+                .into_iter()
+                .map(|idx| format!("{} ({})", databases[idx].zone(), databases[idx].manager()))
+                .collect();
+            Err(anyhow!(
+                "inconsistent state: the transaction committed, but {} didn't confirm it \
+                 and must be repaired by hand",
+                failed.join(", ")
+            ))
+        }
+    }
+}
@@ -1,11 +1,22 @@
 use anyhow::anyhow;
 
-use crate::token::Token;
+use crate::{
+    diagnostic::{Diagnostic, Span},
+    token::Token,
+};
 
 use std::str::Chars;
 
 pub struct Cursor<'a> {
     chars: Chars<'a>,
+    /// Position of the next character.
+    line: usize,
+    column: usize,
+    /// Characters consumed so far.
+    consumed: usize,
+    /// `?` placeholders seen so far.
+    positional: usize,
+    numbered: bool,
 }
 
 const EOF: char = '\0';
@@ -14,22 +25,98 @@ impl<'a> Cursor<'a> {
     pub fn new(source: &'a str) -> Self {
         Self {
             chars: source.chars(),
+            line: 1,
+            column: 1,
+            consumed: 0,
+            positional: 0,
+            numbered: false,
         }
     }
 
-    pub fn advance_token(&mut self) -> anyhow::Result<Token> {
+    /// The next token and where it's written, `Token::Eof` at the end of the source.
+    pub fn advance_token(&mut self) -> Result<(Token, Span), Diagnostic> {
+        self.skip_trivia()?;
+
+        let (line, column, consumed) = (self.line, self.column, self.consumed);
+        let token = self.token();
+        let span = Span {
+            line,
+            column,
+            len: self.consumed - consumed,
+        };
+
+        token
+            .map(|token| (token, span))
+            .map_err(|e| Diagnostic::new(e, Some(span)))
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), Diagnostic> {
+        loop {
+            match (self.first(), self.second()) {
+                (' ' | '\r' | '\t' | '\n', _) => {
+                    self.bump();
+                }
+                ('-', '-') => self.line_comment(),
+                ('/', '*') => {
+                    let span = Span {
+                        line: self.line,
+                        column: self.column,
+                        len: 2,
+                    };
+                    self.block_comment()
+                        .map_err(|e| Diagnostic::new(e, Some(span)))?;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn token(&mut self) -> anyhow::Result<Token> {
         let token = match self.bump() {
             '(' => Token::LeftParen,
             ')' => Token::RightParen,
             '[' => Token::LeftBracket,
             ']' => Token::RightBracket,
             ';' => Token::Semicolon,
+            ',' => Token::Comma,
             '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '/' => Token::Slash,
+            '?' => self.placeholder(None)?,
+            '$' => self.placeholder(Some(self.first()))?,
+            '|' if self.first() == '|' => {
+                self.bump();
+                Token::Concat
+            }
             '=' => Token::Equal,
-            ' ' | ',' | '\r' | '\t' | '\n' => self.advance_token()?,
-            c if c == '"' || c == '\'' => self.string(c)?,
+            '!' if self.first() == '=' => {
+                self.bump();
+                Token::Neq
+            }
+            '<' => match self.first() {
+                '=' => {
+                    self.bump();
+                    Token::Le
+                }
+                '>' => {
+                    self.bump();
+                    Token::Neq
+                }
+                _ => Token::Lt,
+            },
+            '>' => match self.first() {
+                '=' => {
+                    self.bump();
+                    Token::Ge
+                }
+                _ => Token::Gt,
+            },
+            '\'' => self.string('\'')?,
+            '"' => self.identifier('"')?,
             c @ '0'..='9' => self.number(c)?,
-            c if c.is_alphanumeric() => self.identifier(c),
+            c if is_identifier_start(c) => self.identifier(c)?,
             EOF => Token::Eof,
             a => return Err(anyhow!("found invalid character {a:?}")),
         };
@@ -37,76 +124,252 @@ impl<'a> Cursor<'a> {
         Ok(token)
     }
 
-    fn identifier(&mut self, first_char: char) -> Token {
-        let mut ident = String::from(first_char);
+    /// Skips a `--` comment up to the end of its line.
+    fn line_comment(&mut self) {
+        while !matches!(self.bump(), '\n' | EOF) {}
+    }
 
-        while matches!(self.first(), c if c != ' ' && c != ',' && c != ';' && c != ')' && c != EOF)
-        {
+    /// Skips a `/* */` comment, which can span several lines.
+    fn block_comment(&mut self) -> anyhow::Result<()> {
+        self.bump();
+        self.bump();
+        loop {
+            match self.bump() {
+                '*' if self.first() == '/' => {
+                    self.bump();
+                    return Ok(());
+                }
+                EOF => return Err(anyhow!("comment unterminated")),
+                _ => (),
+            }
+        }
+    }
+
+    /// A name, or a keyword when it's written without quotes. A qualified
+    /// `table.column` is a single identifier, each part may be quoted.
+    fn identifier(&mut self, first_char: char) -> anyhow::Result<Token> {
+        let mut ident = String::new();
+        let mut first_char = first_char;
+        let mut quoted = false;
+
+        loop {
+            if first_char == '"' {
+                quoted = true;
+                self.quoted_identifier(&mut ident)?;
+            } else {
+                ident.push(first_char);
+                while is_identifier_part(self.first()) {
+                    ident.push(self.bump());
+                }
+            }
+
+            if self.first() != '.' {
+                break;
+            }
             ident.push(self.bump());
+            first_char = match self.bump() {
+                c if c == '"' || is_identifier_start(c) => c,
+                _ => return Err(anyhow!("expected a name after '.'")),
+            };
+        }
+
+        if !quoted && let Some(token) = keyword(&ident) {
+            return Ok(token);
+        }
+
+        Ok(Token::Identifier(ident))
+    }
+
+    /// A `"quoted"` name, which can hold any character, `""` stands for a quote.
+    fn quoted_identifier(&mut self, ident: &mut String) -> anyhow::Result<()> {
+        let start = ident.len();
+        loop {
+            match self.bump() {
+                '"' if self.first() == '"' => ident.push(self.bump()),
+                '"' if ident.len() == start => return Err(anyhow!("empty quoted name")),
+                '"' => return Ok(()),
+                EOF => return Err(anyhow!("quoted name unterminated")),
+                c => ident.push(c),
+            }
         }
+    }
+
+    fn placeholder(&mut self, first_digit: Option<char>) -> anyhow::Result<Token> {
+        let idx = match first_digit {
+            Some('0'..='9') => {
+                let mut number = String::new();
+                while let '0'..='9' = self.first() {
+                    number.push(self.bump());
+                }
+                self.numbered = true;
+                number.parse::<usize>()?
+            }
+            Some(_) => return Err(anyhow!("expected the number of the placeholder after '$'")),
+            None => {
+                self.positional += 1;
+                self.positional
+            }
+        };
 
-        if let Some(token) = self.keyword(&ident) {
-            return token;
+        if self.numbered && self.positional > 0 {
+            return Err(anyhow!("'?' and '$n' placeholders can't be mixed"));
+        }
+        if idx == 0 {
+            return Err(anyhow!("placeholders are numbered from $1"));
         }
 
-        Token::Identifier(ident)
+        Ok(Token::Placeholder(idx))
     }
 
+    /// An integer, or a float when it has a fraction or an exponent.
     fn number(&mut self, first_char: char) -> anyhow::Result<Token> {
         let mut number = String::from(first_char);
+        let mut float = false;
 
         while let '0'..='9' = self.first() {
             number.push(self.bump());
         }
         if self.first() == '.' {
+            float = true;
             number.push(self.bump());
             while let '0'..='9' = self.first() {
                 number.push(self.bump());
             }
         }
+        if matches!(self.first(), 'e' | 'E') {
+            let mut rest = self.chars.clone().skip(1);
+            let digit = match rest.next() {
+                Some('+' | '-') => rest.next(),
+                c => c,
+            };
+            if digit.is_some_and(|c| c.is_ascii_digit()) {
+                float = true;
+                number.push(self.bump());
+                if matches!(self.first(), '+' | '-') {
+                    number.push(self.bump());
+                }
+                while let '0'..='9' = self.first() {
+                    number.push(self.bump());
+                }
+            }
+        }
 
-        let number = number.parse::<f64>()?;
-        Ok(Token::Number(number))
+        if float {
+            match number.parse::<f64>()? {
+                value if value.is_finite() => Ok(Token::Float(value)),
+                _ => Err(anyhow!("number {number} out of range")),
+            }
+        } else {
+            let number = number
+                .parse()
+                .map_err(|_| anyhow!("integer {number} out of range"))?;
+            Ok(Token::Int(number))
+        }
     }
 
+    /// A quoted string, the delimiter is written twice to include it.
     fn string(&mut self, delimiter: char) -> anyhow::Result<Token> {
         let mut string = String::new();
 
-        while matches!(self.first(), c if c != delimiter) {
-            string.push(self.bump());
+        loop {
+            match self.bump() {
+                c if c == delimiter && self.first() == delimiter => {
+                    string.push(self.bump());
+                }
+                c if c == delimiter => return Ok(Token::Str(string)),
+                EOF => return Err(anyhow!("string unterminated")),
+                c => string.push(c),
+            }
         }
+    }
 
-        if self.first() != delimiter {
-            return Err(anyhow!("string untermineted"));
+    fn bump(&mut self) -> char {
+        match self.chars.next() {
+            Some('\n') => {
+                self.line += 1;
+                self.column = 1;
+                self.consumed += 1;
+                '\n'
+            }
+            Some(c) => {
+                self.column += 1;
+                self.consumed += 1;
+                c
+            }
+            None => EOF,
         }
-
-        self.bump();
-        Ok(Token::Str(string))
     }
 
-    fn keyword(&self, ident: &str) -> Option<Token> {
-        match &*ident.to_lowercase() {
-            "true" => Some(Token::True),
-            "false" => Some(Token::False),
-            "select" => Some(Token::Select),
-            "insert" => Some(Token::Insert),
-            "into" => Some(Token::Into),
-            "values" => Some(Token::Values),
-            "update" => Some(Token::Update),
-            "set" => Some(Token::Set),
-            "delete" => Some(Token::Delete),
-            "from" => Some(Token::From),
-            "where" => Some(Token::Where),
-            "zone" => Some(Token::Zone),
-            _ => None,
-        }
+    fn first(&self) -> char {
+        self.chars.clone().next().unwrap_or(EOF)
     }
 
-    fn bump(&mut self) -> char {
-        self.chars.next().unwrap_or(EOF)
+    fn second(&self) -> char {
+        self.chars.clone().nth(1).unwrap_or(EOF)
     }
+}
 
-    fn first(&self) -> char {
-        self.chars.clone().next().unwrap_or(EOF)
+pub(crate) fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+pub(crate) fn is_identifier_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The keyword `ident` spells, in any case.
+pub(crate) fn keyword(ident: &str) -> Option<Token> {
+    match &*ident.to_lowercase() {
+        "true" => Some(Token::True),
+        "false" => Some(Token::False),
+        "select" => Some(Token::Select),
+        "distinct" => Some(Token::Distinct),
+        "as" => Some(Token::As),
+        "insert" => Some(Token::Insert),
+        "into" => Some(Token::Into),
+        "values" => Some(Token::Values),
+        "update" => Some(Token::Update),
+        "set" => Some(Token::Set),
+        "delete" => Some(Token::Delete),
+        "from" => Some(Token::From),
+        "join" => Some(Token::Join),
+        "inner" => Some(Token::Inner),
+        "on" => Some(Token::On),
+        "where" => Some(Token::Where),
+        "zone" => Some(Token::Zone),
+        "and" => Some(Token::And),
+        "or" => Some(Token::Or),
+        "not" => Some(Token::Not),
+        "in" => Some(Token::In),
+        "exists" => Some(Token::Exists),
+        "between" => Some(Token::Between),
+        "like" => Some(Token::Like),
+        "is" => Some(Token::Is),
+        "null" => Some(Token::Null),
+        "order" => Some(Token::Order),
+        "by" => Some(Token::By),
+        "asc" => Some(Token::Asc),
+        "desc" => Some(Token::Desc),
+        "limit" => Some(Token::Limit),
+        "offset" => Some(Token::Offset),
+        "group" => Some(Token::Group),
+        "having" => Some(Token::Having),
+        "create" => Some(Token::Create),
+        "table" => Some(Token::Table),
+        "fragment" => Some(Token::Fragment),
+        "alter" => Some(Token::Alter),
+        "add" => Some(Token::Add),
+        "drop" => Some(Token::Drop),
+        "rename" => Some(Token::Rename),
+        "column" => Some(Token::Column),
+        "to" => Some(Token::To),
+        "explain" => Some(Token::Explain),
+        "analyze" => Some(Token::Analyze),
+        "show" => Some(Token::Show),
+        "describe" => Some(Token::Describe),
+        "begin" => Some(Token::Begin),
+        "commit" => Some(Token::Commit),
+        "rollback" => Some(Token::Rollback),
+        _ => None,
     }
 }
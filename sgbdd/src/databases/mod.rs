@@ -2,7 +2,10 @@ mod mongo;
 mod neo4j;
 mod postgres;
 
-use crate::{QueryMessage, config::FragmentField};
+use crate::{
+    Aggregate, Alteration, BinaryOp, Expr, Phase, QueryMessage, QueryResult, SelectItem,
+    config::FragmentField,
+};
 
 use super::{Config, Query};
 use std::{
@@ -45,11 +48,67 @@ pub(crate) fn generate_id() -> u64 {
     NEXT_ID.fetch_add(1, Ordering::SeqCst)
 }
 
+/// Where an INSERT takes the id of every row from.
+#[derive(Clone, Copy)]
+pub enum Ids {
+    Generated,
+    /// Shown as `$id`, so explaining an INSERT uses up no id.
+    Placeholder,
+}
+
+impl Ids {
+    fn next(self) -> Option<u64> {
+        match self {
+            Ids::Generated => Some(generate_id()),
+            Ids::Placeholder => None,
+        }
+    }
+}
+
+/// How the statement of a backend shows an id that isn't generated.
+const ID_PLACEHOLDER: &str = "$id";
+
 pub trait Database {
     type QueryType;
 
-    fn query(&self, sql: &Query) -> Self::QueryType;
+    fn query(&self, sql: &Query) -> Self::QueryType {
+        self.query_with_ids(sql, Ids::Generated)
+    }
+    /// [`Database::query`] with the ids of inserted rows taken from `ids`.
+    fn query_with_ids(&self, sql: &Query, ids: Ids) -> Self::QueryType;
     fn execute(&mut self, query_message: QueryMessage) -> anyhow::Result<()>;
+    /// The statement [`Database::query`] builds, as the backend would show it,
+    /// with a placeholder for the ids of inserted rows.
+    fn explain(&self, sql: &Query) -> String;
+    /// Opens a transaction that every statement runs in until [`Database::end`],
+    /// a statement then doesn't wait for the coordinator to commit it.
+    fn begin(&mut self) -> anyhow::Result<()>;
+    /// First phase of two-phase commit, makes the open transaction durable
+    /// without ending it. An error is a vote to abort.
+    fn prepare(&mut self) -> anyhow::Result<()>;
+    /// Commits the open transaction, or rolls it back when `commit` is false.
+    fn end(&mut self, commit: bool) -> anyhow::Result<()>;
+}
+
+/// Follows the coordinator through two-phase commit of the open transaction
+/// of `db`, returns whether it was committed.
+pub(crate) fn finish(db: &mut impl Database, rx_commit: &Receiver<Phase>) -> bool {
+    loop {
+        match rx_commit.recv() {
+            Ok(Phase::Prepare(tx_vote)) => {
+                let _ = tx_vote.send(db.prepare().is_ok());
+            }
+            Ok(Phase::Commit(tx_done)) => {
+                let committed = db.end(true).is_ok();
+                let _ = tx_done.send(committed);
+                return committed;
+            }
+            Ok(Phase::Abort) | Err(_) => {
+                let _ = db.end(false);
+                return false;
+            }
+        }
+    }
 }
 
 pub(crate) fn connections(config: &Config) -> HashSet<(&str, &str, &str)> {
@@ -103,6 +162,18 @@ pub(crate) fn database_info(connection: &str, config: &Config) -> DatabaseInfo {
         .collect()
 }
 
+/// `info` with the fragment table and field names written as `quote` spells
+/// an identifier of the backend, so they can hold any character.
+pub(crate) fn quote_names(mut info: DatabaseInfo, quote: fn(&str) -> String) -> DatabaseInfo {
+    for (table, fields) in info.values_mut() {
+        *table = quote(table);
+        for field in fields.values_mut() {
+            field.name = quote(&field.name);
+        }
+    }
+    info
+}
+
 pub(crate) fn wildcard(connection: &str, config: &Config) -> HashMap<String, Vec<String>> {
     config
         .tables
@@ -126,11 +197,191 @@ pub(crate) fn wildcard(connection: &str, config: &Config) -> HashMap<String, Vec
         .collect()
 }
 
+/// What a CREATE TABLE adds to the info and wildcard of a database, which is
+/// sent just the fragment it stores.
+pub(crate) fn created_table(query: &Query) -> (DatabaseInfo, HashMap<String, Vec<String>>) {
+    let Query::CreateTable {
+        table,
+        columns,
+        fragments,
+    } = query
+    else {
+        unreachable!("expected CREATE TABLE")
+    };
+    let fragment = &fragments[0];
+
+    let fields = fragment
+        .columns
+        .iter()
+        .map(|(column, name)| {
+            let (_, r#type) = columns.iter().find(|(c, _)| c == column).unwrap();
+            let field = FragmentField {
+                name: name.clone(),
+                reference: column.clone(),
+                r#type: r#type.clone(),
+            };
+            (column.clone(), field)
+        })
+        .collect();
+    let info = HashMap::from([(table.clone(), (fragment.name.clone(), fields))]);
+    let wildcard = HashMap::from([(
+        table.clone(),
+        columns.iter().map(|(column, _)| column.clone()).collect(),
+    )]);
+
+    (info, wildcard)
+}
+
+/// Applies an ALTER or DROP TABLE to the info and wildcard of a database,
+/// `quote` spells a new field name the way [`quote_names`] does.
+pub(crate) fn altered_table(
+    info: &mut DatabaseInfo,
+    wildcard: &mut HashMap<String, Vec<String>>,
+    query: &Query,
+    quote: fn(&str) -> String,
+) {
+    match query {
+        Query::AlterTable { table, alteration } => {
+            let fields = &mut info.get_mut(table).unwrap().1;
+            let columns = wildcard.get_mut(table).unwrap();
+            match alteration {
+                Alteration::AddColumn { column, r#type } => {
+                    let field = FragmentField {
+                        name: quote(column),
+                        reference: column.clone(),
+                        r#type: r#type.clone(),
+                    };
+                    fields.insert(column.clone(), field);
+                    columns.push(column.clone());
+                }
+                Alteration::DropColumn { column } => {
+                    fields.remove(column);
+                    columns.retain(|c| c != column);
+                }
+                Alteration::RenameColumn { column, name } => {
+                    let mut field = fields.remove(column).unwrap();
+                    field.name = quote(name);
+                    field.reference.clone_from(name);
+                    fields.insert(name.clone(), field);
+                    for c in columns.iter_mut().filter(|c| *c == column) {
+                        c.clone_from(name);
+                    }
+                }
+            }
+        }
+        Query::DropTable { table } => {
+            info.remove(table);
+            wildcard.remove(table);
+        }
+        query => unreachable!("expected ALTER or DROP TABLE, found {query}"),
+    }
+}
+
+/// The select list with `*` replaced by every field of the table.
+pub(crate) fn select_items(fields: &[SelectItem], wildcard: &[String]) -> Vec<SelectItem> {
+    fields
+        .iter()
+        .flat_map(|item| match item {
+            SelectItem::Wildcard => wildcard
+                .iter()
+                .map(|field| SelectItem::Column(field.clone()))
+                .collect(),
+            item => vec![item.clone()],
+        })
+        .collect()
+}
+
+/// Type of the values a select item produces.
+pub(crate) fn item_type<'a>(
+    item: &SelectItem,
+    field_map: &'a HashMap<String, FragmentField>,
+) -> &'a str {
+    match item {
+        SelectItem::Column(column) => &field_map[column].r#type,
+        SelectItem::Aggregate {
+            function: Aggregate::Count,
+            ..
+        } => "int",
+        SelectItem::Aggregate {
+            function: Aggregate::Avg,
+            ..
+        } => "float",
+        SelectItem::Aggregate {
+            column: Some(column),
+            ..
+        } => &field_map[column].r#type,
+        SelectItem::Aggregate { column: None, .. } => unreachable!("only COUNT takes '*'"),
+        SelectItem::Expression(expr) => expr_type(expr, field_map),
+        SelectItem::Wildcard => unreachable!("wildcard must be expanded"),
+    }
+}
+
+/// Type of the values an expression produces, the operands were already validated.
+pub(crate) fn expr_type<'a>(expr: &Expr, field_map: &'a HashMap<String, FragmentField>) -> &'a str {
+    match expr {
+        Expr::Column(column) => &field_map[column].r#type,
+        Expr::Literal(literal) => literal
+            .r#type()
+            .expect("placeholders are bound before running and NULL is rejected"),
+        Expr::Binary {
+            op: BinaryOp::Concat,
+            ..
+        } => "string",
+        Expr::Binary {
+            op: BinaryOp::Div, ..
+        } => "float",
+        Expr::Binary { left, right, .. } => {
+            if expr_type(left, field_map) == "float" || expr_type(right, field_map) == "float" {
+                "float"
+            } else {
+                "int"
+            }
+        }
+    }
+}
+
+/// Translates a LIKE pattern into an unanchored regular expression.
+pub(crate) fn like_regex(pattern: &str) -> String {
+    let mut regex = String::new();
+    for c in pattern.chars() {
+        match c {
+            '%' => regex.push_str(".*"),
+            '_' => regex.push('.'),
+            '\\' | '.' | '^' | '$' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+
+    regex
+}
+
 pub(crate) fn run_database(mut db: impl Database, rx: Receiver<QueryMessage>) {
     while let Ok(query_message) = rx.recv() {
-        // if let Err(message) = db.execute(query_message) {
-        //     dbg!(message);
-        // }
-        let _ = db.execute(query_message);
+        let result = match &*query_message.query {
+            // Only the statement is built, nothing runs and nothing waits for a commit.
+            Query::Explain { query, .. } => Ok(QueryResult::Explain(db.explain(query))),
+            Query::Begin => db.begin().map(|_| QueryResult::Begin),
+            Query::Rollback => db.end(false).map(|_| QueryResult::Rollback),
+            // The open transaction is committed like the one of a single statement.
+            Query::Commit => {
+                let _ = query_message.tx_result.send(QueryResult::Commit);
+                finish(&mut db, &query_message.rx_commit);
+                continue;
+            }
+            _ => {
+                // if let Err(message) = db.execute(query_message) {
+                //     dbg!(message);
+                // }
+                let _ = db.execute(query_message);
+                continue;
+            }
+        };
+        // A failure drops the result sender, which the coordinator reports.
+        if let Ok(result) = result {
+            let _ = query_message.tx_result.send(result);
+        }
     }
 }
@@ -1,12 +1,20 @@
 use anyhow::anyhow;
 use mongodb::{
     bson::{Bson, Document, doc},
-    sync::{Client, Database as MongoDatabase},
+    options::UpdateModifications,
+    sync::{Client, ClientSession, Database as MongoDatabase},
 };
 
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
 use crate::{
-    QueryResult, Value,
-    databases::{DatabaseInfo, generate_id},
+    Aggregate, Alteration, BinaryOp, Expr, Literal, Operand, OrderBy, QueryMessage, QueryResult,
+    SelectItem, Value, Where,
+    config::FragmentField,
+    databases::{
+        DatabaseInfo, ID_PLACEHOLDER, Ids, altered_table, created_table, finish, item_type,
+        like_regex, select_items,
+    },
 };
 
 use super::{Database, Query};
@@ -14,50 +22,230 @@ use super::{Database, Query};
 pub(crate) struct Mongo {
     client: Client,
     db: MongoDatabase,
+    /// Collection and field names are case-sensitive and BSON keys need no
+    /// quoting, so fragment names are used exactly as written.
     info: DatabaseInfo,
+    wildcard: HashMap<String, Vec<String>>,
+    /// The session whose transaction a BEGIN of the coordinator opened.
+    session: Option<ClientSession>,
+    /// Collection of a DROP TABLE, dropped once its transaction commits since
+    /// Mongo can't drop one inside a transaction.
+    dropped: Option<String>,
 }
 
+/// Times a committed DROP TABLE tries to drop its collection.
+const DROP_ATTEMPTS: u32 = 3;
+
 impl Mongo {
-    pub(crate) fn new(client: Client, db: MongoDatabase, info: DatabaseInfo) -> Mongo {
-        Mongo { client, db, info }
+    pub(crate) fn new(
+        client: Client,
+        db: MongoDatabase,
+        info: DatabaseInfo,
+        wildcard: HashMap<String, Vec<String>>,
+    ) -> Mongo {
+        Mongo {
+            client,
+            db,
+            info,
+            wildcard,
+            session: None,
+            dropped: None,
+        }
+    }
+
+    /// Drops the collection of a committed DROP TABLE. When it keeps failing
+    /// the commit isn't confirmed, so the coordinator reports the fragment
+    /// as in doubt instead of dropping the table from the schema.
+    fn drop_collection(&self, collection: &str) -> anyhow::Result<()> {
+        let mut attempt = 1;
+        loop {
+            match self.db.collection::<Document>(collection).drop().run() {
+                Ok(()) => return Ok(()),
+                Err(_) if attempt < DROP_ATTEMPTS => {
+                    attempt += 1;
+                    thread::sleep(Duration::from_millis(200));
+                }
+                Err(e) => {
+                    return Err(anyhow!(
+                        "DROP TABLE committed but collection '{collection}' wasn't dropped: {e}"
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Runs `write` in a transaction the coordinator then commits or aborts
+    /// with two-phase commit, returns whether it was committed. Inside an
+    /// open transaction it waits for nothing and is only committed with the
+    /// rest of it.
+    fn execute_write<F>(&mut self, query_message: QueryMessage, write: F) -> anyhow::Result<bool>
+    where
+        F: FnOnce(&MongoDatabase, &mut ClientSession) -> anyhow::Result<QueryResult>,
+    {
+        let own = self.session.is_none();
+        if own {
+            self.begin()?;
+        }
+
+        let session = self.session.as_mut().unwrap();
+        let result = match write(&self.db, session) {
+            Ok(result) => result,
+            Err(e) => {
+                if own {
+                    self.end(false)?;
+                }
+                return Err(e);
+            }
+        };
+
+        let sent = query_message.tx_result.send(result);
+        if !own {
+            return sent
+                .map(|_| false)
+                .map_err(|_| anyhow!("failed to send result"));
+        }
+
+        // A coordinator that stopped waiting dropped the commit sender too,
+        // which aborts.
+        Ok(finish(self, &query_message.rx_commit))
     }
 }
 
 pub(crate) enum DocumentType {
-    Select((Document, Document)),
+    Select(Vec<Document>),
     Insert(Vec<Document>),
-    Update((Document, Document)),
+    Update((Document, UpdateModifications)),
     Delete(Document),
+    CreateCollection(String),
+    /// `None` when the documents need no change.
+    AlterCollection(Option<UpdateModifications>),
+    DropCollection,
 }
 
 impl Database for Mongo {
     type QueryType = DocumentType;
 
-    fn query(&self, sql: &Query) -> Self::QueryType {
+    fn query_with_ids(&self, sql: &Query, ids: Ids) -> Self::QueryType {
         match sql {
             Query::Select {
                 table,
                 fields,
+                distinct,
                 filter,
+                group_by,
+                order_by,
+                limit,
+                offset,
                 ..
             } => {
                 let real_fields = &self.info[table].1;
-
-                let project_doc = if fields[0] == "*" {
-                    Document::new()
-                } else {
-                    fields
-                        .iter()
-                        .map(|field| (real_fields[field].name.to_string(), Bson::Int64(1)))
-                        .collect()
-                };
+                let items = select_items(fields, &self.wildcard[table]);
 
                 let filter_doc = filter
                     .as_ref()
-                    .map(|f| doc! { &real_fields[&f.column].name: &f.value })
+                    .map(|f| filter_doc(real_fields, f))
                     .unwrap_or(Document::new());
 
-                DocumentType::Select((filter_doc, project_doc))
+                let mut pipeline = vec![doc! { "$match": filter_doc }];
+
+                if is_aggregate(&items) || !group_by.is_empty() {
+                    let group_id = if group_by.is_empty() {
+                        Bson::Null
+                    } else {
+                        Bson::Document(
+                            group_by
+                                .iter()
+                                .enumerate()
+                                .map(|(idx, column)| {
+                                    let field = format!("${}", real_fields[column].name);
+                                    (format!("g{idx}"), Bson::String(field))
+                                })
+                                .collect(),
+                        )
+                    };
+                    let mut group_doc = doc! { "_id": group_id };
+                    let mut set_doc = Document::new();
+                    for (idx, item) in items.iter().enumerate() {
+                        group_doc.insert(format!("c{idx}"), accumulator(real_fields, item));
+
+                        // $sum yields 0 when there is nothing to add, SQL wants NULL.
+                        if let SelectItem::Aggregate {
+                            function: Aggregate::Sum,
+                            column: Some(column),
+                        } = item
+                        {
+                            group_doc.insert(format!("n{idx}"), count_values(real_fields, column));
+                            set_doc.insert(
+                                format!("c{idx}"),
+                                doc! {
+                                    "$cond": [
+                                        { "$eq": [format!("$n{idx}"), 0] },
+                                        Bson::Null,
+                                        format!("$c{idx}"),
+                                    ]
+                                },
+                            );
+                        }
+                    }
+                    pipeline.push(doc! { "$group": group_doc });
+                    if !set_doc.is_empty() {
+                        pipeline.push(doc! { "$set": set_doc });
+                    }
+                } else if *distinct {
+                    // Missing fields become null so they fall in the same group as explicit nulls.
+                    let mut project_doc = doc! { "_id": 0 };
+                    for (idx, item) in items.iter().enumerate() {
+                        project_doc.insert(
+                            format!("c{idx}"),
+                            doc! { "$ifNull": [projection(real_fields, item), Bson::Null] },
+                        );
+                    }
+                    pipeline.push(doc! { "$project": project_doc });
+                    pipeline.push(doc! { "$group": { "_id": "$$ROOT" } });
+                    pipeline.push(doc! { "$replaceRoot": { "newRoot": "$_id" } });
+
+                    if !order_by.is_empty() {
+                        let sort_doc: Document = order_by
+                            .iter()
+                            .map(|OrderBy { column, descending }| {
+                                let idx = items
+                                    .iter()
+                                    .position(
+                                        |item| matches!(item, SelectItem::Column(c) if c == column),
+                                    )
+                                    .unwrap();
+                                let direction = if *descending { -1 } else { 1 };
+                                (format!("c{idx}"), Bson::Int32(direction))
+                            })
+                            .collect();
+                        pipeline.push(doc! { "$sort": sort_doc });
+                    }
+                } else {
+                    let mut project_doc = doc! { "_id": 0 };
+                    for (idx, item) in items.iter().enumerate() {
+                        project_doc.insert(format!("c{idx}"), projection(real_fields, item));
+                    }
+                    // Sorted on the converted value, only the c fields are read back.
+                    let mut sort_doc = Document::new();
+                    for (idx, OrderBy { column, descending }) in order_by.iter().enumerate() {
+                        project_doc.insert(format!("s{idx}"), column_value(real_fields, column));
+                        sort_doc.insert(format!("s{idx}"), if *descending { -1 } else { 1 });
+                    }
+                    pipeline.push(doc! { "$project": project_doc });
+                    if !sort_doc.is_empty() {
+                        pipeline.push(doc! { "$sort": sort_doc });
+                    }
+                }
+
+                if let Some(offset) = offset.filter(|offset| *offset > 0) {
+                    pipeline.push(doc! { "$skip": offset as i64 });
+                }
+                // LIMIT 0 never runs the pipeline, see execute.
+                if let Some(limit) = limit.filter(|limit| *limit > 0) {
+                    pipeline.push(doc! { "$limit": limit as i64 });
+                }
+
+                DocumentType::Select(pipeline)
             }
             Query::Insert {
                 table,
@@ -72,14 +260,19 @@ impl Database for Mongo {
                         let mut doc = Document::new();
 
                         let id_field = &real_fields["IdCliente"].name;
-                        let id_value = generate_id();
-                        doc.insert(id_field, id_value.to_string());
+                        let id_value = ids
+                            .next()
+                            .map_or(Bson::String(ID_PLACEHOLDER.to_string()), |id| {
+                                Bson::Int64(id as i64)
+                            });
+                        doc.insert(id_field, id_value);
 
                         for (col, val) in columns.iter().zip(row) {
                             if col == "IdCliente" {
                                 continue; // saltar si ya existe
                             }
-                            doc.insert(&real_fields[col].name, val);
+                            let field = &real_fields[col];
+                            doc.insert(&field.name, bson_value(field, val));
                         }
 
                         doc
@@ -95,77 +288,146 @@ impl Database for Mongo {
                 ..
             } => {
                 let real_fields = &self.info[table].1;
+
+                // Values computed from the document need a pipeline update, where
+                // every expression sees the document as it was before the update.
+                let computed = assignments
+                    .iter()
+                    .any(|(_, val)| !matches!(val, Expr::Literal(_)));
+
                 let mut update_doc = Document::new();
                 for (col, val) in assignments {
-                    update_doc.insert(&real_fields[col].name, val);
+                    let field = &real_fields[col];
+                    let value = match val {
+                        Expr::Literal(v) if computed => {
+                            Bson::Document(doc! { "$literal": bson_value(field, v) })
+                        }
+                        Expr::Literal(v) => bson_value(field, v),
+                        val => expression(real_fields, val),
+                    };
+                    update_doc.insert(&field.name, value);
                 }
 
                 let filter_doc = filter
                     .as_ref()
-                    .map(|f| doc! { &real_fields[&f.column].name: &f.value })
+                    .map(|f| filter_doc(real_fields, f))
                     .unwrap_or(Document::new());
 
-                DocumentType::Update((filter_doc, doc! { "$set": update_doc }))
+                let update = if computed {
+                    UpdateModifications::Pipeline(vec![doc! { "$set": update_doc }])
+                } else {
+                    UpdateModifications::Document(doc! { "$set": update_doc })
+                };
+
+                DocumentType::Update((filter_doc, update))
             }
             Query::Delete { table, filter, .. } => {
                 let real_fields = &self.info[table].1;
                 let doc = filter
                     .as_ref()
-                    .map(|f| doc! { &real_fields[&f.column].name: &f.value })
+                    .map(|f| filter_doc(real_fields, f))
                     .unwrap_or(Document::new());
 
                 DocumentType::Delete(doc)
             }
+            Query::CreateTable { fragments, .. } => {
+                DocumentType::CreateCollection(fragments[0].name.clone())
+            }
+            Query::AlterTable { table, alteration } => {
+                let real_fields = &self.info[table].1;
+
+                let update = match alteration {
+                    // A missing field is how Mongo stores NULL, a new column changes no document.
+                    Alteration::AddColumn { .. } => None,
+                    Alteration::DropColumn { column } => {
+                        Some(doc! { "$unset": { &real_fields[column].name: "" } })
+                    }
+                    Alteration::RenameColumn { column, name } => {
+                        Some(doc! { "$rename": { &real_fields[column].name: name } })
+                    }
+                };
+
+                DocumentType::AlterCollection(update.map(UpdateModifications::Document))
+            }
+            Query::DropTable { .. } => DocumentType::DropCollection,
+            Query::Explain { .. } => unreachable!("EXPLAIN is answered by run_database"),
+            Query::Catalog(_) => unreachable!("the coordinator answers SHOW and DESCRIBE"),
+            Query::Begin | Query::Commit | Query::Rollback => {
+                unreachable!("transactions are handled by run_database")
+            }
         }
     }
 
-    fn execute(&mut self, query_message: crate::QueryMessage) -> anyhow::Result<()> {
+    fn execute(&mut self, query_message: QueryMessage) -> anyhow::Result<()> {
         let query = self.query(&query_message.query);
 
         match (&*query_message.query, query) {
-            (Query::Select { table, .. }, DocumentType::Select((filter_doc, project_doc))) => {
+            (
+                Query::Select {
+                    table,
+                    fields,
+                    group_by,
+                    limit,
+                    ..
+                },
+                DocumentType::Select(pipeline),
+            ) => {
+                // Mongo rejects a $limit of 0, there is nothing to fetch anyway.
+                if *limit == Some(0) {
+                    return query_message
+                        .tx_result
+                        .send(QueryResult::Select(Vec::new()))
+                        .map_err(|_| anyhow!("failed to send result"));
+                }
+
                 let (table_real_name, real_fields) = &self.info[table];
+                let items = select_items(fields, &self.wildcard[table]);
 
                 let collection = self.db.collection::<Document>(table_real_name.as_str());
-                let res = collection.find(filter_doc).projection(project_doc).run()?;
+                // Inside a transaction the documents it already changed are seen.
+                let docs: Vec<Document> = match &mut self.session {
+                    Some(session) => collection
+                        .aggregate(pipeline)
+                        .session(&mut *session)
+                        .run()?
+                        .iter(session)
+                        .collect::<Result<_, _>>()?,
+                    None => collection
+                        .aggregate(pipeline)
+                        .run()?
+                        .collect::<Result<_, _>>()?,
+                };
 
                 let mut results = Vec::new();
-                for doc in res {
-                    let doc = doc?;
-                    let mut row_values = Vec::new();
+                for mut doc in docs {
+                    let mut row_values = Vec::with_capacity(items.len());
 
-                    for (name, value) in doc {
-                        if name == "_id" {
-                            continue;
-                        }
-                        let field = real_fields.values().find(|f| f.name == *name).unwrap();
-
-                        let value = match field.r#type.as_str() {
-                            "int" => match value.as_str().unwrap().parse() {
-                                Ok(v) => Value::Int(v),
-                                Err(_) => Value::Null,
-                            },
-                            "float" => match value.as_str().unwrap().parse() {
-                                Ok(v) => Value::Float(v),
-                                Err(_) => Value::Null,
-                            },
-                            "bool" => match value.as_bool() {
-                                Some(v) => Value::Bool(v),
-                                None => Value::Null,
-                            },
-                            "string" => match value.as_str() {
-                                Some(v) => Value::Str(v.to_string()),
-                                None => Value::Null,
-                            },
-                            _ => Value::Null,
-                        };
-
-                        row_values.push(value);
+                    // Missing fields are NULL, so the row is built from the requested columns.
+                    for (idx, item) in items.iter().enumerate() {
+                        let value = doc.remove(format!("c{idx}")).unwrap_or(Bson::Null);
+
+                        row_values.push(value_from_bson(item_type(item, real_fields), value));
                     }
 
                     results.push(row_values);
                 }
 
+                // $group has no output for an empty collection, SQL still returns one row.
+                if results.is_empty() && group_by.is_empty() && is_aggregate(&items) {
+                    results.push(
+                        items
+                            .iter()
+                            .map(|item| match item {
+                                SelectItem::Aggregate {
+                                    function: Aggregate::Count,
+                                    ..
+                                } => Value::Int(0),
+                                _ => Value::Null,
+                            })
+                            .collect(),
+                    );
+                }
+
                 query_message
                     .tx_result
                     .send(QueryResult::Select(results))
@@ -174,113 +436,535 @@ impl Database for Mongo {
                 Ok(())
             }
             (Query::Insert { table, .. }, DocumentType::Insert(docs)) => {
-                let table_real_name = &self.info[table].0;
-                let mut session = self
-                    .client
-                    .start_session()
-                    .run()
-                    .map_err(|_| anyhow!("failed to start session"))?;
-                session.start_transaction().run()?;
-
-                let collection = self.db.collection::<Document>(table_real_name.as_str());
-
-                let res = collection.insert_many(docs).session(&mut session).run()?;
-
-                query_message
-                    .tx_result
-                    .send(QueryResult::Insert(res.inserted_ids.len() as u64))
-                    .map_err(|_| anyhow!("failed to send result"))?;
+                let table_real_name = self.info[table].0.clone();
+                self.execute_write(query_message, |db, session| {
+                    let res = db
+                        .collection::<Document>(&table_real_name)
+                        .insert_many(docs)
+                        .session(session)
+                        .run()?;
+                    Ok(QueryResult::Insert(res.inserted_ids.len() as u64))
+                })
+                .map(|_| ())
+            }
+            (Query::Update { table, .. }, DocumentType::Update((filter_doc, update_doc))) => {
+                let table_real_name = self.info[table].0.clone();
+                self.execute_write(query_message, |db, session| {
+                    let res = db
+                        .collection::<Document>(&table_real_name)
+                        .update_many(filter_doc, update_doc)
+                        .session(session)
+                        .run()?;
+                    Ok(QueryResult::Update(res.modified_count))
+                })
+                .map(|_| ())
+            }
+            (Query::Delete { table, .. }, DocumentType::Delete(filter_doc)) => {
+                let table_real_name = self.info[table].0.clone();
+                self.execute_write(query_message, |db, session| {
+                    let res = db
+                        .collection::<Document>(&table_real_name)
+                        .delete_many(filter_doc)
+                        .session(session)
+                        .run()?;
+                    Ok(QueryResult::Delete(res.deleted_count))
+                })
+                .map(|_| ())
+            }
+            (Query::CreateTable { .. }, DocumentType::CreateCollection(name)) => {
+                let (info, wildcard) = created_table(&query_message.query);
+                let committed = self.execute_write(query_message, |db, session| {
+                    db.create_collection(name).session(session).run()?;
+                    Ok(QueryResult::CreateTable)
+                })?;
+                if committed {
+                    self.info.extend(info);
+                    self.wildcard.extend(wildcard);
+                }
 
-                match query_message.rx_commit.recv() {
-                    Ok(_) => session
-                        .commit_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("failed to commit transaction"))?,
-                    Err(_) => session
-                        .abort_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("transaction aborted"))?,
+                Ok(())
+            }
+            (Query::AlterTable { table, .. }, DocumentType::AlterCollection(update)) => {
+                let table_real_name = self.info[table].0.clone();
+                let ddl = Arc::clone(&query_message.query);
+                let committed = self.execute_write(query_message, |db, session| {
+                    if let Some(update) = update {
+                        db.collection::<Document>(&table_real_name)
+                            .update_many(doc! {}, update)
+                            .session(session)
+                            .run()?;
+                    }
+                    Ok(QueryResult::AlterTable)
+                })?;
+                if committed {
+                    altered_table(&mut self.info, &mut self.wildcard, &ddl, str::to_string);
                 }
 
                 Ok(())
             }
-            (Query::Update { table, .. }, DocumentType::Update((filter_doc, update_doc))) => {
-                let table_real_name = &self.info[table].0;
-
-                let mut session = self
-                    .client
-                    .start_session()
-                    .run()
-                    .map_err(|_| anyhow!("failed to start session"))?;
-                session
-                    .start_transaction()
-                    .run()
-                    .map_err(|_| anyhow!("failed to start transaccion"))?;
+            // A collection can't be dropped inside a transaction, it's dropped
+            // once the coordinator commits the empty one.
+            (Query::DropTable { table }, DocumentType::DropCollection) => {
+                self.dropped = Some(self.info[table].0.clone());
+                let ddl = Arc::clone(&query_message.query);
+                // A transaction that never began doesn't end either.
+                let committed = self
+                    .execute_write(query_message, |_, _| Ok(QueryResult::DropTable))
+                    .inspect_err(|_| self.dropped = None)?;
+                if committed {
+                    altered_table(&mut self.info, &mut self.wildcard, &ddl, str::to_string);
+                }
 
-                let collection = self.db.collection::<Document>(table_real_name.as_str());
+                Ok(())
+            }
+            (_, _) => unreachable!(),
+        }
+    }
 
-                let res = collection
-                    .update_many(filter_doc, update_doc)
-                    .session(&mut session)
-                    .run()?;
+    fn explain(&self, sql: &Query) -> String {
+        let (Query::Select { table, .. }
+        | Query::Insert { table, .. }
+        | Query::Update { table, .. }
+        | Query::Delete { table, .. }) = sql
+        else {
+            unreachable!("only SELECT, INSERT, UPDATE and DELETE are explained")
+        };
+        let collection = &self.info[table].0;
+
+        match self.query_with_ids(sql, Ids::Placeholder) {
+            DocumentType::Select(pipeline) => {
+                format!("db.{collection}.aggregate([{}])", documents(&pipeline))
+            }
+            DocumentType::Insert(docs) => {
+                format!("db.{collection}.insertMany([{}])", documents(&docs))
+            }
+            DocumentType::Update((filter, update)) => {
+                let update = match update {
+                    UpdateModifications::Pipeline(pipeline) => {
+                        format!("[{}]", documents(&pipeline))
+                    }
+                    UpdateModifications::Document(doc) => doc.to_string(),
+                    _ => unreachable!("updates are built as a document or a pipeline"),
+                };
+                format!("db.{collection}.updateMany({filter}, {update})")
+            }
+            DocumentType::Delete(filter) => format!("db.{collection}.deleteMany({filter})"),
+            _ => unreachable!("only SELECT, INSERT, UPDATE and DELETE are explained"),
+        }
+    }
 
-                query_message
-                    .tx_result
-                    .send(QueryResult::Update(res.modified_count))
-                    .map_err(|_| anyhow!("failed to send result"))?;
+    fn begin(&mut self) -> anyhow::Result<()> {
+        let mut session = self
+            .client
+            .start_session()
+            .run()
+            .map_err(|_| anyhow!("failed to start session"))?;
+        session
+            .start_transaction()
+            .run()
+            .map_err(|_| anyhow!("failed to start transaccion"))?;
+        self.session = Some(session);
+        Ok(())
+    }
 
-                match query_message.rx_commit.recv() {
-                    Ok(_) => session
-                        .commit_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("failed to commit transaction"))?,
-                    Err(_) => session
-                        .abort_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("transaction aborted"))?,
-                }
+    /// Mongo has no prepare of its own, so it only takes part in the vote on
+    /// a best-effort basis: it votes yes while its transaction is open. A
+    /// write conflict fails the write before that, but the commit can still
+    /// fail once every database voted, and the coordinator then ends with
+    /// `Outcome::InDoubt`.
+    fn prepare(&mut self) -> anyhow::Result<()> {
+        self.session
+            .as_ref()
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("no transaction to prepare"))
+    }
 
-                Ok(())
+    fn end(&mut self, commit: bool) -> anyhow::Result<()> {
+        let mut session = self
+            .session
+            .take()
+            .ok_or_else(|| anyhow!("no transaction to end"))?;
+        let dropped = self.dropped.take();
+        if commit {
+            session
+                .commit_transaction()
+                .run()
+                .map_err(|_| anyhow!("failed to commit transaction"))?;
+            match dropped {
+                Some(collection) => self.drop_collection(&collection),
+                None => Ok(()),
             }
-            (Query::Delete { table, .. }, DocumentType::Delete(filter_doc)) => {
-                let table_real_name = &self.info[table].0;
-
-                let mut session = self
-                    .client
-                    .start_session()
-                    .run()
-                    .map_err(|_| anyhow!("failed to start session"))?;
-                session
-                    .start_transaction()
-                    .run()
-                    .map_err(|_| anyhow!("failed to start transaccion"))?;
+        } else {
+            session
+                .abort_transaction()
+                .run()
+                .map_err(|_| anyhow!("transaction aborted"))
+        }
+    }
+}
 
-                let collection = self.db.collection::<Document>(table_real_name.as_str());
+fn documents(docs: &[Document]) -> String {
+    docs.iter()
+        .map(Document::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-                let res = collection
-                    .delete_many(filter_doc)
-                    .session(&mut session)
-                    .run()?;
+fn bson(value: &Literal) -> Bson {
+    match value {
+        Literal::Int(val) => Bson::Int64(*val),
+        Literal::Float(val) => Bson::Double(*val),
+        Literal::Str(val) => Bson::String(val.clone()),
+        Literal::Bool(val) => Bson::Boolean(*val),
+        Literal::Null => Bson::Null,
+        Literal::Placeholder(_) => unreachable!("placeholders are bound before running"),
+    }
+}
 
-                query_message
-                    .tx_result
-                    .send(QueryResult::Delete(res.deleted_count))
-                    .map_err(|_| anyhow!("failed to send result"))?;
+/// A literal written into or compared with `field`, integers going into a
+/// float field are stored as doubles.
+fn bson_value(field: &FragmentField, value: &Literal) -> Bson {
+    match (field.r#type.as_str(), value) {
+        ("float", Literal::Int(val)) => Bson::Double(*val as f64),
+        _ => bson(value),
+    }
+}
+
+// Older documents keep numbers as strings, so both representations are accepted.
+fn value_from_bson(r#type: &str, value: Bson) -> Value {
+    match (r#type, value) {
+        ("int", Bson::Int32(v)) => Value::Int(v as i64),
+        ("int", Bson::Int64(v)) => Value::Int(v),
+        ("int", Bson::Double(v)) => Value::Int(v as i64),
+        ("int", Bson::String(v)) => v.parse().map(Value::Int).unwrap_or(Value::Null),
+        ("float", Bson::Int32(v)) => Value::Float(v as f64),
+        ("float", Bson::Int64(v)) => Value::Float(v as f64),
+        ("float", Bson::Double(v)) => Value::Float(v),
+        ("float", Bson::String(v)) => v.parse().map(Value::Float).unwrap_or(Value::Null),
+        ("bool", Bson::Boolean(v)) => Value::Bool(v),
+        ("string", Bson::String(v)) => Value::Str(v),
+        _ => Value::Null,
+    }
+}
+
+/// Value of a column or expression item inside an aggregation stage.
+fn projection(real_fields: &HashMap<String, FragmentField>, item: &SelectItem) -> Bson {
+    match item {
+        SelectItem::Column(column) => column_value(real_fields, column),
+        SelectItem::Expression(expr) => expression(real_fields, expr),
+        item => unreachable!("{item:?} is not a projection"),
+    }
+}
+
+fn expression(real_fields: &HashMap<String, FragmentField>, expr: &Expr) -> Bson {
+    match expr {
+        Expr::Column(column) => column_value(real_fields, column),
+        // $literal keeps strings starting with '$' from being read as field paths.
+        Expr::Literal(Literal::Str(val)) => Bson::Document(doc! { "$literal": val }),
+        Expr::Literal(value) => bson(value),
+        Expr::Binary {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } => {
+            let right = expression(real_fields, right);
+            Bson::Document(doc! {
+                "$cond": [
+                    { "$eq": [right.clone(), 0] },
+                    Bson::Null,
+                    { "$divide": [expression(real_fields, left), right] },
+                ]
+            })
+        }
+        Expr::Binary { op, left, right } => {
+            let operator = match op {
+                BinaryOp::Add => "$add",
+                BinaryOp::Sub => "$subtract",
+                BinaryOp::Mul => "$multiply",
+                BinaryOp::Concat => "$concat",
+                BinaryOp::Div => unreachable!(),
+            };
+            Bson::Document(doc! {
+                operator: [expression(real_fields, left), expression(real_fields, right)]
+            })
+        }
+    }
+}
+
+fn is_aggregate(items: &[SelectItem]) -> bool {
+    items
+        .iter()
+        .any(|item| matches!(item, SelectItem::Aggregate { .. }))
+}
+
+fn accumulator(real_fields: &HashMap<String, FragmentField>, item: &SelectItem) -> Document {
+    let (function, column) = match item {
+        SelectItem::Aggregate { function, column } => (function, column),
+        // Only group by columns reach here, every document of the group shares the value.
+        item @ (SelectItem::Column(_) | SelectItem::Expression(_)) => {
+            return doc! { "$first": projection(real_fields, item) };
+        }
+        SelectItem::Wildcard => unreachable!("wildcard must be expanded"),
+    };
+
+    let Some(column) = column else {
+        return doc! { "$sum": 1 };
+    };
+    let field = column_value(real_fields, column);
+
+    match function {
+        Aggregate::Count => count_values(real_fields, column),
+        Aggregate::Sum => doc! { "$sum": field },
+        Aggregate::Avg => doc! { "$avg": field },
+        Aggregate::Min => doc! { "$min": field },
+        Aggregate::Max => doc! { "$max": field },
+    }
+}
 
-                match query_message.rx_commit.recv() {
-                    Ok(_) => session
-                        .commit_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("failed to commit transaction"))?,
-                    Err(_) => session
-                        .abort_transaction()
-                        .run()
-                        .map_err(|_| anyhow!("transaction aborted"))?,
+/// Counts the documents where `column` is present and not null.
+fn count_values(real_fields: &HashMap<String, FragmentField>, column: &str) -> Document {
+    let field = column_value(real_fields, column);
+    doc! {
+        "$sum": {
+            "$cond": [{ "$eq": [{ "$ifNull": [field, Bson::Null] }, Bson::Null] }, 0, 1]
+        }
+    }
+}
+
+fn filter_doc(real_fields: &HashMap<String, FragmentField>, filter: &Where) -> Document {
+    match filter {
+        Where::Comparison { column, op, value } => {
+            let field = &real_fields[column];
+            let op = match op.as_str() {
+                "=" => "$eq",
+                "!=" => "$ne",
+                "<" => "$lt",
+                ">" => "$gt",
+                "<=" => "$lte",
+                ">=" => "$gte",
+                op => unreachable!("unknown operator '{op}'"),
+            };
+
+            match value {
+                Operand::Column(other) => {
+                    let left = column_value(real_fields, column);
+                    let right = column_value(real_fields, other);
+                    // Aggregation orders null like any other value, SQL never matches it.
+                    // Strings that aren't numbers convert to null too.
+                    doc! {
+                        &field.name: { "$ne": Bson::Null },
+                        &real_fields[other].name: { "$ne": Bson::Null },
+                        "$expr": {
+                            "$and": [
+                                { "$ne": [left.clone(), Bson::Null] },
+                                { "$ne": [right.clone(), Bson::Null] },
+                                { op: [left, right] },
+                            ]
+                        },
+                    }
+                }
+                Operand::Value(value) if is_number(field) => {
+                    number_filter(field, op, bson_value(field, value))
                 }
+                // $ne alone also takes the documents where the field is null or missing.
+                Operand::Value(value) if op == "$ne" => {
+                    doc! { &field.name: { "$nin": [bson_value(field, value), Bson::Null] } }
+                }
+                Operand::Value(value) => {
+                    doc! { &field.name: { op: bson_value(field, value) } }
+                }
+            }
+        }
+        Where::And(left, right) => doc! {
+            "$and": [filter_doc(real_fields, left), filter_doc(real_fields, right)]
+        },
+        Where::Or(left, right) => doc! {
+            "$or": [filter_doc(real_fields, left), filter_doc(real_fields, right)]
+        },
+        Where::Not(inner) => negated_filter(real_fields, inner),
+        Where::In { column, values } => {
+            let field = &real_fields[column];
+            let values: Vec<Bson> = values.iter().map(|v| bson_value(field, v)).collect();
+            if is_number(field) {
+                number_filter(field, "$in", Bson::Array(values))
+            } else {
+                doc! { &field.name: { "$in": values } }
+            }
+        }
+        Where::Between { column, low, high } => {
+            let field = &real_fields[column];
+            if is_number(field) {
+                doc! {
+                    "$and": [
+                        number_filter(field, "$gte", bson_value(field, low)),
+                        number_filter(field, "$lte", bson_value(field, high)),
+                    ]
+                }
+            } else {
+                doc! {
+                    &field.name: {
+                        "$gte": bson_value(field, low),
+                        "$lte": bson_value(field, high),
+                    }
+                }
+            }
+        }
+        Where::Like { column, pattern } => {
+            let Literal::Str(pattern) = pattern else {
+                unreachable!("LIKE takes a string pattern")
+            };
+            let regex = whole_text(pattern);
+            doc! { &real_fields[column].name: { "$regex": regex } }
+        }
+        Where::IsNull { column } => doc! { &real_fields[column].name: { "$eq": Bson::Null } },
+        Where::InSelect { .. } | Where::Exists(_) => {
+            unreachable!("subqueries are resolved by the coordinator")
+        }
+    }
+}
 
-                Ok(())
+/// Documents where `filter` is false. $not and $nor would also take the ones
+/// where it is unknown because a field is null, so the negation is pushed
+/// down to each condition instead.
+fn negated_filter(real_fields: &HashMap<String, FragmentField>, filter: &Where) -> Document {
+    match filter {
+        Where::Comparison { column, op, value } => {
+            let op = match op.as_str() {
+                "=" => "!=",
+                "!=" => "=",
+                "<" => ">=",
+                ">" => "<=",
+                "<=" => ">",
+                ">=" => "<",
+                op => unreachable!("unknown operator '{op}'"),
+            };
+            let comparison = Where::Comparison {
+                column: column.clone(),
+                op: op.to_string(),
+                value: value.clone(),
+            };
+            filter_doc(real_fields, &comparison)
+        }
+        Where::And(left, right) => doc! {
+            "$or": [negated_filter(real_fields, left), negated_filter(real_fields, right)]
+        },
+        Where::Or(left, right) => doc! {
+            "$and": [negated_filter(real_fields, left), negated_filter(real_fields, right)]
+        },
+        Where::Not(inner) => filter_doc(real_fields, inner),
+        // Nothing is in an empty list, not even NULL.
+        Where::In { values, .. } if values.is_empty() => Document::new(),
+        // A NULL in the list leaves every other row unknown.
+        Where::In { values, .. } if values.contains(&Literal::Null) => doc! { "$expr": false },
+        Where::In { column, values } if is_number(&real_fields[column]) => {
+            let conditions: Vec<Document> = values
+                .iter()
+                .map(|value| {
+                    let comparison = Where::Comparison {
+                        column: column.clone(),
+                        op: "!=".to_string(),
+                        value: Operand::Value(value.clone()),
+                    };
+                    filter_doc(real_fields, &comparison)
+                })
+                .collect();
+            doc! { "$and": conditions }
+        }
+        Where::In { column, values } => {
+            let field = &real_fields[column];
+            let mut values: Vec<Bson> = values.iter().map(|v| bson_value(field, v)).collect();
+            values.push(Bson::Null);
+            doc! { &field.name: { "$nin": values } }
+        }
+        Where::Between { column, low, high } => {
+            let bound = |op: &str, value: &Literal| Where::Comparison {
+                column: column.clone(),
+                op: op.to_string(),
+                value: Operand::Value(value.clone()),
+            };
+            doc! {
+                "$or": [
+                    filter_doc(real_fields, &bound("<", low)),
+                    filter_doc(real_fields, &bound(">", high)),
+                ]
             }
-            (_, _) => unreachable!(),
         }
+        Where::Like { column, pattern } => {
+            let Literal::Str(pattern) = pattern else {
+                unreachable!("LIKE takes a string pattern")
+            };
+            let regex = whole_text(pattern);
+            doc! { &real_fields[column].name: { "$type": "string", "$not": { "$regex": regex } } }
+        }
+        Where::IsNull { column } => doc! { &real_fields[column].name: { "$ne": Bson::Null } },
+        Where::InSelect { .. } | Where::Exists(_) => {
+            unreachable!("subqueries are resolved by the coordinator")
+        }
+    }
+}
+
+/// Regex matching the whole text with a LIKE pattern. `$` would also match
+/// before a trailing newline, and `.` only matches one with the `s` flag.
+fn whole_text(pattern: &str) -> String {
+    format!(r"(?s)\A{}\z", like_regex(pattern))
+}
+
+fn is_number(field: &FragmentField) -> bool {
+    matches!(field.r#type.as_str(), "int" | "float")
+}
+
+/// Value of `column` inside an aggregation expression. Numeric fields go
+/// through [`number`] so the older documents are read like the rest.
+fn column_value(real_fields: &HashMap<String, FragmentField>, column: &str) -> Bson {
+    let field = &real_fields[column];
+    if is_number(field) {
+        number(field)
+    } else {
+        Bson::String(format!("${}", field.name))
+    }
+}
+
+/// A numeric field with the numbers older documents keep as strings
+/// converted, null when such a string isn't a number.
+fn number(field: &FragmentField) -> Bson {
+    let stored = format!("${}", field.name);
+    Bson::Document(doc! {
+        "$cond": [
+            { "$eq": [{ "$type": &stored }, "string"] },
+            converted(&stored),
+            &stored,
+        ]
+    })
+}
+
+fn converted(stored: &str) -> Document {
+    doc! { "$convert": { "input": stored, "to": "double", "onError": Bson::Null } }
+}
+
+/// `op` against a numeric field, also matching the older documents that keep
+/// the number as a string.
+fn number_filter(field: &FragmentField, op: &str, value: Bson) -> Document {
+    // Strings are compared in the second branch, $ne alone would take them all.
+    let typed = if op == "$ne" {
+        doc! { "$ne": value.clone(), "$type": "number" }
+    } else {
+        doc! { op: value.clone() }
+    };
+    let number = converted(&format!("${}", field.name));
+    // Aggregation puts null below every number, so strings that aren't
+    // numbers are left out before comparing.
+    doc! {
+        "$or": [
+            { &field.name: typed },
+            {
+                &field.name: { "$type": "string" },
+                "$expr": {
+                    "$and": [
+                        { "$ne": [number.clone(), Bson::Null] },
+                        { op: [number, value] },
+                    ]
+                },
+            },
+        ]
     }
 }
@@ -1,12 +1,17 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use anyhow::anyhow;
-use neo4rs::{Graph, Query as QueryNeo4j};
+use neo4rs::{Graph, Query as QueryNeo4j, Row, Txn};
 use tokio::runtime::Runtime;
 
 use crate::{
-    QueryMessage, QueryResult, Value,
-    databases::{DatabaseInfo, generate_id},
+    Aggregate, Alteration, BinaryOp, Expr, Literal, Operand, OrderBy, QueryMessage, QueryResult,
+    SelectItem, Value, Where,
+    config::FragmentField,
+    databases::{
+        DatabaseInfo, ID_PLACEHOLDER, Ids, altered_table, created_table, finish, item_type,
+        like_regex, quote_names, select_items,
+    },
 };
 
 use super::{Database, Query};
@@ -16,6 +21,8 @@ pub(crate) struct Neo4J {
     info: DatabaseInfo,
     rt: Runtime,
     wildcard: HashMap<String, Vec<String>>,
+    /// The transaction a BEGIN of the coordinator opened.
+    txn: Option<Txn>,
 }
 
 impl Neo4J {
@@ -27,93 +34,137 @@ impl Neo4J {
     ) -> Neo4J {
         Neo4J {
             conn,
-            info,
+            info: quote_names(info, identifier),
             rt,
             wildcard,
+            txn: None,
         }
     }
 
+    /// Runs `query` in a transaction the coordinator then commits or aborts
+    /// with two-phase commit, returns whether it was committed. Inside an
+    /// open transaction it waits for nothing and is only committed with the
+    /// rest of it.
     fn execute_write<F>(
         &mut self,
         query: QueryNeo4j,
         query_message: QueryMessage,
         map_result: F,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<bool>
     where
         F: FnOnce(u64) -> QueryResult,
     {
-        let mut tran = self.rt.block_on(self.conn.start_txn())?;
+        let own = self.txn.is_none();
+        if own {
+            self.begin()?;
+        }
 
-        let mut result = self.rt.block_on(tran.execute(query))?;
+        let txn = self.txn.as_mut().unwrap();
+        let affected_rows = match self.rt.block_on(affected_rows(txn, query)) {
+            Ok(affected_rows) => affected_rows,
+            Err(e) => {
+                if own {
+                    self.end(false)?;
+                }
+                return Err(e);
+            }
+        };
 
-        let mut affected_rows = 0;
-        while let Ok(Some(row)) = self.rt.block_on(result.next(&mut tran)) {
-            affected_rows += row.get::<u64>("affected_rows").unwrap_or(0);
+        let sent = query_message.tx_result.send(map_result(affected_rows));
+        if !own {
+            return sent
+                .map(|_| false)
+                .map_err(|_| anyhow!("failed to send result"));
         }
 
-        query_message
-            .tx_result
-            .send(map_result(affected_rows))
-            .map_err(|_| anyhow!("failed to send result"))?;
-
-        match query_message.rx_commit.recv() {
-            Ok(_) => self
-                .rt
-                .block_on(tran.commit())
-                .map_err(|_| anyhow!("failed to commit transaction"))?,
-            Err(_) => self
-                .rt
-                .block_on(tran.rollback())
-                .map_err(|_| anyhow!("transaction aborted"))?,
-        }
+        // A coordinator that stopped waiting dropped the commit sender too,
+        // which aborts.
+        Ok(finish(self, &query_message.rx_commit))
+    }
+}
 
-        Ok(())
+async fn affected_rows(txn: &mut Txn, query: QueryNeo4j) -> anyhow::Result<u64> {
+    let mut result = txn.execute(query).await?;
+
+    let mut affected_rows = 0;
+    while let Ok(Some(row)) = result.next(&mut *txn).await {
+        affected_rows += row.get::<u64>("affected_rows").unwrap_or(0);
     }
+
+    Ok(affected_rows)
 }
 
 impl Database for Neo4J {
-    type QueryType = QueryNeo4j;
-    fn query(&self, sql: &Query) -> Self::QueryType {
+    type QueryType = String;
+    fn query_with_ids(&self, sql: &Query, ids: Ids) -> Self::QueryType {
         match sql {
             Query::Select {
                 table,
                 fields,
+                distinct,
                 filter,
+                group_by,
+                order_by,
+                limit,
+                offset,
                 ..
             } => {
                 let (real_table, field_map) = &self.info[table];
 
-                let real_fields = if fields[0] == "*" {
-                    self.wildcard[table]
-                        .iter()
-                        .map(|field| format!("n.{}", field_map[field].name.as_str()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                } else {
-                    fields
-                        .iter()
-                        .map(|field| format!("n.{}", field_map[field].name.as_str()))
-                        .collect::<Vec<_>>()
-                        .join(", ")
-                };
+                let items = select_items(fields, &self.wildcard[table]);
+                let mut real_fields = items
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, item)| format!("{} AS c{idx}", projection(field_map, item)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                // Cypher groups by every returned non-aggregate, without aggregates
+                // DISTINCT leaves one row per group.
+                let aggregated = items
+                    .iter()
+                    .any(|item| matches!(item, SelectItem::Aggregate { .. }));
+                if *distinct || !group_by.is_empty() && !aggregated {
+                    real_fields.insert_str(0, "DISTINCT ");
+                }
 
-                let query = match filter {
-                    Some(f) => {
-                        let field = &field_map[&f.column];
-                        let value = if field.r#type == "string" {
-                            format!("\"{}\"", f.value)
-                        } else {
-                            f.value.clone()
-                        };
-                        format!(
-                            "MATCH (n:{}) WHERE n.{} = {} RETURN {}",
-                            real_table, field.name, value, real_fields
-                        )
-                    }
+                let mut query = match filter {
+                    Some(f) => format!(
+                        "MATCH (n:{}) WHERE {} RETURN {}",
+                        real_table,
+                        condition(field_map, f),
+                        real_fields
+                    ),
                     None => format!("MATCH (n:{}) RETURN {}", real_table, real_fields),
                 };
 
-                QueryNeo4j::new(query)
+                if !order_by.is_empty() {
+                    // Cypher sorts NULL last, the extra key moves it first to match the coordinator.
+                    let order = order_by
+                        .iter()
+                        .map(|OrderBy { column, descending }| {
+                            let idx = items
+                                .iter()
+                                .position(
+                                    |item| matches!(item, SelectItem::Column(c) if c == column),
+                                )
+                                .unwrap();
+                            let direction = if *descending { "DESC" } else { "ASC" };
+                            format!("c{idx} IS NOT NULL {direction}, c{idx} {direction}")
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    query.push_str(&format!(" ORDER BY {}", order));
+                }
+
+                if let Some(offset) = offset {
+                    query.push_str(&format!(" SKIP {}", offset));
+                }
+                if let Some(limit) = limit {
+                    query.push_str(&format!(" LIMIT {}", limit));
+                }
+
+                query
             }
             Query::Insert {
                 table,
@@ -128,20 +179,20 @@ impl Database for Neo4J {
                         let mut node = Vec::new();
 
                         let id_field = &field_map["IdCliente"].name;
-                        let id_value = generate_id();
+                        let id_value = ids
+                            .next()
+                            .map_or(ID_PLACEHOLDER.to_string(), |id| id.to_string());
                         node.push(format!("{}: {}", id_field, id_value));
 
                         for (col, val) in columns.iter().zip(r.iter()) {
                             if col == "IdCliente" {
                                 continue;
                             }
-                            let field = &field_map[col];
-                            let part = if field.r#type == "string" {
-                                format!("{}: '{}'", field.name, val)
-                            } else {
-                                format!("{}: {}", field.name, val)
-                            };
-                            node.push(part);
+                            // A missing property is how Neo4j stores NULL.
+                            if *val != Literal::Null {
+                                let field = &field_map[col];
+                                node.push(format!("{}: {}", field.name, stored(field, val)));
+                            }
                         }
 
                         format!("{{{}}}", node.join(", "))
@@ -157,7 +208,7 @@ impl Database for Neo4J {
                     real_table,
                 );
 
-                QueryNeo4j::new(query)
+                query
             }
             Query::Update {
                 table,
@@ -167,39 +218,32 @@ impl Database for Neo4J {
             } => {
                 let (real_table, field_map) = &self.info[table];
 
-                let assigns: Vec<String> = assignments
-                    .iter()
-                    .map(|(c, v)| {
-                        let field = &field_map[c];
-                        if field.r#type == "string" {
-                            format!("n.{} = '{}'", field.name, v)
-                        } else {
-                            format!("n.{} = {}", field.name, v)
-                        }
-                    })
-                    .collect();
+                // SET applies its items in order, the values are computed first so
+                // every expression sees the row as it was before the update.
+                let mut values = Vec::new();
+                let mut assigns = Vec::new();
+                for (idx, (c, v)) in assignments.iter().enumerate() {
+                    let field = &field_map[c];
+                    let value = match v {
+                        Expr::Literal(v) => stored(field, v),
+                        v => expression(field_map, v),
+                    };
+                    values.push(format!("{value} AS v{idx}"));
+                    assigns.push(format!("n.{} = v{idx}", field.name));
+                }
 
-                let mut query = match filter {
-                    Some(f) => {
-                        let field = &field_map[&f.column];
-                        let value = if field.r#type == "string" {
-                            format!("\"{}\"", f.value)
-                        } else {
-                            f.value.clone()
-                        };
-                        format!(
-                            "MATCH (n:{}) WHERE n.{} = {} SET {}",
-                            real_table,
-                            field.name,
-                            value,
-                            assigns.join(", ")
-                        )
-                    }
-                    None => format!("MATCH (n:{}) SET {}", real_table, assigns.join(", ")),
-                };
-                query.push_str(" RETURN count(n) as affected_rows");
+                let mut query = format!("MATCH (n:{})", real_table);
+
+                if let Some(filter) = filter {
+                    query.push_str(&format!(" WHERE {}", condition(field_map, filter)));
+                }
+                query.push_str(&format!(
+                    " WITH n, {} SET {} RETURN count(n) as affected_rows",
+                    values.join(", "),
+                    assigns.join(", ")
+                ));
 
-                QueryNeo4j::new(query)
+                query
             }
             Query::Delete { table, filter, .. } => {
                 let (real_table, field_map) = &self.info[table];
@@ -207,64 +251,106 @@ impl Database for Neo4J {
                 let mut query = format!("MATCH (n:{})", real_table);
 
                 if let Some(filter) = filter {
-                    let col = &field_map[&filter.column];
-                    let value = if col.r#type == "string" {
-                        format!("\"{}\"", filter.value)
-                    } else {
-                        filter.value.clone()
-                    };
-                    query.push_str(&format!(" WHERE n.{} {} {}", col.name, filter.op, value));
+                    query.push_str(&format!(" WHERE {}", condition(field_map, filter)));
                 }
                 query.push_str(" DELETE n RETURN count(n) as affected_rows");
 
-                QueryNeo4j::new(query)
+                query
+            }
+            // Labels need no schema, the index on the generated id is all there is to create.
+            Query::CreateTable { fragments, .. } => {
+                let fragment = &fragments[0];
+                let (_, id_field) = fragment
+                    .columns
+                    .iter()
+                    .find(|(column, _)| column == "IdCliente")
+                    .unwrap();
+
+                format!(
+                    "CREATE INDEX IF NOT EXISTS FOR (n:{}) ON (n.{})",
+                    identifier(&fragment.name),
+                    identifier(id_field)
+                )
+            }
+            Query::AlterTable { table, alteration } => {
+                let (real_table, field_map) = &self.info[table];
+
+                let change = match alteration {
+                    // A missing property is how Neo4j stores NULL, a new column changes no node.
+                    Alteration::AddColumn { .. } => {
+                        return "RETURN 0 AS affected_rows".to_string();
+                    }
+                    Alteration::DropColumn { column } => {
+                        format!("REMOVE n.{}", field_map[column].name)
+                    }
+                    Alteration::RenameColumn { column, name } => {
+                        let old = &field_map[column].name;
+                        format!(
+                            "WHERE n.{old} IS NOT NULL SET n.{} = n.{old} REMOVE n.{old}",
+                            identifier(name)
+                        )
+                    }
+                };
+
+                format!(
+                    "MATCH (n:{}) {} RETURN count(n) as affected_rows",
+                    real_table, change
+                )
+            }
+            Query::DropTable { table } => format!(
+                "MATCH (n:{}) DETACH DELETE n RETURN count(n) as affected_rows",
+                self.info[table].0
+            ),
+            Query::Explain { .. } => unreachable!("EXPLAIN is answered by run_database"),
+            Query::Catalog(_) => unreachable!("the coordinator answers SHOW and DESCRIBE"),
+            Query::Begin | Query::Commit | Query::Rollback => {
+                unreachable!("transactions are handled by run_database")
             }
         }
     }
 
     fn execute(&mut self, query_message: QueryMessage) -> anyhow::Result<()> {
-        let query = self.query(&query_message.query);
+        let query = QueryNeo4j::new(self.query(&query_message.query));
 
         match &*query_message.query {
             Query::Select { table, fields, .. } => {
                 let real_fields = &self.info[table].1;
 
-                let (real_name_fields, fields): (Vec<_>, &Vec<_>) = if fields[0] == "*" {
-                    (
-                        self.wildcard[table]
-                            .iter()
-                            .map(|field| real_fields[field].name.as_str())
-                            .collect(),
-                        &self.wildcard[table],
-                    )
-                } else {
-                    (
-                        fields
-                            .iter()
-                            .map(|field| real_fields[field].name.as_str())
-                            .collect(),
-                        fields,
-                    )
-                };
+                let types: Vec<_> = select_items(fields, &self.wildcard[table])
+                    .iter()
+                    .map(|item| item_type(item, real_fields))
+                    .collect();
 
-                let mut res = self.rt.block_on(self.conn.execute(query))?;
+                // Inside a transaction the rows it already changed are seen.
+                let mut rows: Vec<Row> = Vec::new();
+                if let Some(txn) = &mut self.txn {
+                    let mut res = self.rt.block_on(txn.execute(query))?;
+                    while let Some(row) = self.rt.block_on(res.next(&mut *txn))? {
+                        rows.push(row);
+                    }
+                } else {
+                    let mut res = self.rt.block_on(self.conn.execute(query))?;
+                    while let Some(row) = self.rt.block_on(res.next())? {
+                        rows.push(row);
+                    }
+                }
 
                 let mut results = Vec::new();
-                while let Some(row) = self.rt.block_on(res.next())? {
-                    let mut record = Vec::with_capacity(real_name_fields.len());
-
-                    for (real_field, query_field) in real_name_fields.iter().zip(fields) {
-                        let field = format!("n.{real_field}");
-                        let value = match real_fields[query_field].r#type.as_str() {
-                            "int" => match row.get::<i32>(&field) {
-                                Ok(v) => Value::Int(v as i64),
+                for row in rows {
+                    let mut record = Vec::with_capacity(types.len());
+
+                    for (idx, r#type) in types.iter().enumerate() {
+                        let field = format!("c{idx}");
+                        let value = match *r#type {
+                            "int" => match row.get::<i64>(&field) {
+                                Ok(v) => Value::Int(v),
                                 Err(_) => Value::Null,
                             },
                             "float" => {
                                 row.get::<f64>(&field)
                                     .map(Value::Float)
                                     .unwrap_or_else(|_| {
-                                        row.get::<u32>(&field)
+                                        row.get::<i64>(&field)
                                             .map(|v| Value::Float(v as f64))
                                             .unwrap_or(Value::Null)
                                     })
@@ -291,9 +377,230 @@ impl Database for Neo4J {
 
                 Ok(())
             }
-            Query::Insert { .. } => self.execute_write(query, query_message, QueryResult::Insert),
-            Query::Update { .. } => self.execute_write(query, query_message, QueryResult::Update),
-            Query::Delete { .. } => self.execute_write(query, query_message, QueryResult::Delete),
+            Query::Insert { .. } => self
+                .execute_write(query, query_message, QueryResult::Insert)
+                .map(|_| ()),
+            Query::Update { .. } => self
+                .execute_write(query, query_message, QueryResult::Update)
+                .map(|_| ()),
+            Query::Delete { .. } => self
+                .execute_write(query, query_message, QueryResult::Delete)
+                .map(|_| ()),
+            Query::CreateTable { .. } => {
+                let (info, wildcard) = created_table(&query_message.query);
+                if self.execute_write(query, query_message, |_| QueryResult::CreateTable)? {
+                    self.info.extend(quote_names(info, identifier));
+                    self.wildcard.extend(wildcard);
+                }
+
+                Ok(())
+            }
+            Query::AlterTable { .. } | Query::DropTable { .. } => {
+                let ddl = Arc::clone(&query_message.query);
+                let result = match *ddl {
+                    Query::AlterTable { .. } => QueryResult::AlterTable,
+                    _ => QueryResult::DropTable,
+                };
+                if self.execute_write(query, query_message, |_| result)? {
+                    altered_table(&mut self.info, &mut self.wildcard, &ddl, identifier);
+                }
+
+                Ok(())
+            }
+            Query::Explain { .. } => unreachable!("EXPLAIN is answered by run_database"),
+            Query::Catalog(_) => unreachable!("the coordinator answers SHOW and DESCRIBE"),
+            Query::Begin | Query::Commit | Query::Rollback => {
+                unreachable!("transactions are handled by run_database")
+            }
+        }
+    }
+
+    fn explain(&self, sql: &Query) -> String {
+        self.query_with_ids(sql, Ids::Placeholder)
+    }
+
+    fn begin(&mut self) -> anyhow::Result<()> {
+        self.txn = Some(self.rt.block_on(self.conn.start_txn())?);
+        Ok(())
+    }
+
+    /// Bolt has no prepare, the closest is checking the transaction still
+    /// runs statements, which it stops doing once one of them failed.
+    fn prepare(&mut self) -> anyhow::Result<()> {
+        let txn = self
+            .txn
+            .as_mut()
+            .ok_or_else(|| anyhow!("no transaction to prepare"))?;
+        self.rt
+            .block_on(txn.run(QueryNeo4j::new("RETURN 1".to_string())))
+            .map_err(|_| anyhow!("failed to prepare transaction"))
+    }
+
+    fn end(&mut self, commit: bool) -> anyhow::Result<()> {
+        let txn = self
+            .txn
+            .take()
+            .ok_or_else(|| anyhow!("no transaction to end"))?;
+        if commit {
+            self.rt
+                .block_on(txn.commit())
+                .map_err(|_| anyhow!("failed to commit transaction"))
+        } else {
+            self.rt
+                .block_on(txn.rollback())
+                .map_err(|_| anyhow!("transaction aborted"))
+        }
+    }
+}
+
+fn projection(field_map: &HashMap<String, FragmentField>, item: &SelectItem) -> String {
+    match item {
+        SelectItem::Column(column) => format!("n.{}", field_map[column].name),
+        SelectItem::Aggregate { column: None, .. } => "count(*)".to_string(),
+        SelectItem::Aggregate {
+            function,
+            column: Some(column),
+        } => {
+            let name = &field_map[column].name;
+            match function {
+                Aggregate::Count => format!("count(n.{name})"),
+                // sum() yields 0 when there is nothing to add, SQL wants NULL.
+                Aggregate::Sum => {
+                    format!("CASE count(n.{name}) WHEN 0 THEN null ELSE sum(n.{name}) END")
+                }
+                Aggregate::Avg => format!("avg(n.{name})"),
+                Aggregate::Min => format!("min(n.{name})"),
+                Aggregate::Max => format!("max(n.{name})"),
+            }
+        }
+        SelectItem::Expression(expr) => expression(field_map, expr),
+        SelectItem::Wildcard => unreachable!("wildcard must be expanded"),
+    }
+}
+
+fn expression(field_map: &HashMap<String, FragmentField>, expr: &Expr) -> String {
+    match expr {
+        Expr::Column(column) => format!("n.{}", field_map[column].name),
+        Expr::Literal(value) => literal(value),
+        // Cypher divides integers with truncation and dividing a float by zero gives Infinity.
+        Expr::Binary {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } => {
+            let right = expression(field_map, right);
+            format!(
+                "CASE WHEN {right} = 0 THEN null ELSE toFloat({}) / {right} END",
+                expression(field_map, left)
+            )
+        }
+        // `+` concatenates strings in Cypher.
+        Expr::Binary {
+            op: BinaryOp::Concat,
+            left,
+            right,
+        } => format!(
+            "({} + {})",
+            expression(field_map, left),
+            expression(field_map, right)
+        ),
+        Expr::Binary { op, left, right } => format!(
+            "({} {} {})",
+            expression(field_map, left),
+            op,
+            expression(field_map, right)
+        ),
+    }
+}
+
+fn literal(value: &Literal) -> String {
+    match value {
+        Literal::Str(val) => quote(val),
+        Literal::Null => "null".to_string(),
+        Literal::Placeholder(_) => unreachable!("placeholders are bound before running"),
+        value if value.is_negative() => format!("({value})"),
+        value => value.to_string(),
+    }
+}
+
+/// A literal written into `field`, Neo4j keeps the type of each property so
+/// integers going into a float field are stored as floats.
+fn stored(field: &FragmentField, value: &Literal) -> String {
+    match (field.r#type.as_str(), value) {
+        ("float", Literal::Int(val)) => literal(&Literal::Float(*val as f64)),
+        _ => literal(value),
+    }
+}
+
+/// Labels and property names are case-sensitive, fragment names are kept as
+/// written and quoted with backticks.
+fn identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "``"))
+}
+
+/// A string literal with its backslashes and quotes escaped.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn condition(field_map: &HashMap<String, FragmentField>, filter: &Where) -> String {
+    match filter {
+        Where::Comparison { column, op, value } => {
+            let field = &field_map[column];
+            let op = match op.as_str() {
+                "!=" => "<>",
+                op => op,
+            };
+            let value = match value {
+                Operand::Column(other) => format!("n.{}", field_map[other].name),
+                Operand::Value(value) => literal(value),
+            };
+            format!("n.{} {} {}", field.name, op, value)
+        }
+        Where::And(left, right) => format!(
+            "({} AND {})",
+            condition(field_map, left),
+            condition(field_map, right)
+        ),
+        Where::Or(left, right) => format!(
+            "({} OR {})",
+            condition(field_map, left),
+            condition(field_map, right)
+        ),
+        Where::Not(inner) => format!("NOT ({})", condition(field_map, inner)),
+        Where::In { column, values } => {
+            let field = &field_map[column];
+            let values = values.iter().map(literal).collect::<Vec<_>>().join(", ");
+            format!("n.{} IN [{}]", field.name, values)
+        }
+        Where::Between { column, low, high } => {
+            let field = &field_map[column];
+            format!(
+                "(n.{0} >= {1} AND n.{0} <= {2})",
+                field.name,
+                literal(low),
+                literal(high)
+            )
+        }
+        Where::Like { column, pattern } => {
+            let field = &field_map[column];
+            let Literal::Str(pattern) = pattern else {
+                unreachable!("LIKE takes a string pattern")
+            };
+            match pattern.strip_suffix('%') {
+                Some(prefix) if !prefix.contains(['%', '_']) => {
+                    format!("n.{} STARTS WITH {}", field.name, quote(prefix))
+                }
+                // `.` only matches a newline with the `s` flag.
+                _ => {
+                    let regex = format!("(?s){}", like_regex(pattern));
+                    format!("n.{} =~ {}", field.name, quote(&regex))
+                }
+            }
+        }
+        Where::IsNull { column } => format!("n.{} IS NULL", field_map[column].name),
+        Where::InSelect { .. } | Where::Exists(_) => {
+            unreachable!("subqueries are resolved by the coordinator")
         }
     }
 }
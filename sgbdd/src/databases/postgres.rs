@@ -1,10 +1,21 @@
 use anyhow::anyhow;
-use sqlx::{Acquire, Column, PgConnection, Row};
+use sqlx::{PgConnection, Row};
 use tokio::runtime::Runtime;
 
-use super::{Database, DatabaseInfo, Query, generate_id};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    Database, DatabaseInfo, ID_PLACEHOLDER, Ids, Query, altered_table, created_table, expr_type,
+    finish, item_type, quote_names, select_items,
+};
 use crate::{
-    QueryMessage,
+    Aggregate, Alteration, BinaryOp, Expr, Literal, Operand, OrderBy, QueryMessage, SelectItem,
+    Where,
+    config::FragmentField,
     connections::{QueryResult, Value},
 };
 
@@ -12,85 +23,145 @@ pub(crate) struct Postgres {
     conn: PgConnection,
     info: DatabaseInfo,
     rt: Runtime,
+    wildcard: HashMap<String, Vec<String>>,
+    /// Whether a transaction is open on `conn`.
+    transaction: bool,
+    /// Global id of the open transaction once PREPARE TRANSACTION detached
+    /// it from `conn`.
+    prepared: Option<String>,
 }
 
 impl Postgres {
-    pub(crate) fn new(conn: PgConnection, info: DatabaseInfo, rt: Runtime) -> Postgres {
-        Postgres { conn, info, rt }
+    pub(crate) fn new(
+        conn: PgConnection,
+        info: DatabaseInfo,
+        rt: Runtime,
+        wildcard: HashMap<String, Vec<String>>,
+    ) -> Postgres {
+        Postgres {
+            conn,
+            info: quote_names(info, identifier),
+            rt,
+            wildcard,
+            transaction: false,
+            prepared: None,
+        }
     }
 
+    /// Runs `query` in a transaction the coordinator then commits or aborts
+    /// with two-phase commit, returns whether it was committed. Inside an
+    /// open transaction it waits for nothing and is only committed with the
+    /// rest of it.
     fn execute_write<F>(
         &mut self,
         query: &str,
         query_message: QueryMessage,
         map_result: F,
-    ) -> anyhow::Result<()>
+    ) -> anyhow::Result<bool>
     where
         F: FnOnce(u64) -> QueryResult,
     {
-        let mut tran = self
-            .rt
-            .block_on(self.conn.begin())
-            .map_err(|_| anyhow!("failed to begin transaction"))?;
+        let own = !self.transaction;
+        if own {
+            self.begin()?;
+        }
 
-        let res = self
-            .rt
-            .block_on(sqlx::query(query).execute(&mut *tran))
-            .map_err(|_| anyhow!("failed to execute query"))?;
+        let res = match self.rt.block_on(sqlx::query(query).execute(&mut self.conn)) {
+            Ok(res) => res,
+            Err(_) => {
+                if own {
+                    self.end(false)?;
+                }
+                return Err(anyhow!("failed to execute query"));
+            }
+        };
 
-        query_message
+        let sent = query_message
             .tx_result
-            .send(map_result(res.rows_affected()))
-            .map_err(|_| anyhow!("failed to send result"))?;
+            .send(map_result(res.rows_affected()));
+        if !own {
+            return sent
+                .map(|_| false)
+                .map_err(|_| anyhow!("failed to send result"));
+        }
 
         // std::thread::sleep(std::time::Duration::from_secs(10));
 
-        match query_message.rx_commit.recv() {
-            Ok(_) => self
-                .rt
-                .block_on(tran.commit())
-                .map_err(|_| anyhow!("failed to commit transaction"))?,
-            Err(_) => self
-                .rt
-                .block_on(tran.rollback())
-                .map_err(|_| anyhow!("transaction aborted"))?,
-        }
-
-        Ok(())
+        // A coordinator that stopped waiting dropped the commit sender too,
+        // which aborts.
+        Ok(finish(self, &query_message.rx_commit))
     }
 }
 
 impl Database for Postgres {
     type QueryType = String;
 
-    fn query(&self, sql: &Query) -> Self::QueryType {
+    fn query_with_ids(&self, sql: &Query, ids: Ids) -> Self::QueryType {
         match sql {
             Query::Select {
                 table,
                 fields,
+                distinct,
                 filter,
+                group_by,
+                order_by,
+                limit,
+                offset,
                 ..
             } => {
                 let (real_table, field_map) = &self.info[table];
 
-                let real_fields = if fields[0] == "*" {
-                    "*".to_string()
-                } else {
-                    fields
+                // Expanded so every fragment returns the columns in the global table order.
+                let real_fields = select_items(fields, &self.wildcard[table])
+                    .iter()
+                    .map(|item| projection(field_map, item))
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                let distinct = if *distinct { "DISTINCT " } else { "" };
+                let mut query = format!("SELECT {distinct}{} FROM {}", real_fields, real_table);
+
+                if let Some(filter) = filter {
+                    query.push_str(&format!(" WHERE {}", condition(field_map, filter)));
+                }
+
+                if !group_by.is_empty() {
+                    let columns = group_by
                         .iter()
-                        .map(|field| field_map[field].name.as_str())
+                        .map(|column| field_map[column].name.as_str())
                         .collect::<Vec<_>>()
-                        .join(",")
-                };
+                        .join(",");
+                    query.push_str(&format!(" GROUP BY {}", columns));
+                }
 
-                let mut query = format!("SELECT {} FROM {}", real_fields, real_table);
+                if !order_by.is_empty() {
+                    let order = order_by
+                        .iter()
+                        .map(|OrderBy { column, descending }| {
+                            let col = &field_map[column];
+                            // Same ordering the coordinator merges with: bytewise strings, NULL lowest.
+                            let collate = if col.r#type == "string" {
+                                " COLLATE \"C\""
+                            } else {
+                                ""
+                            };
+                            let direction = if *descending {
+                                "DESC NULLS LAST"
+                            } else {
+                                "ASC NULLS FIRST"
+                            };
+                            format!("{}{} {}", col.name, collate, direction)
+                        })
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    query.push_str(&format!(" ORDER BY {}", order));
+                }
 
-                if let Some(filter) = filter {
-                    let col = &field_map[&filter.column];
-                    query.push_str(&format!(
-                        " WHERE {} {} '{}'",
-                        col.name, filter.op, filter.value
-                    ));
+                if let Some(limit) = limit {
+                    query.push_str(&format!(" LIMIT {}", limit));
+                }
+                if let Some(offset) = offset {
+                    query.push_str(&format!(" OFFSET {}", offset));
                 }
 
                 query
@@ -115,19 +186,16 @@ impl Database for Postgres {
                     .map(|r| {
                         let mut row_values = Vec::new();
 
-                        let id_value = generate_id();
-                        row_values.push(id_value.to_string());
+                        row_values.push(
+                            ids.next()
+                                .map_or(ID_PLACEHOLDER.to_string(), |id| id.to_string()),
+                        );
 
                         for (col, val) in columns.iter().zip(r.iter()) {
                             if col == "IdCliente" {
                                 continue;
                             }
-                            let f = &field_map[col];
-                            let v = match f.r#type.as_str() {
-                                "string" => format!("'{}'", val),
-                                _ => val.to_string(),
-                            };
-                            row_values.push(v);
+                            row_values.push(literal(val));
                         }
 
                         format!("({})", row_values.join(","))
@@ -155,7 +223,7 @@ impl Database for Postgres {
                     .iter()
                     .map(|(col, val)| {
                         let real_col = &field_map[col];
-                        format!("{} = {}", real_col.name, val)
+                        format!("{} = {}", real_col.name, expression(field_map, val))
                     })
                     .collect::<Vec<_>>()
                     .join(",");
@@ -163,11 +231,7 @@ impl Database for Postgres {
                 let mut query = format!("UPDATE {} SET {}", real_table, assignments_str);
 
                 if let Some(filter) = filter {
-                    let col = &field_map[&filter.column];
-                    query.push_str(&format!(
-                        " WHERE {} {} {}",
-                        col.name, filter.op, filter.value
-                    ));
+                    query.push_str(&format!(" WHERE {}", condition(field_map, filter)));
                 }
 
                 query
@@ -178,15 +242,57 @@ impl Database for Postgres {
                 let mut query = format!("DELETE FROM {}", real_table);
 
                 if let Some(filter) = filter {
-                    let col = &field_map[&filter.column];
-                    query.push_str(&format!(
-                        " WHERE {} {} {}",
-                        col.name, filter.op, filter.value
-                    ));
+                    query.push_str(&format!(" WHERE {}", condition(field_map, filter)));
                 }
 
                 query
             }
+            Query::CreateTable {
+                columns, fragments, ..
+            } => {
+                let fragment = &fragments[0];
+
+                let real_columns = fragment
+                    .columns
+                    .iter()
+                    .map(|(column, name)| {
+                        let (_, r#type) = columns.iter().find(|(c, _)| c == column).unwrap();
+                        format!("{} {}", identifier(name), column_type(r#type))
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                format!(
+                    "CREATE TABLE {} ({})",
+                    identifier(&fragment.name),
+                    real_columns
+                )
+            }
+            Query::AlterTable { table, alteration } => {
+                let (real_table, field_map) = &self.info[table];
+
+                let change = match alteration {
+                    Alteration::AddColumn { column, r#type } => {
+                        format!("ADD COLUMN {} {}", identifier(column), column_type(r#type))
+                    }
+                    Alteration::DropColumn { column } => {
+                        format!("DROP COLUMN {}", field_map[column].name)
+                    }
+                    Alteration::RenameColumn { column, name } => format!(
+                        "RENAME COLUMN {} TO {}",
+                        field_map[column].name,
+                        identifier(name)
+                    ),
+                };
+
+                format!("ALTER TABLE {} {}", real_table, change)
+            }
+            Query::DropTable { table } => format!("DROP TABLE {}", self.info[table].0),
+            Query::Explain { .. } => unreachable!("EXPLAIN is answered by run_database"),
+            Query::Catalog(_) => unreachable!("the coordinator answers SHOW and DESCRIBE"),
+            Query::Begin | Query::Commit | Query::Rollback => {
+                unreachable!("transactions are handled by run_database")
+            }
         }
     }
 
@@ -194,8 +300,12 @@ impl Database for Postgres {
         let query = self.query(&query_message.query);
 
         match &*query_message.query {
-            Query::Select { table, .. } => {
+            Query::Select { table, fields, .. } => {
                 let real_fields = &self.info[table].1;
+                let types: Vec<_> = select_items(fields, &self.wildcard[table])
+                    .iter()
+                    .map(|item| item_type(item, real_fields))
+                    .collect();
 
                 let rows = self
                     .rt
@@ -203,32 +313,26 @@ impl Database for Postgres {
 
                 let mut results = Vec::new();
                 for row in rows {
-                    let mut row_values = Vec::new();
-
-                    for col in row.columns() {
-                        let col_name = col.name();
-                        let field = real_fields
-                            .values()
-                            .find(|v| v.name.to_lowercase() == col_name)
-                            .unwrap();
-
-                        let value = match field.r#type.as_str() {
-                            "int" => match row.try_get::<i32, _>(col_name) {
-                                Ok(v) => Value::Int(v as i64),
-                                Err(_) => Value::Null,
-                            },
-                            "float" => match row.try_get::<f64, _>(col_name) {
-                                Ok(v) => Value::Float(v),
-                                Err(_) => Value::Null,
-                            },
-                            "bool" => match row.try_get::<bool, _>(col_name) {
-                                Ok(v) => Value::Bool(v),
-                                Err(_) => Value::Null,
-                            },
-                            "string" => match row.try_get::<String, _>(col_name) {
-                                Ok(v) => Value::Str(v),
-                                Err(_) => Value::Null,
-                            },
+                    let mut row_values = Vec::with_capacity(types.len());
+
+                    for (idx, r#type) in types.iter().enumerate() {
+                        let value = match *r#type {
+                            // Aggregates come back as BIGINT, plain columns as INTEGER.
+                            "int" => row
+                                .try_get::<Option<i64>, _>(idx)
+                                .or_else(|_| {
+                                    row.try_get::<Option<i32>, _>(idx).map(|v| v.map(i64::from))
+                                })?
+                                .map_or(Value::Null, Value::Int),
+                            "float" => row
+                                .try_get::<Option<f64>, _>(idx)?
+                                .map_or(Value::Null, Value::Float),
+                            "bool" => row
+                                .try_get::<Option<bool>, _>(idx)?
+                                .map_or(Value::Null, Value::Bool),
+                            "string" => row
+                                .try_get::<Option<String>, _>(idx)?
+                                .map_or(Value::Null, Value::Str),
                             _ => Value::Null,
                         };
 
@@ -241,13 +345,246 @@ impl Database for Postgres {
                 query_message
                     .tx_result
                     .send(QueryResult::Select(results))
-                    .unwrap();
+                    .map_err(|_| anyhow!("failed to send result"))?;
+
+                Ok(())
+            }
+            Query::Insert { .. } => self
+                .execute_write(&query, query_message, QueryResult::Insert)
+                .map(|_| ()),
+            Query::Update { .. } => self
+                .execute_write(&query, query_message, QueryResult::Update)
+                .map(|_| ()),
+            Query::Delete { .. } => self
+                .execute_write(&query, query_message, QueryResult::Delete)
+                .map(|_| ()),
+            Query::CreateTable { .. } => {
+                let (info, wildcard) = created_table(&query_message.query);
+                if self.execute_write(&query, query_message, |_| QueryResult::CreateTable)? {
+                    self.info.extend(quote_names(info, identifier));
+                    self.wildcard.extend(wildcard);
+                }
+
+                Ok(())
+            }
+            Query::AlterTable { .. } | Query::DropTable { .. } => {
+                let ddl = Arc::clone(&query_message.query);
+                let result = match *ddl {
+                    Query::AlterTable { .. } => QueryResult::AlterTable,
+                    _ => QueryResult::DropTable,
+                };
+                if self.execute_write(&query, query_message, |_| result)? {
+                    altered_table(&mut self.info, &mut self.wildcard, &ddl, identifier);
+                }
 
                 Ok(())
             }
-            Query::Insert { .. } => self.execute_write(&query, query_message, QueryResult::Insert),
-            Query::Update { .. } => self.execute_write(&query, query_message, QueryResult::Update),
-            Query::Delete { .. } => self.execute_write(&query, query_message, QueryResult::Delete),
+            Query::Explain { .. } => unreachable!("EXPLAIN is answered by run_database"),
+            Query::Catalog(_) => unreachable!("the coordinator answers SHOW and DESCRIBE"),
+            Query::Begin | Query::Commit | Query::Rollback => {
+                unreachable!("transactions are handled by run_database")
+            }
+        }
+    }
+
+    fn explain(&self, sql: &Query) -> String {
+        self.query_with_ids(sql, Ids::Placeholder)
+    }
+
+    /// The transaction is held by the connection itself, so every statement
+    /// sent on it runs inside.
+    fn begin(&mut self) -> anyhow::Result<()> {
+        self.rt
+            .block_on(sqlx::query("BEGIN").execute(&mut self.conn))
+            .map_err(|_| anyhow!("failed to begin transaction"))?;
+        self.transaction = true;
+        Ok(())
+    }
+
+    /// A transaction that can't be prepared is rolled back without an
+    /// error, so the vote looks for it among the prepared ones.
+    fn prepare(&mut self) -> anyhow::Result<()> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let gid = format!("sgbdd_{nanos}");
+        self.rt
+            .block_on(sqlx::query(&format!("PREPARE TRANSACTION '{gid}'")).execute(&mut self.conn))
+            .map_err(|_| anyhow!("failed to prepare transaction"))?;
+
+        let prepared = self
+            .rt
+            .block_on(
+                sqlx::query("SELECT 1 FROM pg_prepared_xacts WHERE gid = $1")
+                    .bind(&gid)
+                    .fetch_optional(&mut self.conn),
+            )?
+            .is_some();
+        if !prepared {
+            self.transaction = false;
+            return Err(anyhow!("transaction was rolled back instead of prepared"));
+        }
+        self.prepared = Some(gid);
+        Ok(())
+    }
+
+    fn end(&mut self, commit: bool) -> anyhow::Result<()> {
+        self.transaction = false;
+        let statement = match (self.prepared.take(), commit) {
+            (Some(gid), true) => format!("COMMIT PREPARED '{gid}'"),
+            (Some(gid), false) => format!("ROLLBACK PREPARED '{gid}'"),
+            (None, true) => "COMMIT".to_string(),
+            (None, false) => "ROLLBACK".to_string(),
+        };
+        self.rt
+            .block_on(sqlx::query(&statement).execute(&mut self.conn))
+            .map_err(|_| anyhow!("failed to end transaction"))?;
+        Ok(())
+    }
+}
+
+fn projection(field_map: &HashMap<String, FragmentField>, item: &SelectItem) -> String {
+    match item {
+        SelectItem::Column(column) => field_map[column].name.clone(),
+        SelectItem::Aggregate {
+            function,
+            column: None,
+        } => format!("{}(*)", aggregate(*function)),
+        SelectItem::Aggregate {
+            function,
+            column: Some(column),
+        } => {
+            let col = &field_map[column];
+            let cast = match (function, col.r#type.as_str()) {
+                (Aggregate::Sum, "int") => "::bigint",
+                (Aggregate::Sum | Aggregate::Avg, _) => "::float8",
+                _ => "",
+            };
+            format!("{}({}){}", aggregate(*function), col.name, cast)
+        }
+        // Cast so mixed int and float operands decode with the type the coordinator expects.
+        SelectItem::Expression(expr) => {
+            let cast = match expr_type(expr, field_map) {
+                "int" => "bigint",
+                "float" => "float8",
+                "bool" => "boolean",
+                _ => "text",
+            };
+            format!("({})::{}", expression(field_map, expr), cast)
+        }
+        SelectItem::Wildcard => unreachable!("wildcard must be expanded"),
+    }
+}
+
+fn expression(field_map: &HashMap<String, FragmentField>, expr: &Expr) -> String {
+    match expr {
+        Expr::Column(column) => field_map[column].name.clone(),
+        Expr::Literal(value) => literal(value),
+        Expr::Binary {
+            op: BinaryOp::Div,
+            left,
+            right,
+        } => format!(
+            "({}::float8 / NULLIF({}, 0))",
+            expression(field_map, left),
+            expression(field_map, right)
+        ),
+        Expr::Binary { op, left, right } => format!(
+            "({} {} {})",
+            expression(field_map, left),
+            op,
+            expression(field_map, right)
+        ),
+    }
+}
+
+fn aggregate(function: Aggregate) -> &'static str {
+    match function {
+        Aggregate::Count => "COUNT",
+        Aggregate::Sum => "SUM",
+        Aggregate::Avg => "AVG",
+        Aggregate::Min => "MIN",
+        Aggregate::Max => "MAX",
+    }
+}
+
+/// A literal as Postgres reads it, integers are widened when compared with floats.
+fn literal(value: &Literal) -> String {
+    match value {
+        Literal::Str(val) => quote(val),
+        Literal::Null => "NULL".to_string(),
+        Literal::Placeholder(_) => unreachable!("placeholders are bound before running"),
+        // Parenthesized so `a - -1` doesn't read as a comment.
+        value if value.is_negative() => format!("({value})"),
+        value => value.to_string(),
+    }
+}
+
+/// Postgres folds unquoted names to lowercase, fragment names are matched
+/// the same way and then quoted.
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.to_lowercase().replace('"', "\"\""))
+}
+
+/// The column type a CREATE TABLE gives a field, the widest Postgres has.
+fn column_type(r#type: &str) -> &'static str {
+    match r#type {
+        "int" => "BIGINT",
+        "float" => "DOUBLE PRECISION",
+        "bool" => "BOOLEAN",
+        _ => "TEXT",
+    }
+}
+
+/// A string literal with its quotes doubled.
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn condition(field_map: &HashMap<String, FragmentField>, filter: &Where) -> String {
+    match filter {
+        Where::Comparison { column, op, value } => {
+            let col = &field_map[column];
+            let value = match value {
+                Operand::Column(other) => field_map[other].name.clone(),
+                Operand::Value(value) => literal(value),
+            };
+            format!("{} {} {}", col.name, op, value)
+        }
+        Where::And(left, right) => format!(
+            "({} AND {})",
+            condition(field_map, left),
+            condition(field_map, right)
+        ),
+        Where::Or(left, right) => format!(
+            "({} OR {})",
+            condition(field_map, left),
+            condition(field_map, right)
+        ),
+        Where::Not(inner) => format!("NOT ({})", condition(field_map, inner)),
+        Where::In { column, values } => {
+            let col = &field_map[column];
+            let values = values.iter().map(literal).collect::<Vec<_>>().join(",");
+            format!("{} = ANY(ARRAY[{}])", col.name, values)
+        }
+        Where::Between { column, low, high } => {
+            let col = &field_map[column];
+            format!(
+                "{} BETWEEN {} AND {}",
+                col.name,
+                literal(low),
+                literal(high)
+            )
+        }
+        Where::Like { column, pattern } => {
+            // Without an escape character '\' is literal, like in the other backends.
+            format!(
+                "{} LIKE {} ESCAPE ''",
+                field_map[column].name,
+                literal(pattern)
+            )
+        }
+        Where::IsNull { column } => format!("{} IS NULL", field_map[column].name),
+        Where::InSelect { .. } | Where::Exists(_) => {
+            unreachable!("subqueries are resolved by the coordinator")
         }
     }
 }
@@ -0,0 +1,368 @@
+//! Statements that change the schema. They are checked against the schema
+//! they would leave, every database then changes its own fragment and the
+//! coordinator saves the schema once all of them committed.
+
+use anyhow::anyhow;
+
+use crate::{
+    Alteration, FragmentDef, Query,
+    config::{Config, Field, Fragment, FragmentField, Table},
+    diagnostic::name_error,
+    names::same_name,
+    validations::validate_config,
+};
+
+/// Inserts generate `IdCliente` and route rows by `Estado`, so every table has them.
+pub(crate) const REQUIRED_COLUMNS: [(&str, &str); 2] = [("IdCliente", "int"), ("Estado", "string")];
+
+const TYPES: [&str; 4] = ["int", "float", "string", "bool"];
+
+/// The schema once the CREATE, ALTER or DROP TABLE `query` ran.
+pub fn new_schema(query: &Query, config: &Config) -> anyhow::Result<Config> {
+    match query {
+        Query::CreateTable {
+            table,
+            columns,
+            fragments,
+        } => create_table(table, columns, fragments, config),
+        Query::AlterTable { table, alteration } => alter_table(table, alteration, config),
+        Query::DropTable { table } => {
+            let mut schema = config.clone();
+            let idx = table_index(table, &schema)?;
+            schema.tables.remove(idx);
+            Ok(schema)
+        }
+        query => Err(anyhow!(
+            "expected CREATE, ALTER or DROP TABLE, found {query}"
+        )),
+    }
+}
+
+/// A fragment is stored by the database of its manager that already serves its zone.
+fn create_table(
+    table: &str,
+    columns: &[(String, String)],
+    fragments: &[FragmentDef],
+    config: &Config,
+) -> anyhow::Result<Config> {
+    if config.tables.iter().any(|t| same_name(&t.name, table)) {
+        return Err(name_error(table, format!("table '{table}' already exists")));
+    }
+    for (idx, (column, r#type)) in columns.iter().enumerate() {
+        if columns[..idx]
+            .iter()
+            .any(|(other, _)| same_name(other, column))
+        {
+            return Err(name_error(
+                column,
+                format!("column '{column}' appears twice in '{table}'"),
+            ));
+        }
+        check_type(column, r#type)?;
+    }
+    for (name, r#type) in REQUIRED_COLUMNS {
+        if !columns
+            .iter()
+            .any(|(column, t)| column == name && t == r#type)
+        {
+            return Err(anyhow!("table '{table}' needs a column '{name} {type}'"));
+        }
+    }
+
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|(name, r#type)| Field {
+            name: name.clone(),
+            r#type: r#type.clone(),
+        })
+        .collect();
+
+    let mut new_fragments: Vec<Fragment> = Vec::new();
+    for fragment in fragments {
+        if new_fragments
+            .iter()
+            .any(|other| other.zone == fragment.zone)
+        {
+            return Err(name_error(
+                &fragment.name,
+                format!("two fragments of '{table}' in zone '{}'", fragment.zone),
+            ));
+        }
+        let connection = connection(fragment, config)?;
+        if config
+            .tables
+            .iter()
+            .flat_map(|t| &t.fragments)
+            .any(|other| other.connection == connection && same_name(&other.name, &fragment.name))
+        {
+            return Err(name_error(
+                &fragment.name,
+                format!(
+                    "'{}' already exists in the {} database of zone '{}'",
+                    fragment.name, fragment.manager, fragment.zone
+                ),
+            ));
+        }
+        new_fragments.push(Fragment {
+            name: fragment.name.clone(),
+            connection: connection.to_string(),
+            manager: fragment.manager.clone(),
+            zone: fragment.zone.clone(),
+            fields: fragment_fields(table, &fields, fragment)?,
+        });
+    }
+
+    let mut schema = config.clone();
+    schema.tables.push(Table {
+        name: table.to_string(),
+        fields,
+        fragments: new_fragments,
+    });
+    if validate_config(&schema) {
+        return Err(anyhow!(
+            "every fragment of '{table}' must store each of its columns"
+        ));
+    }
+
+    Ok(schema)
+}
+
+fn alter_table(table: &str, alteration: &Alteration, config: &Config) -> anyhow::Result<Config> {
+    let mut schema = config.clone();
+    let idx = table_index(table, &schema)?;
+    let global = &mut schema.tables[idx];
+
+    match alteration {
+        Alteration::AddColumn { column, r#type } => {
+            check_type(column, r#type)?;
+            if global
+                .fields
+                .iter()
+                .any(|field| same_name(&field.name, column))
+            {
+                return Err(name_error(
+                    column,
+                    format!("column '{column}' already exists in '{table}'"),
+                ));
+            }
+            for fragment in &mut global.fragments {
+                check_stored(fragment, column)?;
+                fragment.fields.push(FragmentField {
+                    name: column.clone(),
+                    reference: column.clone(),
+                    r#type: r#type.clone(),
+                });
+            }
+            global.fields.push(Field {
+                name: column.clone(),
+                r#type: r#type.clone(),
+            });
+        }
+        Alteration::DropColumn { column } => {
+            check_changed(global, column)?;
+            global.fields.retain(|field| field.name != *column);
+            for fragment in &mut global.fragments {
+                fragment.fields.retain(|field| field.reference != *column);
+            }
+        }
+        Alteration::RenameColumn { column, name } => {
+            check_changed(global, column)?;
+            if same_name(column, name) {
+                return Err(name_error(
+                    name,
+                    format!("column '{column}' is already named '{name}'"),
+                ));
+            }
+            if global
+                .fields
+                .iter()
+                .any(|field| same_name(&field.name, name))
+            {
+                return Err(name_error(
+                    name,
+                    format!("column '{name}' already exists in '{table}'"),
+                ));
+            }
+            for fragment in &mut global.fragments {
+                check_stored(fragment, name)?;
+                for field in &mut fragment.fields {
+                    if field.reference == *column {
+                        field.name.clone_from(name);
+                        field.reference.clone_from(name);
+                    }
+                }
+            }
+            for field in &mut global.fields {
+                if field.name == *column {
+                    field.name.clone_from(name);
+                }
+            }
+        }
+    }
+
+    Ok(schema)
+}
+
+/// The statement the database at `connection` runs, `None` when it stores
+/// none of the table. `config` is the schema before the statement and
+/// `schema` the one after it, a CREATE TABLE only holds the fragment
+/// `schema` places in the database.
+pub fn fragment_ddl(
+    query: &Query,
+    config: &Config,
+    schema: &Config,
+    connection: &str,
+) -> Option<Query> {
+    let (table, columns, fragments) = match query {
+        Query::CreateTable {
+            table,
+            columns,
+            fragments,
+        } => (table, columns, fragments),
+        Query::AlterTable { table, .. } | Query::DropTable { table } => {
+            return config
+                .has_fragment(table, connection)
+                .then(|| query.clone());
+        }
+        _ => return None,
+    };
+
+    let stored = schema
+        .tables
+        .iter()
+        .find(|t| t.name == *table)?
+        .fragments
+        .iter()
+        .find(|fragment| fragment.connection == connection)?;
+    let fragment = fragments
+        .iter()
+        .find(|fragment| fragment.name == stored.name)?;
+
+    Some(Query::CreateTable {
+        table: table.clone(),
+        columns: columns.clone(),
+        fragments: vec![fragment.clone()],
+    })
+}
+
+fn table_index(table: &str, config: &Config) -> anyhow::Result<usize> {
+    config
+        .tables
+        .iter()
+        .position(|t| t.name == table)
+        .ok_or_else(|| name_error(table, format!("table '{table}' not found")))
+}
+
+fn check_type(column: &str, r#type: &str) -> anyhow::Result<()> {
+    if TYPES.contains(&r#type) {
+        Ok(())
+    } else {
+        Err(name_error(
+            r#type,
+            format!("unknown type '{type}' for column '{column}'"),
+        ))
+    }
+}
+
+/// Only a column of the table that inserts don't rely on can be dropped or renamed.
+fn check_changed(table: &Table, column: &str) -> anyhow::Result<()> {
+    if !table.fields.iter().any(|field| field.name == column) {
+        return Err(name_error(
+            column,
+            format!("missing field '{column}' in table '{}'", table.name),
+        ));
+    }
+    if REQUIRED_COLUMNS.iter().any(|(name, _)| *name == column) {
+        return Err(name_error(
+            column,
+            format!("every table needs column '{column}'"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether `fragment` can store a new field called `name`.
+fn check_stored(fragment: &Fragment, name: &str) -> anyhow::Result<()> {
+    if fragment
+        .fields
+        .iter()
+        .any(|field| same_name(&field.name, name))
+    {
+        return Err(name_error(
+            name,
+            format!(
+                "fragment '{}' already stores a field '{name}'",
+                fragment.name
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+fn connection<'a>(fragment: &FragmentDef, config: &'a Config) -> anyhow::Result<&'a str> {
+    config
+        .tables
+        .iter()
+        .flat_map(|table| &table.fragments)
+        .find(|other| other.manager == fragment.manager && other.zone == fragment.zone)
+        .map(|other| other.connection.as_str())
+        .ok_or_else(|| {
+            name_error(
+                &fragment.manager,
+                format!(
+                    "no {} database serves zone '{}'",
+                    fragment.manager, fragment.zone
+                ),
+            )
+        })
+}
+
+fn fragment_fields(
+    table: &str,
+    fields: &[Field],
+    fragment: &FragmentDef,
+) -> anyhow::Result<Vec<FragmentField>> {
+    if fragment.columns.len() != fields.len() {
+        return Err(name_error(
+            &fragment.name,
+            format!(
+                "fragment '{}' must store every column of '{table}' once",
+                fragment.name
+            ),
+        ));
+    }
+
+    let mut fragment_fields: Vec<FragmentField> = Vec::new();
+    for (column, name) in &fragment.columns {
+        let field = fields
+            .iter()
+            .find(|field| field.name == *column)
+            .ok_or_else(|| {
+                name_error(
+                    column,
+                    format!("missing field '{column}' in table '{table}'"),
+                )
+            })?;
+        if fragment_fields
+            .iter()
+            .any(|other| same_name(&other.name, name))
+        {
+            return Err(name_error(
+                name,
+                format!(
+                    "fragment '{}' stores two columns as '{name}'",
+                    fragment.name
+                ),
+            ));
+        }
+        fragment_fields.push(FragmentField {
+            name: name.clone(),
+            reference: column.clone(),
+            r#type: field.r#type.clone(),
+        });
+    }
+
+    Ok(fragment_fields)
+}
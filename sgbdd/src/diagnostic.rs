@@ -0,0 +1,134 @@
+use std::fmt;
+
+/// Where a token starts in the source, lines and columns count from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// Characters the token covers.
+    pub len: usize,
+}
+
+/// An error that points at the part of the source that caused it.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl fmt::Display, span: Option<Span>) -> Self {
+        Self {
+            message: message.to_string(),
+            span,
+        }
+    }
+
+    /// The offending line of `source` with a caret under the span.
+    pub fn render(&self, source: &str) -> String {
+        let Some(span) = self.span else {
+            return self.message.clone();
+        };
+        let Some(line) = source.lines().nth(span.line - 1) else {
+            return self.to_string();
+        };
+
+        let number = span.line.to_string();
+        // Tabs are kept so the caret lines up with the text above it.
+        let indent: String = line
+            .chars()
+            .take(span.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        // A token spanning several lines is only underlined up to the end of its first one.
+        let len = span
+            .len
+            .min(line.chars().count().saturating_sub(span.column - 1))
+            .max(1);
+
+        format!(
+            "{number} | {line}\n{} | {indent}{} {}",
+            " ".repeat(number.len()),
+            "^".repeat(len),
+            self.message
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}:{}: {}", span.line, span.column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// The part of a statement a name is written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Clause {
+    Select,
+    Join,
+    Where,
+    GroupBy,
+    Having,
+    OrderBy,
+    Set,
+    Columns,
+}
+
+impl fmt::Display for Clause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let clause = match self {
+            Clause::Select => "select list",
+            Clause::Join => "join condition",
+            Clause::Where => "where clause",
+            Clause::GroupBy => "group by clause",
+            Clause::Having => "having clause",
+            Clause::OrderBy => "order by clause",
+            Clause::Set => "set clause",
+            Clause::Columns => "insert column list",
+        };
+        write!(f, "{clause}")
+    }
+}
+
+/// A validation error caused by a name of the statement, reported under the
+/// first place that name is written in `clause`, or anywhere without one.
+#[derive(Debug)]
+pub(crate) struct NameError {
+    pub name: String,
+    pub clause: Option<Clause>,
+    pub message: String,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for NameError {}
+
+pub(crate) fn name_error(name: &str, message: impl fmt::Display) -> anyhow::Error {
+    anyhow::Error::new(NameError {
+        name: name.to_string(),
+        clause: None,
+        message: message.to_string(),
+    })
+}
+
+/// [`name_error`] for a name written in `clause`.
+pub(crate) fn clause_error(
+    name: &str,
+    clause: Clause,
+    message: impl fmt::Display,
+) -> anyhow::Error {
+    anyhow::Error::new(NameError {
+        name: name.to_string(),
+        clause: Some(clause),
+        message: message.to_string(),
+    })
+}
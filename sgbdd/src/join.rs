@@ -0,0 +1,808 @@
+use std::{
+    collections::{HashMap, HashSet},
+    iter,
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+
+use super::{
+    BinaryOp, Config, Expr, Join, Literal, Operand, OrderBy, Query, QueryResult, SelectItem, Value,
+    Where,
+    config::{Field, Table},
+    databases,
+    diagnostic::name_error,
+    merge,
+};
+
+/// Most join keys sent to the fragments of the next table as an IN filter,
+/// with more of them the whole table is fetched and only hash joined.
+const SEMIJOIN_LIMIT: usize = 1000;
+
+/// Runs a join as one scan per table, a single-table SELECT sent to the
+/// fragments of that table, and hash joins the rows on the coordinator.
+///
+/// Tables are fetched in the order they appear in the query. Conditions on a
+/// single table are pushed to its scan, and once a table is joined the keys
+/// it left restrict the scan of the next one (a semijoin).
+pub struct JoinPlan {
+    query: Query,
+    wildcard: Vec<String>,
+    scans: Vec<Query>,
+    /// Qualified columns returned by every scan.
+    scan_columns: Vec<Vec<String>>,
+    /// Conditions over several tables, evaluated once everything is joined.
+    residual: Option<Where>,
+    fetched: usize,
+    /// Columns of the rows joined so far, the columns of each scan one after the other.
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl JoinPlan {
+    pub fn new(query: &Query, config: &Config) -> anyhow::Result<JoinPlan> {
+        let query = qualify(query, config)?;
+        let Query::Select {
+            table,
+            joins,
+            fields,
+            filter,
+            order_by,
+            zones,
+            ..
+        } = &query
+        else {
+            return Err(anyhow!("expected a SELECT query"));
+        };
+
+        let tables: Vec<&str> = iter::once(table.as_str())
+            .chain(joins.iter().map(|join| join.table.as_str()))
+            .collect();
+        let wildcard: Vec<String> = joined_table(table, joins, config)?
+            .fields
+            .into_iter()
+            .map(|field| field.name)
+            .collect();
+
+        let mut pushed: Vec<Vec<Where>> = vec![Vec::new(); tables.len()];
+        let mut residual = Vec::new();
+        if let Some(filter) = filter {
+            for conjunct in conjuncts(filter) {
+                let mut columns = Vec::new();
+                where_columns(&conjunct, &mut columns);
+                let owners: HashSet<_> = columns.iter().map(|column| owner(column)).collect();
+                match owners.into_iter().collect::<Vec<_>>()[..] {
+                    [table] => {
+                        let idx = tables.iter().position(|t| *t == table).unwrap();
+                        pushed[idx].push(conjunct);
+                    }
+                    _ => residual.push(conjunct),
+                }
+            }
+        }
+
+        let mut needed = Vec::new();
+        for item in databases::select_items(fields, &wildcard) {
+            match item {
+                SelectItem::Column(column) => needed.push(column),
+                SelectItem::Expression(expr) => expr_columns(&expr, &mut needed),
+                item => unreachable!("{item:?} is rejected over joins"),
+            }
+        }
+        for join in joins {
+            needed.push(join.left.clone());
+            needed.push(join.right.clone());
+        }
+        for conjunct in &residual {
+            let mut columns = Vec::new();
+            where_columns(conjunct, &mut columns);
+            needed.extend(columns.into_iter().map(str::to_string));
+        }
+        needed.extend(order_by.iter().map(|order| order.column.clone()));
+
+        let mut scans = Vec::new();
+        let mut scan_columns = Vec::new();
+        for (idx, table) in tables.iter().enumerate() {
+            let mut columns: Vec<String> = Vec::new();
+            for column in needed.iter().filter(|column| owner(column) == *table) {
+                if !columns.contains(column) {
+                    columns.push(column.clone());
+                }
+            }
+
+            let filter = pushed[idx]
+                .drain(..)
+                .map(|conjunct| rename_where(&conjunct, &|column| Ok(name(column).to_string())))
+                .collect::<anyhow::Result<Vec<_>>>()?
+                .into_iter()
+                .reduce(|left, right| Where::And(Box::new(left), Box::new(right)));
+
+            scans.push(Query::Select {
+                table: table.to_string(),
+                joins: Vec::new(),
+                fields: columns
+                    .iter()
+                    .map(|column| SelectItem::Column(name(column).to_string()))
+                    .collect(),
+                aliases: vec![None; columns.len()],
+                distinct: false,
+                filter,
+                group_by: Vec::new(),
+                having: None,
+                order_by: Vec::new(),
+                limit: None,
+                offset: None,
+                zones: zones.clone(),
+            });
+            scan_columns.push(columns);
+        }
+
+        let residual = residual
+            .into_iter()
+            .reduce(|left, right| Where::And(Box::new(left), Box::new(right)));
+
+        Ok(JoinPlan {
+            query,
+            wildcard,
+            scans,
+            scan_columns,
+            residual,
+            fetched: 0,
+            columns: Vec::new(),
+            rows: Vec::new(),
+        })
+    }
+
+    /// The scan of the next table, `None` once every table was fetched or
+    /// the join is already known to be empty.
+    pub fn next_scan(&self) -> Option<Arc<Query>> {
+        let mut scan = self.scans.get(self.fetched)?.clone();
+        if self.fetched == 0 {
+            return Some(Arc::new(scan));
+        }
+
+        let join = &self.joins()[self.fetched - 1];
+        let position = self.position(&join.left);
+        // NULL never matches, so only the other keys can find a partner.
+        let mut keys: Vec<&Value> = self
+            .rows
+            .iter()
+            .map(|row| &row[position])
+            .filter(|key| !matches!(key, Value::Null))
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        keys.sort();
+        if keys.is_empty() {
+            return None;
+        }
+
+        if keys.len() <= SEMIJOIN_LIMIT {
+            let semijoin = Where::In {
+                column: name(&join.right).to_string(),
+                values: keys.iter().map(|key| Literal::from_value(key)).collect(),
+            };
+            if let Query::Select { filter, .. } = &mut scan {
+                *filter = Some(match filter.take() {
+                    Some(filter) => Where::And(Box::new(filter), Box::new(semijoin)),
+                    None => semijoin,
+                });
+            }
+        }
+
+        Some(Arc::new(scan))
+    }
+
+    /// The scan of every table before the keys of the previous ones restrict it.
+    pub fn scans(&self) -> impl Iterator<Item = Arc<Query>> + '_ {
+        self.scans.iter().cloned().map(Arc::new)
+    }
+
+    /// Joins the merged rows of the last scan with the rows joined so far.
+    pub fn push_rows(&mut self, rows: Vec<Vec<Value>>) {
+        let idx = self.fetched;
+        self.fetched += 1;
+
+        if idx == 0 {
+            self.columns = self.scan_columns[0].clone();
+            self.rows = rows;
+            return;
+        }
+
+        let join = &self.joins()[idx - 1];
+        let left = self.position(&join.left);
+        let right = self.scan_columns[idx]
+            .iter()
+            .position(|column| *column == join.right)
+            .unwrap();
+
+        let mut table: HashMap<&Value, Vec<&Vec<Value>>> = HashMap::new();
+        for row in &rows {
+            if !matches!(row[right], Value::Null) {
+                table.entry(&row[right]).or_default().push(row);
+            }
+        }
+
+        self.rows = self
+            .rows
+            .iter()
+            .flat_map(|row| {
+                table
+                    .get(&row[left])
+                    .into_iter()
+                    .flatten()
+                    .map(|other| row.iter().chain(other.iter()).cloned().collect())
+            })
+            .collect();
+        self.columns.extend(self.scan_columns[idx].iter().cloned());
+    }
+
+    /// Filters, projects, sorts and cuts the joined rows into the result of the query.
+    pub fn finish(self) -> anyhow::Result<QueryResult> {
+        let Query::Select {
+            fields,
+            distinct,
+            order_by,
+            limit,
+            offset,
+            ..
+        } = &self.query
+        else {
+            return Err(anyhow!("expected a SELECT query"));
+        };
+
+        // A scan left out means an empty intermediate result.
+        let rows = if self.fetched < self.scans.len() {
+            Vec::new()
+        } else {
+            self.rows
+        };
+
+        let items = databases::select_items(fields, &self.wildcard);
+        let columns = &self.columns;
+        let mut rows: Vec<Vec<Value>> = rows
+            .into_iter()
+            .filter(|row| {
+                self.residual
+                    .as_ref()
+                    .is_none_or(|residual| evaluate(residual, columns, row) == Some(true))
+            })
+            .map(|row| {
+                items
+                    .iter()
+                    .map(|item| match item {
+                        SelectItem::Column(column) => {
+                            row[columns.iter().position(|c| c == column).unwrap()].clone()
+                        }
+                        SelectItem::Expression(expr) => evaluate_expr(expr, columns, &row),
+                        item => unreachable!("{item:?} is rejected over joins"),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if !order_by.is_empty() {
+            let keys = order_by
+                .iter()
+                .map(|OrderBy { column, descending }| {
+                    items
+                        .iter()
+                        .position(|item| matches!(item, SelectItem::Column(c) if c == column))
+                        .map(|idx| (idx, *descending))
+                        .ok_or_else(|| anyhow!("order by field '{column}' not in the result"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            rows.sort_by(|left, right| merge::compare_rows(left, right, &keys));
+        }
+
+        if *distinct {
+            let mut seen = HashSet::new();
+            rows.retain(|row| seen.insert(row.clone()));
+        }
+
+        let rows = rows
+            .into_iter()
+            .skip(offset.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect();
+
+        Ok(QueryResult::Select(rows))
+    }
+
+    fn joins(&self) -> &[Join] {
+        match &self.query {
+            Query::Select { joins, .. } => joins,
+            _ => &[],
+        }
+    }
+
+    fn position(&self, column: &str) -> usize {
+        self.columns.iter().position(|c| c == column).unwrap()
+    }
+}
+
+/// `query` with every column written as `table.column` and the right side of
+/// each ON condition on the joined table.
+pub(crate) fn qualify(query: &Query, config: &Config) -> anyhow::Result<Query> {
+    let Query::Select { table, joins, .. } = query else {
+        return Ok(query.clone());
+    };
+    let tables = join_tables(table, joins, config)?;
+    let resolve = |column: &str| resolve(&tables, column);
+
+    let mut query = query.clone();
+    let Query::Select {
+        joins,
+        fields,
+        filter,
+        order_by,
+        ..
+    } = &mut query
+    else {
+        unreachable!()
+    };
+
+    for (idx, join) in joins.iter_mut().enumerate() {
+        let (mut left, mut right) = (resolve(&join.left)?, resolve(&join.right)?);
+        if owner(&left) == join.table {
+            (left, right) = (right, left);
+        }
+        let previous = &tables[..=idx];
+        if owner(&right) != join.table || !previous.iter().any(|t| t.name == owner(&left)) {
+            return Err(name_error(
+                &join.right,
+                format!(
+                    "ON must compare a field of '{}' with one of a previous table",
+                    join.table
+                ),
+            ));
+        }
+        (join.left, join.right) = (left, right);
+    }
+
+    for item in fields.iter_mut() {
+        *item = match &*item {
+            SelectItem::Column(column) => SelectItem::Column(resolve(column)?),
+            SelectItem::Expression(expr) => SelectItem::Expression(rename_expr(expr, &resolve)?),
+            item => item.clone(),
+        };
+    }
+    if let Some(filter) = filter {
+        *filter = rename_where(filter, &resolve)?;
+    }
+    for order in order_by.iter_mut() {
+        order.column = resolve(&order.column)?;
+    }
+
+    Ok(query)
+}
+
+/// All the tables of a join as one, its fields written as `table.column`.
+pub(crate) fn joined_table(table: &str, joins: &[Join], config: &Config) -> anyhow::Result<Table> {
+    let tables = join_tables(table, joins, config)?;
+
+    Ok(Table {
+        name: tables
+            .iter()
+            .map(|table| table.name.as_str())
+            .collect::<Vec<_>>()
+            .join(" JOIN "),
+        fields: tables
+            .iter()
+            .flat_map(|table| {
+                table.fields.iter().map(|field| Field {
+                    name: format!("{}.{}", table.name, field.name),
+                    r#type: field.r#type.clone(),
+                })
+            })
+            .collect(),
+        fragments: Vec::new(),
+    })
+}
+
+fn join_tables<'a>(
+    table: &str,
+    joins: &[Join],
+    config: &'a Config,
+) -> anyhow::Result<Vec<&'a Table>> {
+    let mut tables: Vec<&Table> = Vec::new();
+    for name in iter::once(table).chain(joins.iter().map(|join| join.table.as_str())) {
+        if tables.iter().any(|table| table.name == name) {
+            return Err(name_error(
+                name,
+                format!("table '{name}' appears more than once in the join"),
+            ));
+        }
+        let table = config
+            .tables
+            .iter()
+            .find(|table| table.name == name)
+            .ok_or_else(|| name_error(name, format!("table '{name}' not found")))?;
+        tables.push(table);
+    }
+
+    Ok(tables)
+}
+
+/// Writes `column` as `table.column`, unqualified names must belong to a single table.
+fn resolve(tables: &[&Table], column: &str) -> anyhow::Result<String> {
+    let has_field = |table: &Table, name: &str| table.fields.iter().any(|field| field.name == name);
+
+    match column.split_once('.') {
+        Some((table_name, name)) => {
+            let table = tables
+                .iter()
+                .find(|table| table.name == table_name)
+                .ok_or_else(|| {
+                    name_error(
+                        column,
+                        format!("table '{table_name}' is not part of the query"),
+                    )
+                })?;
+            if has_field(table, name) {
+                Ok(column.to_string())
+            } else {
+                Err(name_error(
+                    column,
+                    format!("field '{name}' not found in table '{table_name}'"),
+                ))
+            }
+        }
+        None => {
+            let owners: Vec<_> = tables
+                .iter()
+                .filter(|table| has_field(table, column))
+                .collect();
+            match owners[..] {
+                [table] => Ok(format!("{}.{column}", table.name)),
+                [] => Err(name_error(column, format!("field '{column}' not found"))),
+                _ => Err(name_error(
+                    column,
+                    format!("field '{column}' is ambiguous, write it as table.{column}"),
+                )),
+            }
+        }
+    }
+}
+
+fn owner(column: &str) -> &str {
+    column.split_once('.').map_or(column, |(table, _)| table)
+}
+
+fn name(column: &str) -> &str {
+    column.split_once('.').map_or(column, |(_, name)| name)
+}
+
+fn rename_where(
+    filter: &Where,
+    rename: &impl Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<Where> {
+    let boxed = |filter: &Where| rename_where(filter, rename).map(Box::new);
+    Ok(match filter {
+        Where::Comparison { column, op, value } => Where::Comparison {
+            column: rename(column)?,
+            op: op.clone(),
+            value: match value {
+                Operand::Column(other) => Operand::Column(rename(other)?),
+                Operand::Value(value) => Operand::Value(value.clone()),
+            },
+        },
+        Where::And(left, right) => Where::And(boxed(left)?, boxed(right)?),
+        Where::Or(left, right) => Where::Or(boxed(left)?, boxed(right)?),
+        Where::Not(inner) => Where::Not(boxed(inner)?),
+        Where::In { column, values } => Where::In {
+            column: rename(column)?,
+            values: values.clone(),
+        },
+        Where::Between { column, low, high } => Where::Between {
+            column: rename(column)?,
+            low: low.clone(),
+            high: high.clone(),
+        },
+        Where::Like { column, pattern } => Where::Like {
+            column: rename(column)?,
+            pattern: pattern.clone(),
+        },
+        Where::IsNull { column } => Where::IsNull {
+            column: rename(column)?,
+        },
+        // The subquery names its own tables.
+        Where::InSelect { column, query } => Where::InSelect {
+            column: rename(column)?,
+            query: query.clone(),
+        },
+        Where::Exists(query) => Where::Exists(query.clone()),
+    })
+}
+
+fn rename_expr(
+    expr: &Expr,
+    rename: &impl Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<Expr> {
+    Ok(match expr {
+        Expr::Column(column) => Expr::Column(rename(column)?),
+        Expr::Binary { op, left, right } => Expr::Binary {
+            op: *op,
+            left: Box::new(rename_expr(left, rename)?),
+            right: Box::new(rename_expr(right, rename)?),
+        },
+        expr => expr.clone(),
+    })
+}
+
+fn conjuncts(filter: &Where) -> Vec<Where> {
+    match filter {
+        Where::And(left, right) => {
+            let mut all = conjuncts(left);
+            all.extend(conjuncts(right));
+            all
+        }
+        filter => vec![filter.clone()],
+    }
+}
+
+fn where_columns<'a>(filter: &'a Where, columns: &mut Vec<&'a str>) {
+    match filter {
+        Where::Comparison { column, value, .. } => {
+            columns.push(column);
+            if let Operand::Column(other) = value {
+                columns.push(other);
+            }
+        }
+        Where::And(left, right) | Where::Or(left, right) => {
+            where_columns(left, columns);
+            where_columns(right, columns);
+        }
+        Where::Not(inner) => where_columns(inner, columns),
+        Where::In { column, .. }
+        | Where::Between { column, .. }
+        | Where::Like { column, .. }
+        | Where::IsNull { column }
+        | Where::InSelect { column, .. } => columns.push(column),
+        Where::Exists(_) => (),
+    }
+}
+
+fn expr_columns(expr: &Expr, columns: &mut Vec<String>) {
+    match expr {
+        Expr::Column(column) => columns.push(column.clone()),
+        Expr::Binary { left, right, .. } => {
+            expr_columns(left, columns);
+            expr_columns(right, columns);
+        }
+        _ => (),
+    }
+}
+
+/// Three-valued evaluation of `filter`, `None` stands for SQL's UNKNOWN.
+fn evaluate(filter: &Where, columns: &[String], row: &[Value]) -> Option<bool> {
+    let value = |column: &str| &row[columns.iter().position(|c| c == column).unwrap()];
+
+    match filter {
+        Where::Comparison {
+            column,
+            op,
+            value: operand,
+        } => {
+            let left = value(column);
+            let right = match operand {
+                Operand::Column(other) => value(other).clone(),
+                Operand::Value(literal) => literal.value(),
+            };
+            if matches!(left, Value::Null) || matches!(right, Value::Null) {
+                return None;
+            }
+            Some(merge::compare(op, left.cmp(&right)))
+        }
+        Where::And(left, right) => {
+            match (evaluate(left, columns, row), evaluate(right, columns, row)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            }
+        }
+        Where::Or(left, right) => {
+            match (evaluate(left, columns, row), evaluate(right, columns, row)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            }
+        }
+        Where::Not(inner) => evaluate(inner, columns, row).map(|matches| !matches),
+        Where::In { column, values } => {
+            let left = value(column);
+            if matches!(left, Value::Null) {
+                None
+            } else {
+                Some(values.iter().any(|right| *left == right.value()))
+            }
+        }
+        Where::Between { column, low, high } => {
+            let left = value(column);
+            if matches!(left, Value::Null) {
+                return None;
+            }
+            Some(low.value() <= *left && *left <= high.value())
+        }
+        Where::Like { column, pattern } => match value(column) {
+            Value::Null => None,
+            Value::Str(text) => {
+                let Literal::Str(pattern) = pattern else {
+                    unreachable!("LIKE takes a string pattern")
+                };
+                let pattern: Vec<char> = pattern.chars().collect();
+                let text: Vec<char> = text.chars().collect();
+                Some(like(&pattern, &text))
+            }
+            _ => Some(false),
+        },
+        Where::IsNull { column } => Some(matches!(value(column), Value::Null)),
+        Where::InSelect { .. } | Where::Exists(_) => {
+            unreachable!("subqueries are resolved before the join")
+        }
+    }
+}
+
+/// Matches `text` with a LIKE pattern, `%` is any run of characters and `_` any single one.
+fn like(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('%', rest)) => (0..=text.len()).any(|skip| like(rest, &text[skip..])),
+        Some(('_', rest)) => !text.is_empty() && like(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && like(rest, &text[1..]),
+    }
+}
+
+/// Evaluates `expr` like the fragments do, NULL operands yield NULL and
+/// dividing by zero yields NULL.
+fn evaluate_expr(expr: &Expr, columns: &[String], row: &[Value]) -> Value {
+    let number = |value: &Value| match value {
+        Value::Int(v) => *v as f64,
+        Value::Float(v) => *v,
+        _ => f64::NAN,
+    };
+
+    match expr {
+        Expr::Column(column) => row[columns.iter().position(|c| c == column).unwrap()].clone(),
+        Expr::Literal(literal) => literal.value(),
+        Expr::Binary { op, left, right } => {
+            let left = evaluate_expr(left, columns, row);
+            let right = evaluate_expr(right, columns, row);
+            match (op, left, right) {
+                (_, Value::Null, _) | (_, _, Value::Null) => Value::Null,
+                (BinaryOp::Concat, Value::Str(left), Value::Str(right)) => {
+                    Value::Str(left + &right)
+                }
+                (BinaryOp::Concat, _, _) => Value::Null,
+                (BinaryOp::Div, left, right) => match number(&right) {
+                    0.0 => Value::Null,
+                    right => Value::Float(number(&left) / right),
+                },
+                (op, Value::Int(left), Value::Int(right)) => Value::Int(match op {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    _ => left.wrapping_mul(right),
+                }),
+                (op, left, right) => Value::Float(match op {
+                    BinaryOp::Add => number(&left) + number(&right),
+                    BinaryOp::Sub => number(&left) - number(&right),
+                    _ => number(&left) * number(&right),
+                }),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Config {
+        toml::from_str(
+            r#"
+            [[tables]]
+            name = "clientes"
+            fields = [
+              { name = "IdCliente", type = "int" },
+              { name = "Nombre", type = "string" },
+              { name = "Deuda", type = "float" },
+            ]
+
+            [[tables]]
+            name = "pedidos"
+            fields = [
+              { name = "IdPedido", type = "int" },
+              { name = "Cliente", type = "int" },
+              { name = "Total", type = "float" },
+            ]
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn plan(sql: &str) -> JoinPlan {
+        JoinPlan::new(&crate::parse_query(sql).unwrap(), &schema()).unwrap()
+    }
+
+    const SQL: &str = "SELECT Nombre, Total FROM clientes JOIN pedidos ON IdCliente = Cliente \
+                       WHERE Deuda > 10 AND Total > Deuda;";
+
+    #[test]
+    fn conditions_on_one_table_go_to_its_scan() {
+        let plan = plan(SQL);
+        let scans: Vec<_> = plan.scans().map(|scan| scan.to_string()).collect();
+        assert_eq!(
+            scans,
+            [
+                "SELECT Nombre, IdCliente, Deuda FROM clientes WHERE Deuda > 10;",
+                "SELECT Total, Cliente FROM pedidos;",
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_restrict_the_next_scan_and_null_never_joins() {
+        let mut plan = plan(SQL);
+        plan.next_scan().unwrap();
+        plan.push_rows(vec![
+            vec![Value::Str("Ana".into()), Value::Int(2), Value::Float(20.0)],
+            vec![Value::Str("Luis".into()), Value::Int(1), Value::Float(30.0)],
+            vec![Value::Str("Eva".into()), Value::Null, Value::Float(50.0)],
+        ]);
+
+        let scan = plan.next_scan().unwrap();
+        assert_eq!(
+            scan.to_string(),
+            "SELECT Total, Cliente FROM pedidos WHERE Cliente IN (1, 2);"
+        );
+        plan.push_rows(vec![
+            vec![Value::Float(25.0), Value::Int(2)],
+            vec![Value::Float(5.0), Value::Int(1)],
+            vec![Value::Float(40.0), Value::Null],
+            vec![Value::Float(99.0), Value::Int(2)],
+        ]);
+
+        // Luis is left out by the condition over both tables.
+        let QueryResult::Select(rows) = plan.finish().unwrap() else {
+            panic!("expected rows");
+        };
+        assert_eq!(
+            rows,
+            [
+                [Value::Str("Ana".into()), Value::Float(25.0)],
+                [Value::Str("Ana".into()), Value::Float(99.0)],
+            ]
+        );
+    }
+
+    #[test]
+    fn an_empty_table_ends_the_join() {
+        let mut plan = plan(SQL);
+        plan.next_scan().unwrap();
+        plan.push_rows(Vec::new());
+
+        assert!(plan.next_scan().is_none());
+        let QueryResult::Select(rows) = plan.finish().unwrap() else {
+            panic!("expected rows");
+        };
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn residual_conditions_are_unknown_over_null() {
+        let columns = ["t.a".to_string(), "t.b".to_string()];
+        let filter = Where::Not(Box::new(Where::Comparison {
+            column: "t.a".into(),
+            op: ">".into(),
+            value: Operand::Column("t.b".into()),
+        }));
+        assert_eq!(
+            evaluate(&filter, &columns, &[Value::Int(1), Value::Null]),
+            None
+        );
+        assert_eq!(
+            evaluate(&filter, &columns, &[Value::Int(1), Value::Int(2)]),
+            Some(true)
+        );
+    }
+}
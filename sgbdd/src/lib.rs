@@ -1,31 +1,59 @@
 #![allow(warnings)]
 
+mod catalog;
 mod config;
 mod connections;
+mod coordinator;
 mod cursor;
 mod databases;
+mod ddl;
+mod diagnostic;
+mod join;
+mod merge;
+mod names;
 mod parser;
+mod prepared;
+mod subquery;
 mod token;
 mod validations;
 
 use std::thread;
 
+use anyhow::anyhow;
+
 use connections::{
     DistributedMongoConnection, DistributedNeo4jConnection, DistributedPgConnection,
 };
 use cursor::Cursor;
+use diagnostic::NameError;
 use mongodb::sync::Client;
 use neo4rs::Graph;
 use parser::Parser;
 use sqlx::{Connection, PgConnection};
 use token::Token;
 
+pub use catalog::show_catalog;
 pub use config::Config;
-pub use connections::{DistributedConnection, QueryMessage, QueryResult, Value};
-pub use parser::{Query, Where};
+pub use connections::{
+    DistributedConnection, Outcome, Phase, QueryMessage, QueryResult, Value, two_phase_commit,
+};
+pub use coordinator::{
+    Route, collect_results, commit, execute, recv_result, route, run_subqueries,
+};
+pub use diagnostic::{Clause, Diagnostic, Span};
+pub use parser::{
+    Aggregate, Alteration, BinaryOp, Catalog, Expr, FragmentDef, Having, Join, Literal, Occurrence,
+    Operand, OrderBy, Query, SelectItem, Statement, Where,
+};
+pub use prepared::Prepared;
 pub use validations::{check_query, validate_config};
 
 pub use databases::{load_next_id, save_next_id};
+pub use ddl::{fragment_ddl, new_schema};
+pub use join::JoinPlan;
+pub use merge::{fragment_query, merge_select, select_headers};
+pub use names::resolve_names;
+pub use subquery::resolve_subqueries;
 
 pub fn spawn_databases(config: &Config) -> Vec<Box<dyn DistributedConnection>> {
     let connections = databases::connections(config);
@@ -49,8 +77,9 @@ pub fn spawn_databases(config: &Config) -> Vec<Box<dyn DistributedConnection>> {
                     .block_on(PgConnection::connect(connection_url))
                     .expect("unable to connect with the PostgreSQL databse");
                 let info = databases::database_info(connection_url, config);
+                let wildcard = databases::wildcard(connection_url, config);
 
-                let db = databases::Postgres::new(conn, info, rt);
+                let db = databases::Postgres::new(conn, info, rt, wildcard);
                 thread::spawn(move || databases::run_database(db, rx));
             }
             "mongo" => {
@@ -75,8 +104,9 @@ pub fn spawn_databases(config: &Config) -> Vec<Box<dyn DistributedConnection>> {
 
                 let db = client.database(db_name);
                 let info = databases::database_info(connection_url, config);
+                let wildcard = databases::wildcard(connection_url, config);
 
-                let db = databases::Mongo::new(client, db, info);
+                let db = databases::Mongo::new(client, db, info, wildcard);
                 thread::spawn(move || databases::run_database(db, rx));
             }
             "neo4j" => {
@@ -119,17 +149,236 @@ pub fn spawn_databases(config: &Config) -> Vec<Box<dyn DistributedConnection>> {
     distributed_connections
 }
 
-fn tokenize(source: &str) -> impl Iterator<Item = anyhow::Result<Token>> {
+fn tokenize(source: &str) -> impl Iterator<Item = Result<(Token, Span), Diagnostic>> {
     let mut cursor = Cursor::new(source);
     std::iter::from_fn(move || match cursor.advance_token() {
-        Ok(Token::Eof) => None,
+        Ok((Token::Eof, _)) => None,
         Ok(token) => Some(Ok(token)),
         Err(e) => Some(Err(e)),
     })
 }
 
 pub fn parse_query(query: &str) -> anyhow::Result<Query> {
-    let tokens: Vec<Token> = tokenize(query).collect::<anyhow::Result<_>>()?;
+    let mut queries = parse_script(query)?;
+    if queries.len() != 1 {
+        return Err(anyhow!("expected one statement, found {}", queries.len()));
+    }
+    Ok(queries.remove(0))
+}
+
+/// Parses the `;` separated statements of `script`, which may span several
+/// lines and hold `--` and `/* */` comments.
+pub fn parse_script(script: &str) -> anyhow::Result<Vec<Query>> {
+    let statements = parse_statements(script)?;
+    Ok(statements
+        .into_iter()
+        .map(|statement| statement.query)
+        .collect())
+}
+
+/// Like [`parse_script`] but keeps where each statement and its names are
+/// written, so later errors can be shown under the source.
+pub fn parse_statements(script: &str) -> Result<Vec<Statement>, Diagnostic> {
+    let tokens = tokenize(script).collect::<Result<_, _>>()?;
     let mut parser = Parser::new(tokens);
-    parser.parse()
+    parser.parse_script()
+}
+
+/// [`check_query`] for a parsed statement. An error caused by a name points
+/// at the place it's written.
+pub fn check_statement(statement: &mut Statement, config: &Config) -> Result<(), Diagnostic> {
+    check_query(&mut statement.query, config).map_err(|e| {
+        let span = e
+            .downcast_ref::<NameError>()
+            .and_then(|error| statement.span_of(&error.name, error.clause))
+            .unwrap_or(statement.span);
+        Diagnostic::new(e, Some(span))
+    })
+}
+
+/// Whether `source` ends with a complete statement, the prompt keeps reading
+/// lines until it does. Input holding only comments has nothing to run.
+pub fn ends_statement(source: &str) -> bool {
+    matches!(
+        tokenize(source).last(),
+        Some(Ok((Token::Semicolon, _))) | None
+    )
+}
+
+/// Parses and checks `query` once, its `$n` or `?` placeholders are bound
+/// with typed values on every run.
+pub fn prepare(query: &str, config: &Config) -> anyhow::Result<Prepared> {
+    let mut query = parse_query(query)?;
+    if !matches!(
+        query,
+        Query::Select { .. } | Query::Insert { .. } | Query::Update { .. } | Query::Delete { .. }
+    ) {
+        return Err(anyhow!(
+            "only SELECT, INSERT, UPDATE and DELETE can be prepared"
+        ));
+    }
+    resolve_names(&mut query, config);
+    validations::check_unbound(&query, config)?;
+    let types = validations::placeholder_types(&query, config)?;
+    Ok(Prepared::new(query, types))
+}
+
+/// Binds `params` to `prepared` and runs it on the fragments like any other
+/// checked query, its writes are committed before returning.
+pub fn execute_prepared(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    prepared: &Prepared,
+    params: &[Value],
+) -> anyhow::Result<QueryResult> {
+    execute(databases, config, &prepared.bind(params)?, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Stands in for the database of a zone: answers every query with
+    /// `answer`, votes yes and keeps the queries it was sent.
+    struct FakeDatabase {
+        conn: String,
+        zone: String,
+        answer: fn(&Query) -> QueryResult,
+        received: Arc<Mutex<Vec<Query>>>,
+    }
+
+    impl DistributedConnection for FakeDatabase {
+        fn execute_query(&self, query: QueryMessage) {
+            self.received.lock().unwrap().push((*query.query).clone());
+            query.tx_result.send((self.answer)(&query.query)).unwrap();
+            thread::spawn(move || {
+                while let Ok(phase) = query.rx_commit.recv() {
+                    match phase {
+                        Phase::Prepare(tx_vote) => {
+                            let _ = tx_vote.send(true);
+                        }
+                        Phase::Commit(tx_done) => {
+                            let _ = tx_done.send(true);
+                            break;
+                        }
+                        Phase::Abort => break,
+                    }
+                }
+            });
+        }
+
+        fn zone(&self) -> &str {
+            &self.zone
+        }
+
+        fn connection(&self) -> &str {
+            &self.conn
+        }
+
+        fn manager(&self) -> &str {
+            "fake"
+        }
+    }
+
+    fn answer(query: &Query) -> QueryResult {
+        match query {
+            Query::Select { .. } => QueryResult::Select(vec![vec![Value::Str("Ana".into())]]),
+            Query::Update { .. } => QueryResult::Update(2),
+            query => panic!("unexpected {query:?}"),
+        }
+    }
+
+    fn fake_databases(
+        config: &Config,
+    ) -> (
+        Vec<Box<dyn DistributedConnection>>,
+        Vec<(String, Arc<Mutex<Vec<Query>>>)>,
+    ) {
+        let mut databases: Vec<Box<dyn DistributedConnection>> = Vec::new();
+        let mut received = Vec::new();
+        for (_, conn, zone) in databases::connections(config) {
+            let queries = Arc::new(Mutex::new(Vec::new()));
+            received.push((zone.to_string(), Arc::clone(&queries)));
+            databases.push(Box::new(FakeDatabase {
+                conn: conn.to_string(),
+                zone: zone.to_string(),
+                answer,
+                received: queries,
+            }));
+        }
+        (databases, received)
+    }
+
+    fn schema() -> Config {
+        toml::from_str(include_str!("../schema.toml")).unwrap()
+    }
+
+    #[test]
+    fn prepared_update_runs_bound_in_the_zone_of_its_state() {
+        let config = schema();
+        let (databases, received) = fake_databases(&config);
+        let prepared = prepare(
+            "UPDATE clientes SET Credito = $1 WHERE Estado = $2;",
+            &config,
+        )
+        .unwrap();
+
+        let params = [Value::Float(10.5), Value::Str("Sonora".into())];
+        let result = execute_prepared(&databases, &config, &prepared, &params).unwrap();
+        assert!(matches!(result, QueryResult::Update(2)));
+
+        for (zone, queries) in received {
+            let queries = queries.lock().unwrap();
+            if zone == "Norte" {
+                assert_eq!(*queries, [prepared.bind(&params).unwrap()]);
+            } else {
+                assert!(queries.is_empty(), "{zone} was sent {queries:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn prepared_select_merges_every_fragment() {
+        let config = schema();
+        let (databases, _) = fake_databases(&config);
+        let prepared = prepare("SELECT Nombre FROM clientes WHERE Credito > ?;", &config).unwrap();
+
+        let result = execute_prepared(&databases, &config, &prepared, &[Value::Int(100)]).unwrap();
+        let QueryResult::Select(rows) = result else {
+            panic!("expected rows, found {result:?}");
+        };
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn prepared_rejects_mistyped_parameters() {
+        let config = schema();
+        let (databases, received) = fake_databases(&config);
+        let prepared = prepare("SELECT Nombre FROM clientes WHERE Credito > $1;", &config).unwrap();
+
+        let params = [Value::Str("many".into())];
+        assert!(execute_prepared(&databases, &config, &prepared, &params).is_err());
+        assert!(
+            received
+                .iter()
+                .all(|(_, queries)| queries.lock().unwrap().is_empty())
+        );
+    }
+
+    #[test]
+    fn errors_point_at_the_clause_that_failed() {
+        let config = schema();
+        let mut statements =
+            parse_statements("SELECT Deuda FROM clientes WHERE Deuda > 'x' ORDER BY Credito;")
+                .unwrap();
+        let error = check_statement(&mut statements[0], &config).unwrap_err();
+        assert_eq!(error.span.map(|span| span.column), Some(34));
+
+        let mut statements =
+            parse_statements("UPDATE clientes SET Deuda = 1 WHERE Deuda = 'x';").unwrap();
+        let error = check_statement(&mut statements[0], &config).unwrap_err();
+        assert_eq!(error.span.map(|span| span.column), Some(37));
+    }
 }
@@ -2,73 +2,20 @@
 
 use anyhow::anyhow;
 use sgbdd::{
-    Config, DistributedConnection, Query, QueryMessage, QueryResult, Where, parse_query,
-    validate_config,
+    Config, DistributedConnection, JoinPlan, Query, QueryMessage, QueryResult, validate_config,
 };
 use std::{
-    collections::HashSet,
     fmt::Display,
     io::Write,
-    sync::{
-        Arc, LazyLock,
-        mpsc::{Receiver, RecvTimeoutError, Sender},
-    },
+    sync::Arc,
+    time::{Duration, Instant},
 };
-pub static NORTH: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    [
-        "Baja California",
-        "Baja California Sur",
-        "Sonora",
-        "Chihuahua",
-        "Coahuila de Zaragoza",
-        "Nuevo León",
-        "Tamaulipas",
-        "Durango",
-        "Sinaloa",
-    ]
-    .into_iter()
-    .collect()
-});
-
-pub static CENTER: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    [
-        "Aguascalientes",
-        "Zacatecas",
-        "San Luis Potosí",
-        "Nayarit",
-        "Jalisco",
-        "Colima",
-        "Michoacán de Ocampo",
-        "Guanajuato",
-        "Querétaro",
-        "Hidalgo",
-        "México",
-        "Ciudad de México",
-        "Tlaxcala",
-        "Puebla",
-        "Morelos",
-    ]
-    .into_iter()
-    .collect()
-});
-
-pub static SOUTH: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
-    [
-        "Guerrero",
-        "Oaxaca",
-        "Chiapas",
-        "Veracruz de Ignacio de la Llave",
-        "Tabasco",
-        "Campeche",
-        "Yucatán",
-        "Quintana Roo",
-    ]
-    .into_iter()
-    .collect()
-});
+
+/// Where the schema is read from, DDL statements write it back.
+const SCHEMA: &str = "schema.toml";
 
 fn main() -> anyhow::Result<()> {
-    let config_file = std::fs::read_to_string("schema.toml")?;
+    let config_file = std::fs::read_to_string(SCHEMA)?;
     let config: Config = toml::from_str(&config_file)?;
 
     if validate_config(&config) {
@@ -87,191 +34,383 @@ fn run(config: Config) {
     run_prompt(databases, config);
 }
 
-fn run_prompt(databases: Vec<Box<dyn DistributedConnection>>, config: Config) {
+fn run_prompt(databases: Vec<Box<dyn DistributedConnection>>, mut config: Config) {
     let stdin = std::io::stdin();
     let mut stdout = std::io::stdout();
     let mut sql = String::new();
+    let mut transaction = false;
 
-    'main_loop: loop {
-        write!(stdout, "> ").unwrap();
+    loop {
+        // A statement continues on the next line until it ends with ';'.
+        let prompt = if sql.is_empty() { "> " } else { "-> " };
+        write!(stdout, "{prompt}").unwrap();
         stdout.flush().unwrap();
-        sql.clear();
-        let query = match stdin.read_line(&mut sql) {
-            Ok(0) => break,
-            Ok(_) => {
-                let query = match parse_query(&sql) {
-                    Ok(query) => Arc::new(query),
-                    Err(message) => {
-                        report(message);
-                        continue;
-                    }
-                };
-                if let Err(message) = sgbdd::check_query(&query, &config) {
-                    report(message);
-                    continue;
-                }
-
-                query
-            }
+        match stdin.read_line(&mut sql) {
+            Ok(0) if sql.trim().is_empty() => break,
+            Ok(0) => (),
+            Ok(_) if !sgbdd::ends_statement(&sql) => continue,
+            Ok(_) => (),
             Err(_) => {
                 report("error");
                 break;
             }
+        }
+
+        let statements = match sgbdd::parse_statements(&sql) {
+            Ok(statements) => statements,
+            Err(diagnostic) => {
+                report(diagnostic.render(&sql));
+                sql.clear();
+                continue;
+            }
         };
 
-        let mut results = Vec::new();
-        let mut rxs_result = Vec::new();
-        let mut txs_commit = Vec::new();
+        // The rest of a script is skipped once a statement fails.
+        for mut statement in statements {
+            if let Err(diagnostic) = sgbdd::check_statement(&mut statement, &config) {
+                report(diagnostic.render(&sql));
+                break;
+            }
+            if let Err(message) =
+                run_statement(&databases, &mut config, &mut transaction, statement.query)
+            {
+                report(message);
+                break;
+            }
+        }
+        sql.clear();
+    }
 
-        execute_query(&databases, query.clone(), &mut rxs_result, &mut txs_commit).unwrap();
+    // What was never committed is undone on exit.
+    if transaction {
+        match broadcast(&databases, Query::Rollback) {
+            Ok(results) => show_result(results, &[]),
+            Err(message) => report(message),
+        }
+    }
+}
 
-        for rx_result in rxs_result {
-            match rx_result.recv_timeout(std::time::Duration::from_secs(5)) {
-                Ok(res) => results.push(res),
-                Err(RecvTimeoutError::Timeout) => {
-                    report("timeout, query canceled");
-                    continue 'main_loop;
-                }
-                Err(RecvTimeoutError::Disconnected) => {
-                    report("failed to execute, query canceled");
-                    continue 'main_loop;
-                }
-            }
+/// Runs a checked statement and shows its result. `transaction` is whether a
+/// BEGIN is open, a statement that fails inside it rolls all of it back.
+fn run_statement(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &mut Config,
+    transaction: &mut bool,
+    query: Query,
+) -> anyhow::Result<()> {
+    match query {
+        Query::Begin if *transaction => return Err(anyhow!("a transaction is already open")),
+        Query::Commit | Query::Rollback if !*transaction => {
+            return Err(anyhow!("no transaction is open"));
+        }
+        Query::Begin => {
+            // The databases that did begin are not left with an open transaction.
+            let results = broadcast(databases, Query::Begin).inspect_err(|_| {
+                let _ = broadcast(databases, Query::Rollback);
+            })?;
+            *transaction = true;
+            show_result(results, &[]);
+            return Ok(());
         }
+        Query::Commit | Query::Rollback => {
+            *transaction = false;
+            let results = broadcast(databases, query)?;
+            show_result(results, &[]);
+            return Ok(());
+        }
+        _ => (),
+    }
+    if !*transaction {
+        return execute_statement(databases, config, query, false);
+    }
 
-        match *query {
-            Query::Select { .. } => (),
-            _ => commit(txs_commit),
+    // The schema is only saved for DDL that committed, and ANALYZE undoes
+    // what it ran, so neither fits in a transaction.
+    if let Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } = query {
+        return Err(anyhow!(
+            "CREATE, ALTER and DROP TABLE can't run inside a transaction"
+        ));
+    }
+    if let Query::Explain { analyze: true, .. } = query {
+        return Err(anyhow!("EXPLAIN ANALYZE can't run inside a transaction"));
+    }
+    execute_statement(databases, config, query, true).map_err(|message| {
+        *transaction = false;
+        match broadcast(databases, Query::Rollback) {
+            Ok(_) => anyhow!("{message}, transaction rolled back"),
+            Err(rollback) => anyhow!("{message}, failed to roll back: {rollback}"),
         }
+    })
+}
+
+/// Sends BEGIN, COMMIT or ROLLBACK to every database, a COMMIT then ends
+/// with two-phase commit.
+fn broadcast(
+    databases: &[Box<dyn DistributedConnection>],
+    query: Query,
+) -> anyhow::Result<Vec<QueryResult>> {
+    let query = Arc::new(query);
+    let mut rxs_result = Vec::new();
+    let mut txs_commit = Vec::new();
+    for db in databases {
+        let (tx_result, rx_result) = std::sync::mpsc::channel();
+        rxs_result.push(rx_result);
+        let (tx_commit, rx_commit) = std::sync::mpsc::channel();
+        txs_commit.push((&**db, tx_commit));
 
-        show_result(results);
+        let query_message = QueryMessage::new(Arc::clone(&query), tx_result, rx_commit);
+        db.execute_query(query_message);
     }
+    let results = sgbdd::collect_results(rxs_result)?;
+
+    if let Query::Commit = *query {
+        sgbdd::commit(txs_commit)?;
+    }
+    Ok(results)
 }
 
-fn execute_query(
+/// Runs a statement other than BEGIN, COMMIT or ROLLBACK. Inside a
+/// `transaction` the databases keep what it changes until COMMIT.
+fn execute_statement(
     databases: &[Box<dyn DistributedConnection>],
-    query: Arc<Query>,
-    rxs_result: &mut Vec<Receiver<QueryResult>>,
-    txs_commit: &mut Vec<Sender<()>>,
+    config: &mut Config,
+    query: Query,
+    transaction: bool,
 ) -> anyhow::Result<()> {
-    match &*query {
-        Query::Insert {
-            table,
-            columns,
-            values,
-        } => {
-            let state_idx = columns.iter().position(|c| c == "Estado").unwrap();
-
-            let mut north = Vec::new();
-            let mut center = Vec::new();
-            let mut south = Vec::new();
-
-            for row in values {
-                let state = &row[state_idx];
-                if NORTH.contains(state.as_str()) {
-                    north.push(row.clone());
-                } else if CENTER.contains(&state.as_str()) {
-                    center.push(row.clone());
-                } else if SOUTH.contains(&state.as_str()) {
-                    south.push(row.clone());
-                } else {
-                    return Err(anyhow!("unknown state '{state}'"));
+    if let Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } = query {
+        return change_schema(databases, config, &query);
+    }
+
+    if let Query::Catalog(catalog) = &query {
+        let (headers, rows) = sgbdd::show_catalog(catalog, config);
+        show_result(vec![QueryResult::Select(rows)], &headers);
+        return Ok(());
+    }
+    if let Query::Explain { query, analyze } = query {
+        return explain(databases, config, &query, analyze);
+    }
+
+    let result = sgbdd::execute(databases, config, &query, transaction)?;
+    let headers = match query {
+        Query::Select { .. } => sgbdd::select_headers(&query, config)?,
+        _ => Vec::new(),
+    };
+    show_result(vec![result], &headers);
+    Ok(())
+}
+
+/// Changes every fragment of a table in its database, the schema is saved
+/// once all of them committed.
+fn change_schema(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &mut Config,
+    query: &Query,
+) -> anyhow::Result<()> {
+    let schema = sgbdd::new_schema(query, config)?;
+
+    let mut rxs_result = Vec::new();
+    let mut txs_commit = Vec::new();
+    for db in databases {
+        let Some(query) = sgbdd::fragment_ddl(query, config, &schema, db.connection()) else {
+            continue;
+        };
+        let query = Arc::new(query);
+        let (tx_result, rx_result) = std::sync::mpsc::channel();
+        rxs_result.push(rx_result);
+        let (tx_commit, rx_commit) = std::sync::mpsc::channel();
+        txs_commit.push((&**db, tx_commit));
+
+        let query_message = QueryMessage::new(query, tx_result, rx_commit);
+        db.execute_query(query_message);
+    }
+    let results = sgbdd::collect_results(rxs_result)?;
+
+    // When some fragments are in doubt the others already changed, which the
+    // saved schema no longer describes.
+    sgbdd::commit(txs_commit).map_err(|e| anyhow!("{e}, {SCHEMA} was left unchanged"))?;
+    *config = schema;
+    std::fs::write(SCHEMA, toml::to_string(config)?)?;
+    show_result(results, &[]);
+    Ok(())
+}
+
+/// What EXPLAIN shows of a fragment that runs the query, ANALYZE adds the
+/// rows it returned or changed and how long it took.
+struct Step {
+    table: String,
+    zone: String,
+    fragment: String,
+    manager: String,
+    route: sgbdd::Route,
+    statement: String,
+    analyzed: Option<(u64, Duration)>,
+}
+
+/// Shows the fragments `query` is sent to and the statement each one runs.
+/// ANALYZE also runs it, and rolls back whatever it changed.
+fn explain(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    query: &Query,
+    analyze: bool,
+) -> anyhow::Result<()> {
+    // The statements hold the rows of the subqueries, so these always run.
+    let Some(query) = sgbdd::run_subqueries(databases, config, query)? else {
+        println!("\nthe filter never matches, no fragment runs the query\n");
+        return Ok(());
+    };
+    let query = Arc::new(query);
+
+    let mut steps = Vec::new();
+    if matches!(&*query, Query::Select { joins, .. } if !joins.is_empty()) {
+        let mut plan = JoinPlan::new(&query, config)?;
+        if analyze {
+            while let Some(scan) = plan.next_scan() {
+                let results = explain_scan(databases, config, scan.clone(), true, &mut steps)?;
+                match sgbdd::merge_select(&scan, config, results)? {
+                    QueryResult::Select(rows) => plan.push_rows(rows),
+                    result => return Err(anyhow!("expected rows, found {result:?}")),
                 }
             }
-            for db in databases {
-                let zone = db.zone();
-                let query = match zone {
-                    "Norte" => Query::Insert {
-                        table: table.clone(),
-                        columns: columns.clone(),
-                        values: north.clone(),
-                    },
-                    "Centro" => Query::Insert {
-                        table: table.clone(),
-                        columns: columns.clone(),
-                        values: center.clone(),
-                    },
-                    "Sur" => Query::Insert {
-                        table: table.clone(),
-                        columns: columns.clone(),
-                        values: south.clone(),
-                    },
-                    zone => return Err(anyhow!("unknown zone '{zone}'")),
-                };
-                let query = Arc::new(query);
-                let (tx_result, rx_result) = std::sync::mpsc::channel();
-                rxs_result.push(rx_result);
-                let (tx_commit, rx_commit) = std::sync::mpsc::channel();
-                txs_commit.push(tx_commit);
-
-                let query_message = QueryMessage::new(query, tx_result, rx_commit);
-
-                db.execute_query(query_message);
-            }
-        }
-        Query::Select { zones, filter, .. }
-        | Query::Update { zones, filter, .. }
-        | Query::Delete { zones, filter, .. } => {
-            let dbs = select_databases(databases, zones, filter)?;
-            for db in dbs {
-                let query = Arc::clone(&query);
-
-                let (tx_result, rx_result) = std::sync::mpsc::channel();
-                rxs_result.push(rx_result);
-                let (tx_commit, rx_commit) = std::sync::mpsc::channel();
-                txs_commit.push(tx_commit);
-
-                let query_message = QueryMessage::new(query, tx_result, rx_commit);
-                db.execute_query(query_message);
+        } else {
+            // Without rows the later scans are shown before the join keys restrict them.
+            for scan in plan.scans() {
+                explain_scan(databases, config, scan, false, &mut steps)?;
             }
         }
+    } else {
+        explain_scan(databases, config, query, analyze, &mut steps)?;
     }
 
+    show_plan(&steps);
     Ok(())
 }
 
-fn select_databases<'a>(
-    databases: &'a [Box<dyn DistributedConnection>],
-    zones: &Option<Vec<String>>,
-    filter: &Option<Where>,
-) -> anyhow::Result<Vec<&'a Box<dyn DistributedConnection>>> {
-    if let Some(zones) = zones {
-        Ok(databases
-            .iter()
-            .filter(|db| zones.contains(&db.zone().to_string()))
-            .collect())
-    } else if let Some(filter) = filter {
-        if filter.column == "Estado" && filter.op == "=" {
-            let state = &filter.value;
-            let zone = if NORTH.contains(state.as_str()) {
-                "Norte"
-            } else if CENTER.contains(state.as_str()) {
-                "Centro"
-            } else if SOUTH.contains(state.as_str()) {
-                "Sur"
-            } else {
-                return Err(anyhow::anyhow!("unknown state '{state}'"));
-            };
-            Ok(databases.iter().filter(|db| db.zone() == zone).collect())
-        } else {
-            Ok(databases.iter().collect())
-        }
-    } else {
-        Ok(databases.iter().collect())
+/// Explains a query over a single table, with `analyze` the results of its
+/// fragments are returned to be merged.
+fn explain_scan(
+    databases: &[Box<dyn DistributedConnection>],
+    config: &Config,
+    query: Arc<Query>,
+    analyze: bool,
+    steps: &mut Vec<Step>,
+) -> anyhow::Result<Vec<QueryResult>> {
+    let (Query::Select { table, .. }
+    | Query::Insert { table, .. }
+    | Query::Update { table, .. }
+    | Query::Delete { table, .. }) = &*query
+    else {
+        unreachable!("only SELECT, INSERT, UPDATE and DELETE are explained")
+    };
+
+    let routes = sgbdd::route(databases, config, query.clone())?;
+    let first = steps.len();
+    for (db, query, route) in &routes {
+        let explain = Arc::new(Query::Explain {
+            query: Box::new((**query).clone()),
+            analyze: false,
+        });
+        let (tx_result, rx_result) = std::sync::mpsc::channel();
+        let (_tx_commit, rx_commit) = std::sync::mpsc::channel();
+        db.execute_query(QueryMessage::new(explain, tx_result, rx_commit));
+        let statement = match sgbdd::recv_result(&rx_result)? {
+            QueryResult::Explain(statement) => statement,
+            result => return Err(anyhow!("expected a statement, found {result:?}")),
+        };
+
+        let (fragment, manager) = config
+            .fragment_of(table, db.connection())
+            .unwrap_or_default();
+        steps.push(Step {
+            table: table.clone(),
+            zone: db.zone().to_string(),
+            fragment: fragment.to_string(),
+            manager: manager.to_string(),
+            route: *route,
+            statement,
+            analyzed: None,
+        });
+    }
+    if !analyze {
+        return Ok(Vec::new());
+    }
+
+    let mut rxs_result = Vec::new();
+    let mut txs_commit = Vec::new();
+    let start = Instant::now();
+    for (db, query, _) in routes {
+        let (tx_result, rx_result) = std::sync::mpsc::channel();
+        rxs_result.push(rx_result);
+        let (tx_commit, rx_commit) = std::sync::mpsc::channel();
+        txs_commit.push(tx_commit);
+
+        db.execute_query(QueryMessage::new(query, tx_result, rx_commit));
+    }
+    // Every fragment is waited for on its own thread, so a slow one doesn't
+    // add to the time of the others.
+    let timed: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = rxs_result
+            .into_iter()
+            .map(|rx_result| {
+                scope
+                    .spawn(move || sgbdd::recv_result(&rx_result).map(|res| (res, start.elapsed())))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+    // Dropping the commit senders rolls every fragment back.
+    drop(txs_commit);
+
+    let mut results = Vec::new();
+    for (step, timed) in steps[first..].iter_mut().zip(timed) {
+        let (result, elapsed) = timed?;
+        step.analyzed = Some((row_count(&result), elapsed));
+        results.push(result);
+    }
+
+    Ok(results)
+}
+
+/// Rows a fragment returned or changed.
+fn row_count(result: &QueryResult) -> u64 {
+    match result {
+        QueryResult::Select(rows) => rows.len() as u64,
+        QueryResult::Insert(n) | QueryResult::Update(n) | QueryResult::Delete(n) => *n,
+        _ => 0,
     }
 }
 
-fn commit(txs_commit: Vec<Sender<()>>) {
-    for tx_commit in txs_commit {
-        tx_commit.send(()).unwrap();
+fn show_plan(steps: &[Step]) {
+    if steps.is_empty() {
+        println!("\nno fragment runs the query\n");
+        return;
+    }
+
+    println!();
+    for step in steps {
+        println!(
+            "{} in zone {}: {} ({}), {}",
+            step.table, step.zone, step.fragment, step.manager, step.route
+        );
+        println!("    {}", step.statement);
+        if let Some((rows, elapsed)) = step.analyzed {
+            println!(
+                "    rows: {rows}, time: {:.3} ms",
+                elapsed.as_secs_f64() * 1000.0
+            );
+        }
     }
+    println!();
 }
 
 fn report(message: impl Display) {
     println!("\n{message}\n")
 }
 
-fn show_result(result: Vec<QueryResult>) {
+fn show_result(result: Vec<QueryResult>, headers: &[String]) {
     use std::fmt::Write;
 
     if result.is_empty() {
@@ -280,6 +419,7 @@ fn show_result(result: Vec<QueryResult>) {
 
     match &result[0] {
         QueryResult::Select(_) => {
+            println!("{}", headers.join(","));
             for res in result {
                 if let QueryResult::Select(rows) = res {
                     let mut printable = String::new();
@@ -319,5 +459,12 @@ fn show_result(result: Vec<QueryResult>) {
                 .sum();
             println!("\nrows deleted: {total}\n");
         }
+        QueryResult::CreateTable => println!("\ntable created\n"),
+        QueryResult::AlterTable => println!("\ntable altered\n"),
+        QueryResult::DropTable => println!("\ntable dropped\n"),
+        QueryResult::Explain(_) => unreachable!("EXPLAIN is shown by show_plan"),
+        QueryResult::Begin => println!("\ntransaction started\n"),
+        QueryResult::Commit => println!("\ntransaction committed\n"),
+        QueryResult::Rollback => println!("\ntransaction rolled back\n"),
     }
 }
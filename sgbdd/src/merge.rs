@@ -0,0 +1,631 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    sync::Arc,
+};
+
+use anyhow::anyhow;
+
+use super::{
+    Aggregate, Config, Having, OrderBy, Query, QueryResult, SelectItem, Value, databases, join,
+};
+
+/// The query each fragment runs for `query`.
+///
+/// OFFSET is applied once after the merge, so a fragment has to return the
+/// first `limit + offset` rows. Grouped queries return every partial group
+/// instead, with AVG split into SUM and COUNT and the columns HAVING needs,
+/// and leave DISTINCT to the coordinator.
+pub fn fragment_query(query: Arc<Query>) -> Arc<Query> {
+    let Query::Select {
+        fields,
+        group_by,
+        having,
+        offset,
+        ..
+    } = &*query
+    else {
+        return query;
+    };
+    let grouped = is_grouped(fields, group_by, having);
+    if !grouped && offset.is_none() {
+        return query;
+    }
+
+    let partial_fields = split_avg(&merge_items(fields, group_by, having.as_ref()));
+    let mut fragment = (*query).clone();
+    if let Query::Select {
+        fields,
+        distinct,
+        having,
+        order_by,
+        limit,
+        offset,
+        ..
+    } = &mut fragment
+    {
+        if grouped {
+            *fields = partial_fields;
+            *distinct = false;
+            *having = None;
+            order_by.clear();
+            *limit = None;
+        } else {
+            *limit = limit.map(|limit| limit + offset.unwrap_or(0));
+        }
+        *offset = None;
+    }
+
+    Arc::new(fragment)
+}
+
+/// Combines the rows returned by every fragment of a SELECT into one result.
+///
+/// Fragments already sort their rows, so an ORDER BY only needs a k-way merge.
+/// Grouped queries arrive as partial groups, built by [`fragment_query`], that
+/// are merged before HAVING is applied.
+pub fn merge_select(
+    query: &Query,
+    config: &Config,
+    results: Vec<QueryResult>,
+) -> anyhow::Result<QueryResult> {
+    let Query::Select {
+        table,
+        fields,
+        distinct,
+        group_by,
+        having,
+        order_by,
+        limit,
+        offset,
+        ..
+    } = query
+    else {
+        return Err(anyhow!("expected a SELECT query"));
+    };
+
+    let streams = results
+        .into_iter()
+        .map(|result| match result {
+            QueryResult::Select(rows) => Ok(rows),
+            result => Err(anyhow!("expected rows, found {result:?}")),
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let rows = if is_grouped(fields, group_by, having) {
+        let mut rows = group(fields, group_by, having.as_ref(), streams);
+        if !order_by.is_empty() {
+            let keys = order_keys(table, fields, order_by, config)?;
+            rows.sort_by(|left, right| compare_rows(left, right, &keys));
+        }
+        rows
+    } else if order_by.is_empty() {
+        streams.into_iter().flatten().collect()
+    } else {
+        let keys = order_keys(table, fields, order_by, config)?;
+        merge_sorted(streams, &keys)
+    };
+
+    // Fragments deduplicate their own rows, the same row can still come from several.
+    let rows = if *distinct {
+        let mut seen = HashSet::new();
+        rows.into_iter()
+            .filter(|row| seen.insert(row.clone()))
+            .collect()
+    } else {
+        rows
+    };
+
+    // Fragments only got LIMIT n+m, the global cut happens once everything is merged.
+    let rows = rows
+        .into_iter()
+        .skip(offset.unwrap_or(0) as usize)
+        .take(limit.map_or(usize::MAX, |limit| limit as usize))
+        .collect();
+
+    Ok(QueryResult::Select(rows))
+}
+
+fn is_grouped(fields: &[SelectItem], group_by: &[String], having: &Option<Having>) -> bool {
+    !group_by.is_empty()
+        || having.is_some()
+        || fields
+            .iter()
+            .any(|item| matches!(item, SelectItem::Aggregate { .. }))
+}
+
+fn is_avg(item: &SelectItem) -> bool {
+    matches!(
+        item,
+        SelectItem::Aggregate {
+            function: Aggregate::Avg,
+            ..
+        }
+    )
+}
+
+/// The select list followed by the group columns and HAVING aggregates that
+/// weren't selected, everything the coordinator needs from a partial group.
+fn merge_items(
+    fields: &[SelectItem],
+    group_by: &[String],
+    having: Option<&Having>,
+) -> Vec<SelectItem> {
+    fn having_items(having: &Having, items: &mut Vec<SelectItem>) {
+        match having {
+            Having::Comparison { item, .. } => {
+                if !items.contains(item) {
+                    items.push(item.clone());
+                }
+            }
+            Having::And(left, right) | Having::Or(left, right) => {
+                having_items(left, items);
+                having_items(right, items);
+            }
+            Having::Not(inner) => having_items(inner, items),
+        }
+    }
+
+    let mut items = fields.to_vec();
+    for column in group_by {
+        let item = SelectItem::Column(column.clone());
+        if !items.contains(&item) {
+            items.push(item);
+        }
+    }
+    if let Some(having) = having {
+        having_items(having, &mut items);
+    }
+
+    items
+}
+
+fn split_avg(items: &[SelectItem]) -> Vec<SelectItem> {
+    items
+        .iter()
+        .flat_map(|item| match item {
+            SelectItem::Aggregate {
+                function: Aggregate::Avg,
+                column,
+            } => vec![
+                SelectItem::Aggregate {
+                    function: Aggregate::Sum,
+                    column: column.clone(),
+                },
+                SelectItem::Aggregate {
+                    function: Aggregate::Count,
+                    column: column.clone(),
+                },
+            ],
+            item => vec![item.clone()],
+        })
+        .collect()
+}
+
+/// Merges the partial groups of every fragment, then applies HAVING.
+fn group(
+    fields: &[SelectItem],
+    group_by: &[String],
+    having: Option<&Having>,
+    streams: Vec<Vec<Vec<Value>>>,
+) -> Vec<Vec<Value>> {
+    let items = merge_items(fields, group_by, having);
+
+    // Where every item starts in a partial row, AVG takes two columns.
+    let positions: Vec<usize> = items
+        .iter()
+        .scan(0, |next, item| {
+            let position = *next;
+            *next += if is_avg(item) { 2 } else { 1 };
+            Some(position)
+        })
+        .collect();
+    let keys: Vec<(usize, bool)> = group_by
+        .iter()
+        .map(|column| {
+            let idx = items
+                .iter()
+                .position(|item| matches!(item, SelectItem::Column(c) if c == column))
+                .unwrap();
+            (positions[idx], false)
+        })
+        .collect();
+
+    let mut partials: Vec<_> = streams.into_iter().flatten().collect();
+    partials.sort_by(|left, right| compare_rows(left, right, &keys));
+
+    // Without GROUP BY the whole table is one group, even when it is empty.
+    let groups: Vec<&[Vec<Value>]> = if group_by.is_empty() {
+        vec![&partials]
+    } else {
+        partials
+            .chunk_by(|left, right| compare_rows(left, right, &keys).is_eq())
+            .collect()
+    };
+
+    groups
+        .into_iter()
+        .filter_map(|partials| {
+            let mut row = combine(&items, &positions, partials);
+            // Groups where HAVING is UNKNOWN are dropped like the false ones.
+            if having.is_some_and(|having| matches_having(having, &items, &row) != Some(true)) {
+                return None;
+            }
+            row.truncate(fields.len());
+            Some(row)
+        })
+        .collect()
+}
+
+/// Folds the partial rows of one group into its final row.
+fn combine(items: &[SelectItem], positions: &[usize], partials: &[Vec<Value>]) -> Vec<Value> {
+    items
+        .iter()
+        .zip(positions)
+        .map(|(item, &position)| {
+            let column = |position: usize| partials.iter().map(move |row| &row[position]);
+            match item {
+                // Group columns, and expressions over them, are the same in every partial row.
+                SelectItem::Column(_) | SelectItem::Expression(_) => partials
+                    .first()
+                    .map_or(Value::Null, |row| row[position].clone()),
+                SelectItem::Aggregate {
+                    function: Aggregate::Avg,
+                    ..
+                } => {
+                    let sum = fold(Aggregate::Sum, column(position));
+                    let count = fold(Aggregate::Count, column(position + 1));
+                    match (sum, count) {
+                        (_, Value::Int(0)) => Value::Null,
+                        (Value::Int(sum), Value::Int(count)) => {
+                            Value::Float(sum as f64 / count as f64)
+                        }
+                        (Value::Float(sum), Value::Int(count)) => Value::Float(sum / count as f64),
+                        _ => Value::Null,
+                    }
+                }
+                SelectItem::Aggregate { function, .. } => fold(*function, column(position)),
+                SelectItem::Wildcard => unreachable!("'*' is not allowed next to aggregates"),
+            }
+        })
+        .collect()
+}
+
+/// Three-valued evaluation of `having` over a merged group, `None` stands
+/// for SQL's UNKNOWN.
+fn matches_having(having: &Having, items: &[SelectItem], row: &[Value]) -> Option<bool> {
+    match having {
+        Having::Comparison { item, op, value } => {
+            let left = &row[items.iter().position(|i| i == item).unwrap()];
+            let right = value.value();
+            if matches!(left, Value::Null) || matches!(right, Value::Null) {
+                return None;
+            }
+            Some(compare(op, left.cmp(&right)))
+        }
+        Having::And(left, right) => match (
+            matches_having(left, items, row),
+            matches_having(right, items, row),
+        ) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Having::Or(left, right) => match (
+            matches_having(left, items, row),
+            matches_having(right, items, row),
+        ) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Having::Not(inner) => matches_having(inner, items, row).map(|matches| !matches),
+    }
+}
+
+pub(crate) fn compare(op: &str, ordering: Ordering) -> bool {
+    match op {
+        "=" => ordering.is_eq(),
+        "!=" => ordering.is_ne(),
+        "<" => ordering.is_lt(),
+        ">" => ordering.is_gt(),
+        "<=" => ordering.is_le(),
+        ">=" => ordering.is_ge(),
+        op => unreachable!("unknown operator '{op}'"),
+    }
+}
+
+fn fold<'a>(function: Aggregate, values: impl Iterator<Item = &'a Value>) -> Value {
+    let values = values.filter(|value| !matches!(value, Value::Null));
+    match function {
+        Aggregate::Count => Value::Int(
+            values
+                .map(|value| match value {
+                    Value::Int(count) => *count,
+                    _ => 0,
+                })
+                .sum(),
+        ),
+        Aggregate::Sum | Aggregate::Avg => {
+            values.fold(Value::Null, |acc, value| match (acc, value) {
+                (Value::Null, value) => value.clone(),
+                // A sum past the range of i64 carries on as a float instead of panicking.
+                (Value::Int(acc), Value::Int(v)) => acc
+                    .checked_add(*v)
+                    .map_or(Value::Float(acc as f64 + *v as f64), Value::Int),
+                (Value::Int(acc), Value::Float(v)) => Value::Float(acc as f64 + v),
+                (Value::Float(acc), Value::Int(v)) => Value::Float(acc + *v as f64),
+                (Value::Float(acc), Value::Float(v)) => Value::Float(acc + v),
+                (acc, _) => acc,
+            })
+        }
+        Aggregate::Min => values.min().cloned().unwrap_or(Value::Null),
+        Aggregate::Max => values.max().cloned().unwrap_or(Value::Null),
+    }
+}
+
+fn order_keys(
+    table: &str,
+    fields: &[SelectItem],
+    order_by: &[OrderBy],
+    config: &Config,
+) -> anyhow::Result<Vec<(usize, bool)>> {
+    let columns = columns(table, fields, config)?;
+    order_by
+        .iter()
+        .map(|OrderBy { column, descending }| {
+            columns
+                .iter()
+                .position(|item| matches!(item, SelectItem::Column(c) if c == column))
+                .map(|idx| (idx, *descending))
+                .ok_or_else(|| anyhow!("order by field '{column}' not in the result"))
+        })
+        .collect()
+}
+
+/// Column headers of a SELECT result, aliases replace the text of the item.
+pub fn select_headers(query: &Query, config: &Config) -> anyhow::Result<Vec<String>> {
+    let Query::Select {
+        table,
+        joins,
+        fields,
+        aliases,
+        ..
+    } = query
+    else {
+        return Err(anyhow!("expected a SELECT query"));
+    };
+
+    let mut headers = Vec::new();
+    for (item, alias) in fields.iter().zip(aliases) {
+        match (item, alias) {
+            (_, Some(alias)) => headers.push(alias.clone()),
+            // Over a join every column is written as `table.column`.
+            (SelectItem::Wildcard, None) if !joins.is_empty() => headers.extend(
+                join::joined_table(table, joins, config)?
+                    .fields
+                    .into_iter()
+                    .map(|field| field.name),
+            ),
+            (SelectItem::Wildcard, None) => headers.extend(
+                columns(table, &[SelectItem::Wildcard], config)?
+                    .iter()
+                    .map(ToString::to_string),
+            ),
+            (SelectItem::Column(column), None) => headers.push(column.clone()),
+            (item, None) => headers.push(item.to_string()),
+        }
+    }
+
+    Ok(headers)
+}
+
+fn columns(table: &str, fields: &[SelectItem], config: &Config) -> anyhow::Result<Vec<SelectItem>> {
+    let table = config
+        .tables
+        .iter()
+        .find(|t| t.name == table)
+        .ok_or_else(|| anyhow!("table '{table}' not found"))?;
+    let wildcard: Vec<_> = table
+        .fields
+        .iter()
+        .map(|field| field.name.clone())
+        .collect();
+
+    Ok(databases::select_items(fields, &wildcard))
+}
+
+struct Head<'a> {
+    row: Vec<Value>,
+    stream: usize,
+    keys: &'a [(usize, bool)],
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap is a max-heap, so the comparison is reversed to pop the smallest row.
+        compare_rows(&other.row, &self.row, self.keys).then(other.stream.cmp(&self.stream))
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+fn merge_sorted(streams: Vec<Vec<Vec<Value>>>, keys: &[(usize, bool)]) -> Vec<Vec<Value>> {
+    let total = streams.iter().map(Vec::len).sum();
+    let mut streams: Vec<_> = streams.into_iter().map(Vec::into_iter).collect();
+
+    let mut heap = BinaryHeap::with_capacity(streams.len());
+    for (stream, rows) in streams.iter_mut().enumerate() {
+        if let Some(row) = rows.next() {
+            heap.push(Head { row, stream, keys });
+        }
+    }
+
+    let mut merged = Vec::with_capacity(total);
+    while let Some(Head { row, stream, .. }) = heap.pop() {
+        merged.push(row);
+        if let Some(row) = streams[stream].next() {
+            heap.push(Head { row, stream, keys });
+        }
+    }
+
+    merged
+}
+
+pub(crate) fn compare_rows(left: &[Value], right: &[Value], keys: &[(usize, bool)]) -> Ordering {
+    keys.iter()
+        .map(|&(idx, descending)| {
+            let ordering = left[idx].cmp(&right[idx]);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Config {
+        toml::from_str(include_str!("../schema.toml")).unwrap()
+    }
+
+    fn merge(sql: &str, fragments: Vec<Vec<Vec<Value>>>) -> Vec<Vec<Value>> {
+        let query = crate::parse_query(sql).unwrap();
+        let results = fragments.into_iter().map(QueryResult::Select).collect();
+        match merge_select(&query, &schema(), results).unwrap() {
+            QueryResult::Select(rows) => rows,
+            result => panic!("expected rows, found {result:?}"),
+        }
+    }
+
+    #[test]
+    fn avg_is_rebuilt_from_sum_and_count() {
+        let sql = "SELECT Estado, AVG(Deuda) FROM clientes GROUP BY Estado;";
+        let fragment = fragment_query(Arc::new(crate::parse_query(sql).unwrap()));
+        let Query::Select { fields, .. } = &*fragment else {
+            panic!("expected a SELECT, found {fragment:?}");
+        };
+        assert_eq!(
+            fields.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            ["Estado", "SUM(Deuda)", "COUNT(Deuda)"]
+        );
+
+        // One debt of 10 in a fragment and four adding up to 50 in the
+        // other average 12, not the 11.25 of averaging both averages.
+        let rows = merge(
+            sql,
+            vec![
+                vec![vec![
+                    Value::Str("Sonora".into()),
+                    Value::Float(10.0),
+                    Value::Int(1),
+                ]],
+                vec![vec![
+                    Value::Str("Sonora".into()),
+                    Value::Float(50.0),
+                    Value::Int(4),
+                ]],
+            ],
+        );
+        assert_eq!(rows, [[Value::Str("Sonora".into()), Value::Float(12.0)]]);
+    }
+
+    #[test]
+    fn avg_of_only_nulls_is_null() {
+        let rows = merge(
+            "SELECT AVG(Deuda) FROM clientes;",
+            vec![
+                vec![vec![Value::Null, Value::Int(0)]],
+                vec![vec![Value::Null, Value::Int(0)]],
+            ],
+        );
+        assert_eq!(rows, [[Value::Null]]);
+    }
+
+    #[test]
+    fn integer_sums_past_i64_become_floats() {
+        let rows = merge(
+            "SELECT SUM(IdCliente) FROM clientes;",
+            vec![vec![vec![Value::Int(i64::MAX)]], vec![vec![Value::Int(1)]]],
+        );
+        assert_eq!(rows, [[Value::Float(i64::MAX as f64 + 1.0)]]);
+    }
+
+    #[test]
+    fn a_group_in_several_fragments_is_merged() {
+        let state = |name: &str| Value::Str(name.into());
+        let rows = merge(
+            "SELECT Estado, SUM(Deuda), COUNT(*) FROM clientes GROUP BY Estado ORDER BY Estado;",
+            vec![
+                vec![
+                    vec![state("Jalisco"), Value::Float(5.0), Value::Int(1)],
+                    vec![state("Sonora"), Value::Float(10.0), Value::Int(2)],
+                ],
+                vec![vec![state("Sonora"), Value::Float(20.0), Value::Int(3)]],
+            ],
+        );
+        assert_eq!(
+            rows,
+            [
+                [state("Jalisco"), Value::Float(5.0), Value::Int(1)],
+                [state("Sonora"), Value::Float(30.0), Value::Int(5)],
+            ]
+        );
+    }
+
+    #[test]
+    fn having_drops_unknown_groups() {
+        let state = |name: &str| Value::Str(name.into());
+        // NOT of a comparison with a NULL sum is still UNKNOWN.
+        let rows = merge(
+            "SELECT Estado, SUM(Deuda) FROM clientes GROUP BY Estado HAVING NOT SUM(Deuda) > 100;",
+            vec![
+                vec![vec![state("Jalisco"), Value::Null]],
+                vec![vec![state("Sonora"), Value::Float(50.0)]],
+            ],
+        );
+        assert_eq!(rows, [[state("Sonora"), Value::Float(50.0)]]);
+    }
+
+    #[test]
+    fn distinct_removes_rows_repeated_across_fragments() {
+        let state = |name: &str| Value::Str(name.into());
+        let rows = merge(
+            "SELECT DISTINCT Estado FROM clientes ORDER BY Estado;",
+            vec![
+                vec![vec![state("Jalisco")], vec![state("Sonora")]],
+                vec![vec![state("Chiapas")], vec![state("Sonora")]],
+            ],
+        );
+        assert_eq!(
+            rows,
+            [[state("Chiapas")], [state("Jalisco")], [state("Sonora")]]
+        );
+    }
+
+    #[test]
+    fn distinct_treats_equal_ints_and_floats_as_one_row() {
+        let rows = merge(
+            "SELECT DISTINCT Deuda FROM clientes;",
+            vec![vec![vec![Value::Int(3)]], vec![vec![Value::Float(3.0)]]],
+        );
+        assert_eq!(rows.len(), 1);
+    }
+}
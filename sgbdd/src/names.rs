@@ -0,0 +1,212 @@
+//! Table and column names are case-insensitive, quoted or not, so they are
+//! rewritten to the spelling of the schema before a query is checked. Quotes
+//! only let a name hold spaces, punctuation or keywords.
+
+use std::iter;
+
+use crate::{
+    Alteration, Catalog, Expr, Having, Operand, Query, SelectItem, Where,
+    config::{Config, Table},
+    ddl::REQUIRED_COLUMNS,
+};
+
+/// Writes every name of `query` the way the schema does, unknown names are
+/// left as they are for the checks to report.
+pub fn resolve_names(query: &mut Query, config: &Config) {
+    match query {
+        Query::Select {
+            table,
+            joins,
+            fields,
+            filter,
+            group_by,
+            having,
+            order_by,
+            ..
+        } => {
+            let tables: Vec<_> = iter::once(table)
+                .chain(joins.iter_mut().map(|join| &mut join.table))
+                .filter_map(|name| resolve_table(name, config))
+                .collect();
+
+            for join in joins.iter_mut() {
+                resolve_column(&mut join.left, &tables);
+                resolve_column(&mut join.right, &tables);
+            }
+            for item in fields {
+                resolve_item(item, &tables);
+            }
+            if let Some(filter) = filter {
+                resolve_where(filter, &tables, config);
+            }
+            for column in group_by {
+                resolve_column(column, &tables);
+            }
+            if let Some(having) = having {
+                resolve_having(having, &tables);
+            }
+            for order in order_by {
+                resolve_column(&mut order.column, &tables);
+            }
+        }
+        Query::Insert { table, columns, .. } => {
+            let tables: Vec<_> = resolve_table(table, config).into_iter().collect();
+            for column in columns {
+                resolve_column(column, &tables);
+            }
+        }
+        Query::Update {
+            table,
+            assignments,
+            filter,
+            ..
+        } => {
+            let tables: Vec<_> = resolve_table(table, config).into_iter().collect();
+            for (column, value) in assignments {
+                resolve_column(column, &tables);
+                resolve_expr(value, &tables);
+            }
+            if let Some(filter) = filter {
+                resolve_where(filter, &tables, config);
+            }
+        }
+        Query::Delete { table, filter, .. } => {
+            let tables: Vec<_> = resolve_table(table, config).into_iter().collect();
+            if let Some(filter) = filter {
+                resolve_where(filter, &tables, config);
+            }
+        }
+        // A new table spells its own columns, except the ones every table has.
+        Query::CreateTable {
+            columns, fragments, ..
+        } => {
+            for (column, _) in columns.iter_mut() {
+                if let Some((name, _)) = REQUIRED_COLUMNS
+                    .iter()
+                    .find(|(name, _)| same_name(name, column))
+                {
+                    *column = name.to_string();
+                }
+            }
+            for (column, _) in fragments.iter_mut().flat_map(|f| &mut f.columns) {
+                if let Some((name, _)) = columns.iter().find(|(name, _)| same_name(name, column)) {
+                    column.clone_from(name);
+                }
+            }
+        }
+        Query::AlterTable { table, alteration } => {
+            let tables: Vec<_> = resolve_table(table, config).into_iter().collect();
+            match alteration {
+                Alteration::DropColumn { column } | Alteration::RenameColumn { column, .. } => {
+                    resolve_column(column, &tables)
+                }
+                Alteration::AddColumn { .. } => (),
+            }
+        }
+        Query::DropTable { table } => {
+            resolve_table(table, config);
+        }
+        Query::Explain { query, .. } => resolve_names(query, config),
+        Query::Catalog(Catalog::Columns { table })
+        | Query::Catalog(Catalog::Fragments { table: Some(table) }) => {
+            resolve_table(table, config);
+        }
+        Query::Catalog(_) | Query::Begin | Query::Commit | Query::Rollback => (),
+    }
+}
+
+fn resolve_table<'a>(name: &mut String, config: &'a Config) -> Option<&'a Table> {
+    let table = config.tables.iter().find(|t| same_name(&t.name, name))?;
+    name.clone_from(&table.name);
+    Some(table)
+}
+
+/// Whether two names are the same ignoring case.
+pub(crate) fn same_name(left: &str, right: &str) -> bool {
+    left.chars()
+        .flat_map(char::to_lowercase)
+        .eq(right.chars().flat_map(char::to_lowercase))
+}
+
+/// `column` may be written as `table.column`, an unqualified name takes the
+/// spelling of the first table that has it.
+fn resolve_column(column: &mut String, tables: &[&Table]) {
+    let field = |table: &Table, name: &str| {
+        table
+            .fields
+            .iter()
+            .find(|field| same_name(&field.name, name))
+            .map(|field| field.name.clone())
+    };
+
+    let resolved = match column.split_once('.') {
+        Some((table_name, name)) => tables
+            .iter()
+            .find(|table| same_name(&table.name, table_name))
+            .and_then(|table| Some(format!("{}.{}", table.name, field(table, name)?))),
+        None => tables.iter().find_map(|table| field(table, column)),
+    };
+    if let Some(resolved) = resolved {
+        *column = resolved;
+    }
+}
+
+fn resolve_item(item: &mut SelectItem, tables: &[&Table]) {
+    match item {
+        SelectItem::Column(column)
+        | SelectItem::Aggregate {
+            column: Some(column),
+            ..
+        } => resolve_column(column, tables),
+        SelectItem::Expression(expr) => resolve_expr(expr, tables),
+        SelectItem::Aggregate { column: None, .. } | SelectItem::Wildcard => (),
+    }
+}
+
+fn resolve_expr(expr: &mut Expr, tables: &[&Table]) {
+    match expr {
+        Expr::Column(column) => resolve_column(column, tables),
+        Expr::Binary { left, right, .. } => {
+            resolve_expr(left, tables);
+            resolve_expr(right, tables);
+        }
+        Expr::Literal(_) => (),
+    }
+}
+
+fn resolve_where(filter: &mut Where, tables: &[&Table], config: &Config) {
+    match filter {
+        Where::Comparison { column, value, .. } => {
+            resolve_column(column, tables);
+            if let Operand::Column(other) = value {
+                resolve_column(other, tables);
+            }
+        }
+        Where::And(left, right) | Where::Or(left, right) => {
+            resolve_where(left, tables, config);
+            resolve_where(right, tables, config);
+        }
+        Where::Not(inner) => resolve_where(inner, tables, config),
+        Where::In { column, .. }
+        | Where::Between { column, .. }
+        | Where::Like { column, .. }
+        | Where::IsNull { column } => resolve_column(column, tables),
+        // The subquery names its own tables.
+        Where::InSelect { column, query } => {
+            resolve_column(column, tables);
+            resolve_names(query, config);
+        }
+        Where::Exists(query) => resolve_names(query, config),
+    }
+}
+
+fn resolve_having(having: &mut Having, tables: &[&Table]) {
+    match having {
+        Having::Comparison { item, .. } => resolve_item(item, tables),
+        Having::And(left, right) | Having::Or(left, right) => {
+            resolve_having(left, tables);
+            resolve_having(right, tables);
+        }
+        Having::Not(inner) => resolve_having(inner, tables),
+    }
+}
@@ -1,23 +1,40 @@
+use std::fmt;
+
 use anyhow::anyhow;
 
-use super::Token;
+use super::{
+    Clause, Diagnostic, Span, Token, Value,
+    cursor::{is_identifier_part, is_identifier_start, keyword},
+    names::same_name,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Query {
     Select {
         table: String,
-        fields: Vec<String>,
+        /// Tables joined to `table`, columns are then written as `table.column`.
+        joins: Vec<Join>,
+        fields: Vec<SelectItem>,
+        /// Column header of every entry in `fields`, `None` keeps the default one.
+        aliases: Vec<Option<String>>,
+        distinct: bool,
         filter: Option<Where>,
+        group_by: Vec<String>,
+        having: Option<Having>,
+        order_by: Vec<OrderBy>,
+        limit: Option<u64>,
+        offset: Option<u64>,
         zones: Option<Vec<String>>,
     },
     Insert {
         table: String,
         columns: Vec<String>,
-        values: Vec<Vec<String>>,
+        values: Vec<Vec<Literal>>,
     },
     Update {
         table: String,
-        assignments: Vec<(String, String)>,
+        /// Expressions are evaluated against the row being updated.
+        assignments: Vec<(String, Expr)>,
         filter: Option<Where>,
         zones: Option<Vec<String>>,
     },
@@ -26,23 +43,790 @@ pub enum Query {
         filter: Option<Where>,
         zones: Option<Vec<String>>,
     },
+    /// A new global table and the fragments that store it.
+    CreateTable {
+        table: String,
+        /// Every column with its type.
+        columns: Vec<(String, String)>,
+        fragments: Vec<FragmentDef>,
+    },
+    AlterTable {
+        table: String,
+        alteration: Alteration,
+    },
+    DropTable {
+        table: String,
+    },
+    /// Where `query` runs and the statement each fragment gets for it,
+    /// `analyze` also runs it and times every fragment.
+    Explain {
+        query: Box<Query>,
+        analyze: bool,
+    },
+    /// What the schema holds, answered by the coordinator alone.
+    Catalog(Catalog),
+    /// The statements up to COMMIT or ROLLBACK run as one transaction in
+    /// every database.
+    Begin,
+    Commit,
+    Rollback,
 }
 
-#[derive(Debug, Clone)]
-pub struct Where {
+/// `SHOW TABLES`, `DESCRIBE table`, `SHOW FRAGMENTS [FROM table]` and `SHOW ZONES`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Catalog {
+    Tables,
+    Columns {
+        table: String,
+    },
+    /// Every fragment when `table` is `None`.
+    Fragments {
+        table: Option<String>,
+    },
+    Zones,
+}
+
+/// `FRAGMENT name ON manager ZONE 'zone' (column AS name, ...)`, where a
+/// CREATE TABLE stores its rows of `zone`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FragmentDef {
+    pub name: String,
+    pub manager: String,
+    pub zone: String,
+    /// Every column of the table with the name the fragment gives it.
+    pub columns: Vec<(String, String)>,
+}
+
+/// The change an ALTER TABLE makes to a column. A fragment stores an added
+/// or renamed column under its global name.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alteration {
+    AddColumn { column: String, r#type: String },
+    DropColumn { column: String },
+    RenameColumn { column: String, name: String },
+}
+
+/// `JOIN table ON left = right`, an inner equi-join resolved by the coordinator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+    pub table: String,
+    pub left: String,
+    pub right: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Where {
+    Comparison {
+        column: String,
+        op: String,
+        value: Operand,
+    },
+    And(Box<Where>, Box<Where>),
+    Or(Box<Where>, Box<Where>),
+    Not(Box<Where>),
+    In {
+        column: String,
+        values: Vec<Literal>,
+    },
+    Between {
+        column: String,
+        low: Literal,
+        high: Literal,
+    },
+    Like {
+        column: String,
+        pattern: Literal,
+    },
+    IsNull {
+        column: String,
+    },
+    /// `column IN (SELECT ...)`, replaced by an IN list once the subquery ran.
+    InSelect {
+        column: String,
+        query: Box<Query>,
+    },
+    /// `EXISTS (SELECT ...)`, the subquery can't refer to the outer row.
+    Exists(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Column(String),
+    Value(Literal),
+}
+
+/// A value written in the query, placeholders are bound before it runs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Bool(bool),
+    Null,
+    /// `$n`, or the n-th `?`, counting from 1.
+    Placeholder(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard,
+    Column(String),
+    Aggregate {
+        function: Aggregate,
+        /// `None` for `COUNT(*)`.
+        column: Option<String>,
+    },
+    Expression(Expr),
+}
+
+/// Arithmetic and string concatenation over the columns of a row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Literal),
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    /// Always yields a float, dividing by zero yields NULL.
+    Div,
+    Concat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+/// HAVING is evaluated by the coordinator once the groups of every fragment are merged.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Having {
+    Comparison {
+        item: SelectItem,
+        op: String,
+        value: Literal,
+    },
+    And(Box<Having>, Box<Having>),
+    Or(Box<Having>, Box<Having>),
+    Not(Box<Having>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
     pub column: String,
-    pub op: String,
-    pub value: String,
+    pub descending: bool,
+}
+
+impl fmt::Display for SelectItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectItem::Wildcard => write!(f, "*"),
+            SelectItem::Column(column) => write!(f, "{}", Name(column)),
+            SelectItem::Aggregate { function, column } => {
+                let name = match function {
+                    Aggregate::Count => "COUNT",
+                    Aggregate::Sum => "SUM",
+                    Aggregate::Avg => "AVG",
+                    Aggregate::Min => "MIN",
+                    Aggregate::Max => "MAX",
+                };
+                match column {
+                    Some(column) => write!(f, "{name}({})", Name(column)),
+                    None => write!(f, "{name}(*)"),
+                }
+            }
+            SelectItem::Expression(expr) => write!(f, "{expr}"),
+        }
+    }
+}
+
+impl BinaryOp {
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Concat => 1,
+            BinaryOp::Mul | BinaryOp::Div => 2,
+        }
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Concat => "||",
+        };
+        write!(f, "{op}")
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(column) => write!(f, "{}", Name(column)),
+            Expr::Literal(literal) => write!(f, "{literal}"),
+            Expr::Binary { op, left, right } => {
+                // Operators are left associative, a right operand of the same
+                // precedence needs parentheses to keep its grouping.
+                match &**left {
+                    Expr::Binary { op: inner, .. } if inner.precedence() < op.precedence() => {
+                        write!(f, "({left})")?
+                    }
+                    left => write!(f, "{left}")?,
+                }
+                write!(f, " {op} ")?;
+                match &**right {
+                    Expr::Binary { op: inner, .. } if inner.precedence() <= op.precedence() => {
+                        write!(f, "({right})")
+                    }
+                    // `a - -1` would start a comment.
+                    Expr::Literal(literal) if literal.is_negative() => write!(f, "({right})"),
+                    right => write!(f, "{right}"),
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Int(val) => write!(f, "{val}"),
+            // Debug keeps the fraction or exponent that makes it a float.
+            Literal::Float(val) => write!(f, "{val:?}"),
+            Literal::Str(val) => write!(f, "'{}'", val.replace('\'', "''")),
+            Literal::Bool(val) => write!(f, "{val}"),
+            Literal::Null => write!(f, "NULL"),
+            Literal::Placeholder(idx) => write!(f, "${idx}"),
+        }
+    }
+}
+
+/// A table or column name, quoted when it couldn't be read back otherwise.
+struct Name<'a>(&'a str);
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, part) in self.0.split('.').enumerate() {
+            if idx > 0 {
+                write!(f, ".")?;
+            }
+            let mut chars = part.chars();
+            let plain = chars.next().is_some_and(is_identifier_start)
+                && chars.all(is_identifier_part)
+                && keyword(part).is_none();
+            if plain {
+                write!(f, "{part}")?;
+            } else {
+                write!(f, "\"{}\"", part.replace('"', "\"\""))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The statement as SQL that parses back to it, ended by ';'.
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_body(f)?;
+        write!(f, ";")
+    }
+}
+
+impl Query {
+    /// The statement without its terminator, as a subquery is written.
+    fn fmt_body(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Query::Select {
+                table,
+                joins,
+                fields,
+                aliases,
+                distinct,
+                filter,
+                group_by,
+                having,
+                order_by,
+                limit,
+                offset,
+                zones,
+            } => {
+                write!(f, "SELECT ")?;
+                if *distinct {
+                    write!(f, "DISTINCT ")?;
+                }
+                for (idx, (item, alias)) in fields.iter().zip(aliases).enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                    if let Some(alias) = alias {
+                        write!(f, " AS {}", Name(alias))?;
+                    }
+                }
+                write!(f, " FROM {}", Name(table))?;
+                for join in joins {
+                    write!(f, " {join}")?;
+                }
+                if let Some(filter) = filter {
+                    write!(f, " WHERE {filter}")?;
+                }
+                if !group_by.is_empty() {
+                    write!(f, " GROUP BY ")?;
+                    write_names(f, group_by)?;
+                }
+                if let Some(having) = having {
+                    write!(f, " HAVING {having}")?;
+                }
+                for (idx, order) in order_by.iter().enumerate() {
+                    write!(f, "{}{order}", if idx == 0 { " ORDER BY " } else { ", " })?;
+                }
+                if let Some(limit) = limit {
+                    write!(f, " LIMIT {limit}")?;
+                }
+                if let Some(offset) = offset {
+                    write!(f, " OFFSET {offset}")?;
+                }
+                write_zones(f, zones)
+            }
+            Query::Insert {
+                table,
+                columns,
+                values,
+            } => {
+                write!(f, "INSERT INTO {} (", Name(table))?;
+                write_names(f, columns)?;
+                write!(f, ") VALUES ")?;
+                for (idx, record) in values.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    let record: Vec<_> = record.iter().map(Literal::to_string).collect();
+                    write!(f, "({})", record.join(", "))?;
+                }
+                Ok(())
+            }
+            Query::Update {
+                table,
+                assignments,
+                filter,
+                zones,
+            } => {
+                write!(f, "UPDATE {} SET ", Name(table))?;
+                for (idx, (column, value)) in assignments.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} = {value}", Name(column))?;
+                }
+                if let Some(filter) = filter {
+                    write!(f, " WHERE {filter}")?;
+                }
+                write_zones(f, zones)
+            }
+            Query::Delete {
+                table,
+                filter,
+                zones,
+            } => {
+                write!(f, "DELETE FROM {}", Name(table))?;
+                if let Some(filter) = filter {
+                    write!(f, " WHERE {filter}")?;
+                }
+                write_zones(f, zones)
+            }
+            Query::CreateTable {
+                table,
+                columns,
+                fragments,
+            } => {
+                write!(f, "CREATE TABLE {} (", Name(table))?;
+                for (idx, (column, r#type)) in columns.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{} {}", Name(column), Name(r#type))?;
+                }
+                write!(f, ")")?;
+                for fragment in fragments {
+                    write!(f, " {fragment}")?;
+                }
+                Ok(())
+            }
+            Query::AlterTable { table, alteration } => {
+                write!(f, "ALTER TABLE {} ", Name(table))?;
+                match alteration {
+                    Alteration::AddColumn { column, r#type } => {
+                        write!(f, "ADD COLUMN {} {}", Name(column), Name(r#type))
+                    }
+                    Alteration::DropColumn { column } => {
+                        write!(f, "DROP COLUMN {}", Name(column))
+                    }
+                    Alteration::RenameColumn { column, name } => {
+                        write!(f, "RENAME COLUMN {} TO {}", Name(column), Name(name))
+                    }
+                }
+            }
+            Query::DropTable { table } => write!(f, "DROP TABLE {}", Name(table)),
+            Query::Explain { query, analyze } => {
+                write!(f, "EXPLAIN ")?;
+                if *analyze {
+                    write!(f, "ANALYZE ")?;
+                }
+                query.fmt_body(f)
+            }
+            Query::Catalog(Catalog::Tables) => write!(f, "SHOW TABLES"),
+            Query::Catalog(Catalog::Columns { table }) => write!(f, "DESCRIBE {}", Name(table)),
+            Query::Catalog(Catalog::Fragments { table }) => {
+                write!(f, "SHOW FRAGMENTS")?;
+                if let Some(table) = table {
+                    write!(f, " FROM {}", Name(table))?;
+                }
+                Ok(())
+            }
+            Query::Catalog(Catalog::Zones) => write!(f, "SHOW ZONES"),
+            Query::Begin => write!(f, "BEGIN"),
+            Query::Commit => write!(f, "COMMIT"),
+            Query::Rollback => write!(f, "ROLLBACK"),
+        }
+    }
+}
+
+impl fmt::Display for FragmentDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "FRAGMENT {} ON {} ZONE {} (",
+            Name(&self.name),
+            Name(&self.manager),
+            Literal::Str(self.zone.clone())
+        )?;
+        for (idx, (column, name)) in self.columns.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{} AS {}", Name(column), Name(name))?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn write_names(f: &mut fmt::Formatter<'_>, names: &[String]) -> fmt::Result {
+    for (idx, name) in names.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", Name(name))?;
+    }
+    Ok(())
+}
+
+fn write_zones(f: &mut fmt::Formatter<'_>, zones: &Option<Vec<String>>) -> fmt::Result {
+    let Some(zones) = zones else {
+        return Ok(());
+    };
+    let zones: Vec<_> = zones
+        .iter()
+        .map(|zone| Literal::Str(zone.clone()).to_string())
+        .collect();
+    write!(f, " ZONE = [{}]", zones.join(", "))
+}
+
+impl fmt::Display for Join {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "JOIN {} ON {} = {}",
+            Name(&self.table),
+            Name(&self.left),
+            Name(&self.right)
+        )
+    }
+}
+
+impl fmt::Display for OrderBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        write!(f, "{} {direction}", Name(&self.column))
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Column(column) => write!(f, "{}", Name(column)),
+            Operand::Value(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl Where {
+    /// OR binds loosest, then AND, then NOT and the predicates.
+    fn precedence(&self) -> u8 {
+        match self {
+            Where::Or(..) => 1,
+            Where::And(..) => 2,
+            _ => 3,
+        }
+    }
+
+    /// IN, BETWEEN and LIKE, which are negated by a NOT after their column.
+    fn fmt_predicate(&self, f: &mut fmt::Formatter<'_>, negated: bool) -> fmt::Result {
+        let not = if negated { "NOT " } else { "" };
+        match self {
+            Where::In { column, values } => {
+                let values: Vec<_> = values.iter().map(Literal::to_string).collect();
+                write!(f, "{} {not}IN ({})", Name(column), values.join(", "))
+            }
+            Where::InSelect { column, query } => {
+                write!(f, "{} {not}IN (", Name(column))?;
+                query.fmt_body(f)?;
+                write!(f, ")")
+            }
+            Where::Between { column, low, high } => {
+                write!(f, "{} {not}BETWEEN {low} AND {high}", Name(column))
+            }
+            Where::Like { column, pattern } => {
+                write!(f, "{} {not}LIKE {pattern}", Name(column))
+            }
+            filter => unreachable!("{filter:?} is not negated after its column"),
+        }
+    }
+}
+
+impl fmt::Display for Where {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // AND and OR are left associative, like in expressions.
+        let operand = |f: &mut fmt::Formatter<'_>, operand: &Where, min: u8| {
+            if operand.precedence() < min {
+                write!(f, "({operand})")
+            } else {
+                write!(f, "{operand}")
+            }
+        };
+
+        match self {
+            Where::Comparison { column, op, value } => {
+                write!(f, "{} {op} {value}", Name(column))
+            }
+            Where::Or(left, right) | Where::And(left, right) => {
+                let (keyword, precedence) = match self {
+                    Where::Or(..) => ("OR", 1),
+                    _ => ("AND", 2),
+                };
+                operand(f, left, precedence)?;
+                write!(f, " {keyword} ")?;
+                operand(f, right, precedence + 1)
+            }
+            Where::Not(inner) => match &**inner {
+                Where::IsNull { column } => write!(f, "{} IS NOT NULL", Name(column)),
+                Where::In { .. }
+                | Where::InSelect { .. }
+                | Where::Between { .. }
+                | Where::Like { .. } => inner.fmt_predicate(f, true),
+                inner => {
+                    write!(f, "NOT ")?;
+                    operand(f, inner, 3)
+                }
+            },
+            Where::In { .. }
+            | Where::InSelect { .. }
+            | Where::Between { .. }
+            | Where::Like { .. } => self.fmt_predicate(f, false),
+            Where::IsNull { column } => write!(f, "{} IS NULL", Name(column)),
+            Where::Exists(query) => {
+                write!(f, "EXISTS (")?;
+                query.fmt_body(f)?;
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl Having {
+    fn precedence(&self) -> u8 {
+        match self {
+            Having::Or(..) => 1,
+            Having::And(..) => 2,
+            _ => 3,
+        }
+    }
+}
+
+impl fmt::Display for Having {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let operand = |f: &mut fmt::Formatter<'_>, operand: &Having, min: u8| {
+            if operand.precedence() < min {
+                write!(f, "({operand})")
+            } else {
+                write!(f, "{operand}")
+            }
+        };
+
+        match self {
+            Having::Comparison { item, op, value } => write!(f, "{item} {op} {value}"),
+            Having::Or(left, right) | Having::And(left, right) => {
+                let (keyword, precedence) = match self {
+                    Having::Or(..) => ("OR", 1),
+                    _ => ("AND", 2),
+                };
+                operand(f, left, precedence)?;
+                write!(f, " {keyword} ")?;
+                operand(f, right, precedence + 1)
+            }
+            Having::Not(inner) => {
+                write!(f, "NOT ")?;
+                operand(f, inner, 3)
+            }
+        }
+    }
+}
+
+impl Literal {
+    pub(crate) fn from_value(value: &Value) -> Literal {
+        match value {
+            Value::Int(val) => Literal::Int(*val),
+            Value::Float(val) => Literal::Float(*val),
+            Value::Str(val) => Literal::Str(val.clone()),
+            Value::Bool(val) => Literal::Bool(*val),
+            Value::Null => Literal::Null,
+        }
+    }
+
+    /// The value the fragments would return for it, placeholders are bound before running.
+    pub(crate) fn value(&self) -> Value {
+        match self {
+            Literal::Int(val) => Value::Int(*val),
+            Literal::Float(val) => Value::Float(*val),
+            Literal::Str(val) => Value::Str(val.clone()),
+            Literal::Bool(val) => Value::Bool(*val),
+            Literal::Null => Value::Null,
+            Literal::Placeholder(idx) => unreachable!("placeholder ${idx} was not bound"),
+        }
+    }
+
+    /// Type of the fields that can hold it, `None` for NULL and placeholders.
+    pub(crate) fn r#type(&self) -> Option<&'static str> {
+        match self {
+            Literal::Int(_) => Some("int"),
+            Literal::Float(_) => Some("float"),
+            Literal::Str(_) => Some("string"),
+            Literal::Bool(_) => Some("bool"),
+            Literal::Null | Literal::Placeholder(_) => None,
+        }
+    }
+
+    pub(crate) fn is_negative(&self) -> bool {
+        match self {
+            Literal::Int(val) => *val < 0,
+            Literal::Float(val) => val.is_sign_negative(),
+            _ => false,
+        }
+    }
+}
+
+/// A parsed statement with the positions of the names it uses, so errors
+/// found after parsing can still point into the source.
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub query: Query,
+    /// Where the statement starts.
+    pub span: Span,
+    /// Identifiers and placeholders of the statement in source order.
+    pub names: Vec<Occurrence>,
+}
+
+/// An identifier or placeholder as written in the source.
+#[derive(Debug, Clone)]
+pub struct Occurrence {
+    pub name: String,
+    /// `None` for table names and the values of an INSERT.
+    pub clause: Option<Clause>,
+    pub span: Span,
+}
+
+impl Statement {
+    /// Where `name` is first written in `clause`, in any case, or anywhere
+    /// when it isn't written there. A joined column may be written without
+    /// its table.
+    pub fn span_of(&self, name: &str, clause: Option<Clause>) -> Option<Span> {
+        let unqualified = |name: &str| name.rsplit('.').next().unwrap_or_default().to_string();
+        let find = |in_clause: &dyn Fn(&Occurrence) -> bool| {
+            let mut names = self.names.iter().filter(|written| in_clause(written));
+            names
+                .clone()
+                .find(|written| same_name(&written.name, name))
+                .or_else(|| {
+                    names.find(|written| same_name(&unqualified(&written.name), &unqualified(name)))
+                })
+        };
+        clause
+            .and_then(|clause| find(&|written| written.clause == Some(clause)))
+            .or_else(|| find(&|_| true))
+            .map(|written| written.span)
+    }
 }
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<(Token, Span)>,
+    /// Span of the last token taken, where parse errors are reported.
+    span: Span,
+    names: Vec<Occurrence>,
+    /// Clause the names taken now are written in.
+    clause: Option<Clause>,
 }
 
 impl Parser {
-    pub fn new(mut tokens: Vec<Token>) -> Parser {
+    pub fn new(mut tokens: Vec<(Token, Span)>) -> Parser {
         tokens.reverse();
-        Parser { tokens }
+        Parser {
+            tokens,
+            span: Span {
+                line: 1,
+                column: 1,
+                len: 0,
+            },
+            names: Vec::new(),
+            clause: None,
+        }
+    }
+
+    /// Every statement of a script, empty statements are skipped.
+    pub fn parse_script(&mut self) -> Result<Vec<Statement>, Diagnostic> {
+        let mut statements = Vec::new();
+        while let Some((token, span)) = self.tokens.last() {
+            if *token == Token::Semicolon {
+                self.tokens.pop();
+                continue;
+            }
+
+            let span = *span;
+            self.clause = None;
+            let query = self
+                .parse()
+                .map_err(|e| Diagnostic::new(e, Some(self.span)))?;
+            statements.push(Statement {
+                query,
+                span,
+                names: std::mem::take(&mut self.names),
+            });
+        }
+
+        Ok(statements)
     }
 
     pub fn parse(&mut self) -> anyhow::Result<Query> {
@@ -51,226 +835,1105 @@ impl Parser {
             Token::Insert => self.parse_insert(),
             Token::Update => self.parse_update(),
             Token::Delete => self.parse_delete(),
+            Token::Create => self.parse_create(),
+            Token::Alter => self.parse_alter(),
+            Token::Drop => self.parse_drop(),
+            Token::Explain => {
+                let analyze = self.first() == Some(&Token::Analyze);
+                if analyze {
+                    self.bump()?;
+                }
+                Ok(Query::Explain {
+                    query: Box::new(self.parse()?),
+                    analyze,
+                })
+            }
+            Token::Show => self.parse_show(),
+            Token::Begin => self.parse_end(Query::Begin),
+            Token::Commit => self.parse_end(Query::Commit),
+            Token::Rollback => self.parse_end(Query::Rollback),
+            Token::Describe => {
+                let table = match self.bump()? {
+                    Token::Identifier(name) => name,
+                    token => return Err(anyhow!("expected table name, found {token:?}")),
+                };
+                self.parse_end(Query::Catalog(Catalog::Columns { table }))
+            }
             token => Err(anyhow!("found token {token:?}")),
         }
     }
 
     fn parse_select(&mut self) -> anyhow::Result<Query> {
+        let query = self.parse_select_body()?;
+
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(query)
+    }
+
+    /// A SELECT up to its terminator, ';' for a statement and ')' for a subquery.
+    fn parse_select_body(&mut self) -> anyhow::Result<Query> {
+        let distinct = self.first() == Some(&Token::Distinct);
+        if distinct {
+            self.bump()?;
+        }
+
+        self.clause = Some(Clause::Select);
         let mut fields = Vec::new();
+        let mut aliases = Vec::new();
         loop {
+            let item = if self.first() == Some(&Token::Star) {
+                self.bump()?;
+                SelectItem::Wildcard
+            } else {
+                self.parse_select_item()?
+            };
+
+            let alias = if self.first() == Some(&Token::As) {
+                self.bump()?;
+                match self.bump()? {
+                    Token::Identifier(_) if item == SelectItem::Wildcard => {
+                        return Err(anyhow!("'*' can't have an alias"));
+                    }
+                    Token::Identifier(alias) => Some(alias),
+                    token => return Err(anyhow!("expected alias, found {token:?}")),
+                }
+            } else {
+                None
+            };
+
+            fields.push(item);
+            aliases.push(alias);
+
             match self.bump()? {
-                Token::Identifier(ident) => fields.push(ident),
-                Token::Star => fields.push("*".to_string()),
+                Token::Comma => (),
                 Token::From => break,
-                token => return Err(anyhow!("expected FROM found {token:?}")),
+                token => return Err(anyhow!("expected ',' or FROM, found {token:?}")),
             }
         }
+        self.clause = None;
 
         let table = match self.bump()? {
             Token::Identifier(table) => table,
             token => return Err(anyhow!("expected table name, found {token:?}")),
         };
+        let joins = self.parse_joins()?;
         let filter = self.parse_filter()?;
 
+        let group_by = self.parse_group_by()?;
+        let having = self.parse_having()?;
+
+        let order_by = self.parse_order_by()?;
+
+        let limit = self.parse_count(Token::Limit)?;
+        let offset = self.parse_count(Token::Offset)?;
+
         let zones = self.parse_zones()?;
 
-        match self.bump()? {
-            Token::Semicolon => (),
-            token => return Err(anyhow!("expected ';', found {token:?}")),
-        };
+        Ok(Query::Select {
+            table,
+            joins,
+            fields,
+            aliases,
+            distinct,
+            filter,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+            zones,
+        })
+    }
+
+    fn parse_joins(&mut self) -> anyhow::Result<Vec<Join>> {
+        let mut joins = Vec::new();
+        while matches!(self.first(), Some(Token::Join | Token::Inner)) {
+            if self.bump()? == Token::Inner {
+                match self.bump()? {
+                    Token::Join => (),
+                    token => return Err(anyhow!("expected JOIN, found {token:?}")),
+                };
+            }
+
+            self.clause = None;
+            let table = match self.bump()? {
+                Token::Identifier(table) => table,
+                token => return Err(anyhow!("expected table name, found {token:?}")),
+            };
+
+            match self.bump()? {
+                Token::On => (),
+                token => return Err(anyhow!("expected ON, found {token:?}")),
+            };
+            self.clause = Some(Clause::Join);
+
+            let left = match self.bump()? {
+                Token::Identifier(column) => column,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+            match self.bump()? {
+                Token::Equal => (),
+                token => return Err(anyhow!("only '=' is supported in ON, found {token:?}")),
+            };
+            let right = match self.bump()? {
+                Token::Identifier(column) => column,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+
+            joins.push(Join { table, left, right });
+        }
+
+        Ok(joins)
+    }
+
+    fn parse_select_item(&mut self) -> anyhow::Result<SelectItem> {
+        if matches!(self.first(), Some(Token::Identifier(_)))
+            && self.second() == Some(&Token::LeftParen)
+        {
+            let Token::Identifier(name) = self.bump()? else {
+                unreachable!()
+            };
+            let aggregate = self.parse_aggregate(&name)?;
+            if matches!(
+                self.first(),
+                Some(Token::Plus | Token::Minus | Token::Star | Token::Slash | Token::Concat)
+            ) {
+                return Err(anyhow!("aggregates can't be used inside expressions"));
+            }
+            return Ok(aggregate);
+        }
+
+        Ok(match self.parse_expr()? {
+            Expr::Column(column) => SelectItem::Column(column),
+            expr => SelectItem::Expression(expr),
+        })
+    }
+
+    fn parse_expr(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_term()?;
+        loop {
+            let op = match self.first() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Sub,
+                Some(Token::Concat) => BinaryOp::Concat,
+                _ => break,
+            };
+            self.bump()?;
+            let right = self.parse_term()?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_term(&mut self) -> anyhow::Result<Expr> {
+        let mut left = self.parse_factor()?;
+        loop {
+            let op = match self.first() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                _ => break,
+            };
+            self.bump()?;
+            let right = self.parse_factor()?;
+            left = Expr::Binary {
+                op,
+                left: Box::new(left),
+                right: Box::new(right),
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_factor(&mut self) -> anyhow::Result<Expr> {
+        match self.bump()? {
+            Token::Identifier(_) if self.first() == Some(&Token::LeftParen) => {
+                Err(anyhow!("aggregates can't be used inside expressions"))
+            }
+            Token::Identifier(column) => Ok(Expr::Column(column)),
+            Token::Minus if matches!(self.first(), Some(Token::Int(_) | Token::Float(_))) => {
+                Self::number(self.bump()?, true).map(Expr::Literal)
+            }
+            Token::Minus => Ok(match self.parse_factor()? {
+                Expr::Literal(Literal::Int(num)) if num != i64::MIN => {
+                    Expr::Literal(Literal::Int(-num))
+                }
+                Expr::Literal(Literal::Float(num)) => Expr::Literal(Literal::Float(-num)),
+                expr => Expr::Binary {
+                    op: BinaryOp::Sub,
+                    left: Box::new(Expr::Literal(Literal::Int(0))),
+                    right: Box::new(expr),
+                },
+            }),
+            Token::LeftParen => {
+                let expr = self.parse_expr()?;
+                match self.bump()? {
+                    Token::RightParen => Ok(expr),
+                    token => Err(anyhow!("expected ')', found {token:?}")),
+                }
+            }
+            token @ (Token::Int(_) | Token::Float(_)) => {
+                Self::number(token, false).map(Expr::Literal)
+            }
+            token => Self::literal(token.clone())
+                .map(Expr::Literal)
+                .map_err(|_| anyhow!("expected expression, found {token:?}")),
+        }
+    }
+
+    fn parse_aggregate(&mut self, name: &str) -> anyhow::Result<SelectItem> {
+        let function = match &*name.to_lowercase() {
+            "count" => Aggregate::Count,
+            "sum" => Aggregate::Sum,
+            "avg" => Aggregate::Avg,
+            "min" => Aggregate::Min,
+            "max" => Aggregate::Max,
+            _ => return Err(anyhow!("unknown function '{name}'")),
+        };
+        self.bump()?;
+
+        let column = match self.bump()? {
+            Token::Star if function == Aggregate::Count => None,
+            Token::Identifier(column) => Some(column),
+            token => return Err(anyhow!("expected column name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::RightParen => (),
+            token => return Err(anyhow!("expected ')', found {token:?}")),
+        };
+
+        Ok(SelectItem::Aggregate { function, column })
+    }
+
+    fn parse_insert(&mut self) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::Into => (),
+            token => return Err(anyhow!("expected INTO, found {token:?}")),
+        };
+
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::LeftParen => (),
+            token => return Err(anyhow!("expected '(', found {token:?}")),
+        };
+
+        self.clause = Some(Clause::Columns);
+        let mut columns = Vec::new();
+        loop {
+            match self.bump()? {
+                Token::Identifier(col) => columns.push(col),
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            }
+
+            match self.bump()? {
+                Token::Comma => (),
+                Token::RightParen => break,
+                token => return Err(anyhow!("expected ',' or ')', found {token:?}")),
+            }
+        }
+
+        match self.bump()? {
+            Token::Values => (),
+            token => return Err(anyhow!("expected VALUES, found {token:?}")),
+        };
+        self.clause = None;
+
+        match self.bump()? {
+            Token::LeftParen => (),
+            token => return Err(anyhow!("expected '(', found {token:?}")),
+        };
+
+        let mut values = Vec::new();
+        loop {
+            let mut record = Vec::new();
+            loop {
+                record.push(self.parse_literal()?);
+
+                match self.bump()? {
+                    Token::Comma => (),
+                    Token::RightParen => break,
+                    token => return Err(anyhow!("expected ',' or ')', found {token:?}")),
+                }
+            }
+            values.push(record);
+
+            match self.bump()? {
+                Token::Comma => (),
+                Token::Semicolon => break,
+                token => return Err(anyhow!("expected ',' or ';', found {token:?}")),
+            };
+
+            match self.bump()? {
+                Token::LeftParen => (),
+                token => return Err(anyhow!("expected '(', found {token:?}")),
+            };
+        }
+
+        Ok(Query::Insert {
+            table,
+            columns,
+            values,
+        })
+    }
+
+    fn parse_update(&mut self) -> anyhow::Result<Query> {
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::Set => (),
+            token => return Err(anyhow!("expected SET, found {token:?}")),
+        };
+        self.clause = Some(Clause::Set);
+
+        let mut assignments = Vec::new();
+        loop {
+            let column = match self.bump()? {
+                Token::Identifier(name) => name,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+
+            match self.bump()? {
+                Token::Equal => (),
+                token => return Err(anyhow!("expected '=', found {token:?}")),
+            };
+
+            assignments.push((column, self.parse_expr()?));
+
+            if self.first() != Some(&Token::Comma) {
+                break;
+            }
+            self.bump()?;
+        }
+
+        let filter = self.parse_filter()?;
+
+        let zones = self.parse_zones()?;
+
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(Query::Update {
+            table,
+            assignments,
+            filter,
+            zones,
+        })
+    }
+
+    fn parse_delete(&mut self) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::From => (),
+            token => return Err(anyhow!("expected FROM, found {token:?}")),
+        };
+
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        let filter = self.parse_filter()?;
+
+        let zones = self.parse_zones()?;
+
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(Query::Delete {
+            table,
+            filter,
+            zones,
+        })
+    }
+
+    fn parse_create(&mut self) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::Table => (),
+            token => return Err(anyhow!("expected TABLE, found {token:?}")),
+        };
+
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::LeftParen => (),
+            token => return Err(anyhow!("expected '(', found {token:?}")),
+        };
+
+        let mut columns = Vec::new();
+        loop {
+            let column = match self.bump()? {
+                Token::Identifier(name) => name,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+            let r#type = match self.bump()? {
+                Token::Identifier(name) => name.to_lowercase(),
+                token => return Err(anyhow!("expected column type, found {token:?}")),
+            };
+            columns.push((column, r#type));
+
+            match self.bump()? {
+                Token::Comma => (),
+                Token::RightParen => break,
+                token => return Err(anyhow!("expected ',' or ')', found {token:?}")),
+            }
+        }
+
+        let mut fragments = Vec::new();
+        loop {
+            match self.bump()? {
+                Token::Fragment => fragments.push(self.parse_fragment(&columns)?),
+                Token::Semicolon if !fragments.is_empty() => break,
+                token => return Err(anyhow!("expected FRAGMENT, found {token:?}")),
+            }
+        }
+
+        Ok(Query::CreateTable {
+            table,
+            columns,
+            fragments,
+        })
+    }
+
+    /// A fragment without a column list keeps the names of the table.
+    fn parse_fragment(
+        &mut self,
+        table_columns: &[(String, String)],
+    ) -> anyhow::Result<FragmentDef> {
+        let name = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected fragment name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::On => (),
+            token => return Err(anyhow!("expected ON, found {token:?}")),
+        };
+
+        let manager = match self.bump()? {
+            Token::Identifier(name) => name.to_lowercase(),
+            token => return Err(anyhow!("expected database manager, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::Zone => (),
+            token => return Err(anyhow!("expected ZONE, found {token:?}")),
+        };
+
+        let zone = match self.bump()? {
+            Token::Str(zone) => zone,
+            token => return Err(anyhow!("expected zone name, found {token:?}")),
+        };
+
+        if self.first() != Some(&Token::LeftParen) {
+            let columns = table_columns
+                .iter()
+                .map(|(column, _)| (column.clone(), column.clone()))
+                .collect();
+            return Ok(FragmentDef {
+                name,
+                manager,
+                zone,
+                columns,
+            });
+        }
+        self.bump()?;
+
+        let mut columns = Vec::new();
+        loop {
+            let column = match self.bump()? {
+                Token::Identifier(name) => name,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+            let stored = if self.first() == Some(&Token::As) {
+                self.bump()?;
+                match self.bump()? {
+                    Token::Identifier(name) => name,
+                    token => return Err(anyhow!("expected field name, found {token:?}")),
+                }
+            } else {
+                column.clone()
+            };
+            columns.push((column, stored));
+
+            match self.bump()? {
+                Token::Comma => (),
+                Token::RightParen => break,
+                token => return Err(anyhow!("expected ',' or ')', found {token:?}")),
+            }
+        }
+
+        Ok(FragmentDef {
+            name,
+            manager,
+            zone,
+            columns,
+        })
+    }
+
+    fn parse_alter(&mut self) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::Table => (),
+            token => return Err(anyhow!("expected TABLE, found {token:?}")),
+        };
+
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        let action = self.bump()?;
+        if !matches!(action, Token::Add | Token::Drop | Token::Rename) {
+            return Err(anyhow!("expected ADD, DROP or RENAME, found {action:?}"));
+        }
+
+        match self.bump()? {
+            Token::Column => (),
+            token => return Err(anyhow!("expected COLUMN, found {token:?}")),
+        };
+
+        let column = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected column name, found {token:?}")),
+        };
+
+        let alteration = match action {
+            Token::Add => match self.bump()? {
+                Token::Identifier(name) => Alteration::AddColumn {
+                    column,
+                    r#type: name.to_lowercase(),
+                },
+                token => return Err(anyhow!("expected column type, found {token:?}")),
+            },
+            Token::Drop => Alteration::DropColumn { column },
+            _ => {
+                match self.bump()? {
+                    Token::To => (),
+                    token => return Err(anyhow!("expected TO, found {token:?}")),
+                };
+                match self.bump()? {
+                    Token::Identifier(name) => Alteration::RenameColumn { column, name },
+                    token => return Err(anyhow!("expected column name, found {token:?}")),
+                }
+            }
+        };
+
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(Query::AlterTable { table, alteration })
+    }
+
+    fn parse_drop(&mut self) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::Table => (),
+            token => return Err(anyhow!("expected TABLE, found {token:?}")),
+        };
+
+        let table = match self.bump()? {
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected table name, found {token:?}")),
+        };
+
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(Query::DropTable { table })
+    }
+
+    /// TABLES, FRAGMENTS and ZONES are only words after SHOW, they stay free
+    /// to name tables and columns.
+    fn parse_show(&mut self) -> anyhow::Result<Query> {
+        let word = match self.bump()? {
+            Token::Identifier(word) => word,
+            token => {
+                return Err(anyhow!(
+                    "expected TABLES, FRAGMENTS or ZONES, found {token:?}"
+                ));
+            }
+        };
+
+        let catalog = if same_name(&word, "tables") {
+            Catalog::Tables
+        } else if same_name(&word, "zones") {
+            Catalog::Zones
+        } else if same_name(&word, "fragments") {
+            let table = if self.first() == Some(&Token::From) {
+                self.bump()?;
+                match self.bump()? {
+                    Token::Identifier(name) => Some(name),
+                    token => return Err(anyhow!("expected table name, found {token:?}")),
+                }
+            } else {
+                None
+            };
+            Catalog::Fragments { table }
+        } else {
+            return Err(anyhow!(
+                "expected TABLES, FRAGMENTS or ZONES, found '{word}'"
+            ));
+        };
+
+        self.parse_end(Query::Catalog(catalog))
+    }
+
+    /// `query` once the ';' that ends it is taken.
+    fn parse_end(&mut self, query: Query) -> anyhow::Result<Query> {
+        match self.bump()? {
+            Token::Semicolon => (),
+            token => return Err(anyhow!("expected ';', found {token:?}")),
+        };
+
+        Ok(query)
+    }
+
+    fn parse_filter(&mut self) -> anyhow::Result<Option<Where>> {
+        match self.first() {
+            Some(token) if *token == Token::Semicolon => return Ok(None),
+            Some(token) if *token == Token::RightParen => return Ok(None),
+            Some(token) if *token == Token::Zone => return Ok(None),
+            Some(token) if *token == Token::Group => return Ok(None),
+            Some(token) if *token == Token::Having => return Ok(None),
+            Some(token) if *token == Token::Order => return Ok(None),
+            Some(token) if *token == Token::Limit => return Ok(None),
+            Some(token) if *token == Token::Offset => return Ok(None),
+            Some(token) if *token == Token::Where => self.bump()?,
+            Some(_) => {
+                let token = self.bump()?;
+                return Err(anyhow!("expected 'WHERE' found {token:?}"));
+            }
+            None => return Ok(None),
+        };
+
+        self.clause = Some(Clause::Where);
+        self.parse_or().map(Some)
+    }
+
+    fn parse_or(&mut self) -> anyhow::Result<Where> {
+        let mut left = self.parse_and()?;
+        while self.first() == Some(&Token::Or) {
+            self.bump()?;
+            let right = self.parse_and()?;
+            left = Where::Or(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> anyhow::Result<Where> {
+        let mut left = self.parse_not()?;
+        while self.first() == Some(&Token::And) {
+            self.bump()?;
+            let right = self.parse_not()?;
+            left = Where::And(Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> anyhow::Result<Where> {
+        if self.first() == Some(&Token::Not) {
+            self.bump()?;
+            return Ok(Where::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_predicate()
+    }
+
+    fn parse_predicate(&mut self) -> anyhow::Result<Where> {
+        let column = match self.bump()? {
+            Token::LeftParen => {
+                let filter = self.parse_or()?;
+                match self.bump()? {
+                    Token::RightParen => (),
+                    token => return Err(anyhow!("expected ')', found {token:?}")),
+                };
+                return Ok(filter);
+            }
+            Token::Exists => {
+                match self.bump()? {
+                    Token::LeftParen => (),
+                    token => return Err(anyhow!("expected '(', found {token:?}")),
+                };
+                return Ok(Where::Exists(Box::new(self.parse_subquery()?)));
+            }
+            Token::Identifier(name) => name,
+            token => return Err(anyhow!("expected column name, found {token:?}")),
+        };
+
+        if self.first() == Some(&Token::Is) {
+            self.bump()?;
+            let negated = self.first() == Some(&Token::Not);
+            if negated {
+                self.bump()?;
+            }
+            match self.bump()? {
+                Token::Null => (),
+                token => return Err(anyhow!("expected NULL, found {token:?}")),
+            };
+
+            let predicate = Where::IsNull { column };
+            return Ok(if negated {
+                Where::Not(Box::new(predicate))
+            } else {
+                predicate
+            });
+        }
+
+        let negated = self.first() == Some(&Token::Not);
+        if negated {
+            self.bump()?;
+        }
 
-        Ok(Query::Select {
-            table,
-            fields,
-            filter,
-            zones,
-        })
-    }
+        let predicate = match self.first() {
+            Some(Token::In) => Some(self.parse_in(column.clone())?),
+            Some(Token::Between) => Some(self.parse_between(column.clone())?),
+            Some(Token::Like) => Some(self.parse_like(column.clone())?),
+            _ if negated => return Err(anyhow!("expected IN, BETWEEN or LIKE after NOT")),
+            _ => None,
+        };
+        if let Some(predicate) = predicate {
+            return Ok(if negated {
+                Where::Not(Box::new(predicate))
+            } else {
+                predicate
+            });
+        }
 
-    fn parse_insert(&mut self) -> anyhow::Result<Query> {
-        match self.bump()? {
-            Token::Into => (),
-            token => return Err(anyhow!("expected INTO, found {token:?}")),
+        let op = match self.bump()? {
+            Token::Equal => "=".to_string(),
+            Token::Neq => "!=".to_string(),
+            Token::Lt => "<".to_string(),
+            Token::Gt => ">".to_string(),
+            Token::Le => "<=".to_string(),
+            Token::Ge => ">=".to_string(),
+            token => return Err(anyhow!("expected operator, found {token:?}")),
         };
 
-        let table = match self.bump()? {
-            Token::Identifier(name) => name,
-            token => return Err(anyhow!("expected table name, found {token:?}")),
+        let value = match self.first() {
+            Some(Token::Identifier(_)) => {
+                let Token::Identifier(name) = self.bump()? else {
+                    unreachable!()
+                };
+                Operand::Column(name)
+            }
+            _ => Operand::Value(self.parse_value()?),
         };
 
+        Ok(Where::Comparison { column, op, value })
+    }
+
+    fn parse_in(&mut self, column: String) -> anyhow::Result<Where> {
+        self.bump()?;
         match self.bump()? {
             Token::LeftParen => (),
             token => return Err(anyhow!("expected '(', found {token:?}")),
         };
 
-        let mut columns = Vec::new();
+        if self.first() == Some(&Token::Select) {
+            let query = Box::new(self.parse_subquery()?);
+            return Ok(Where::InSelect { column, query });
+        }
+
+        let mut values = Vec::new();
         loop {
+            values.push(self.parse_value()?);
+
             match self.bump()? {
-                Token::Identifier(col) => columns.push(col),
+                Token::Comma => (),
                 Token::RightParen => break,
-                token => return Err(anyhow!("expected column name, found {token:?}")),
+                token => return Err(anyhow!("expected ',' or ')', found {token:?}")),
             }
         }
 
+        Ok(Where::In { column, values })
+    }
+
+    /// A SELECT after its opening '(', up to and including the closing ')'.
+    fn parse_subquery(&mut self) -> anyhow::Result<Query> {
         match self.bump()? {
-            Token::Values => (),
-            token => return Err(anyhow!("expected VALUES, found {token:?}")),
+            Token::Select => (),
+            token => return Err(anyhow!("expected SELECT, found {token:?}")),
         };
+        let outer = self.clause;
+        let query = self.parse_select_body()?;
+        self.clause = outer;
 
         match self.bump()? {
-            Token::LeftParen => (),
-            token => return Err(anyhow!("expected '(', found {token:?}")),
+            Token::RightParen => (),
+            token => return Err(anyhow!("expected ')', found {token:?}")),
         };
 
-        let mut values = Vec::new();
-        loop {
-            let mut record = Vec::new();
-            loop {
-                match self.bump()? {
-                    Token::Number(num) => record.push(num.to_string()),
-                    Token::Str(val) => record.push(val),
-                    Token::True => record.push("true".to_string()),
-                    Token::False => record.push("false".to_string()),
-                    Token::RightParen => break,
-                    token => return Err(anyhow!("expected value, found {token:?}")),
-                }
-            }
-            values.push(record);
-
-            match self.bump()? {
-                Token::LeftParen => (),
-                Token::Semicolon => break,
-                token => return Err(anyhow!("expected '(', found {token:?}")),
-            };
-        }
-
-        Ok(Query::Insert {
-            table,
-            columns,
-            values,
-        })
+        Ok(query)
     }
 
-    fn parse_update(&mut self) -> anyhow::Result<Query> {
-        let table = match self.bump()? {
-            Token::Identifier(name) => name,
-            token => return Err(anyhow!("expected table name, found {token:?}")),
-        };
+    fn parse_between(&mut self, column: String) -> anyhow::Result<Where> {
+        self.bump()?;
+        let low = self.parse_value()?;
 
         match self.bump()? {
-            Token::Set => (),
-            token => return Err(anyhow!("expected SET, found {token:?}")),
+            Token::And => (),
+            token => return Err(anyhow!("expected AND, found {token:?}")),
         };
 
-        let mut assignments = Vec::new();
-        loop {
-            let column = match self.bump()? {
-                Token::Identifier(name) => name,
-                token => return Err(anyhow!("expected column name, found {token:?}")),
-            };
+        let high = self.parse_value()?;
 
-            match self.bump()? {
-                Token::Equal => (),
-                token => return Err(anyhow!("expected '=', found {token:?}")),
-            };
+        Ok(Where::Between { column, low, high })
+    }
 
-            let value = match self.bump()? {
-                Token::Str(val) => val,
-                Token::Number(num) => num.to_string(),
-                token => return Err(anyhow!("expected literal value, found {token:?}")),
-            };
+    fn parse_like(&mut self, column: String) -> anyhow::Result<Where> {
+        self.bump()?;
+        let pattern = match self.bump()? {
+            Token::Str(pattern) => Literal::Str(pattern),
+            Token::Placeholder(idx) => Literal::Placeholder(idx),
+            token => return Err(anyhow!("expected pattern, found {token:?}")),
+        };
 
-            assignments.push((column, value));
+        Ok(Where::Like { column, pattern })
+    }
 
-            match self.first() {
-                Some(token) if *token == Token::Where || *token == Token::Semicolon => break,
-                Some(_) => continue,
-                None => return Err(anyhow!("unterminated query")),
-            };
+    /// A literal compared with a column, where NULL would never match.
+    fn parse_value(&mut self) -> anyhow::Result<Literal> {
+        match self.parse_literal()? {
+            Literal::Null => Err(anyhow!("comparison with NULL, use IS [NOT] NULL")),
+            literal => Ok(literal),
         }
+    }
 
-        let filter = self.parse_filter()?;
+    /// A literal, numbers may have a leading '-'.
+    fn parse_literal(&mut self) -> anyhow::Result<Literal> {
+        match self.bump()? {
+            Token::Minus => Self::number(self.bump()?, true),
+            token => Self::literal(token),
+        }
+    }
 
-        let zones = self.parse_zones()?;
+    /// A number token with its sign, which is part of the value so that
+    /// `-9223372036854775808` fits in an `i64`.
+    fn number(token: Token, negative: bool) -> anyhow::Result<Literal> {
+        match token {
+            Token::Int(num) => {
+                let value = if negative {
+                    -i128::from(num)
+                } else {
+                    i128::from(num)
+                };
+                i64::try_from(value)
+                    .map(Literal::Int)
+                    .map_err(|_| anyhow!("integer {value} out of range"))
+            }
+            Token::Float(num) if negative => Ok(Literal::Float(-num)),
+            Token::Float(num) => Ok(Literal::Float(num)),
+            token => Err(anyhow!("expected a number after '-', found {token:?}")),
+        }
+    }
+
+    fn literal(token: Token) -> anyhow::Result<Literal> {
+        match token {
+            Token::Str(val) => Ok(Literal::Str(val)),
+            token @ (Token::Int(_) | Token::Float(_)) => Self::number(token, false),
+            Token::True => Ok(Literal::Bool(true)),
+            Token::False => Ok(Literal::Bool(false)),
+            Token::Null => Ok(Literal::Null),
+            Token::Placeholder(idx) => Ok(Literal::Placeholder(idx)),
+            token => Err(anyhow!("expected literal value, found {token:?}")),
+        }
+    }
+
+    fn parse_group_by(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.first() != Some(&Token::Group) {
+            return Ok(Vec::new());
+        }
+        self.bump()?;
 
         match self.bump()? {
-            Token::Semicolon => (),
-            token => return Err(anyhow!("expected ';', found {token:?}")),
+            Token::By => (),
+            token => return Err(anyhow!("expected BY, found {token:?}")),
         };
 
-        Ok(Query::Update {
-            table,
-            assignments,
-            filter,
-            zones,
-        })
+        self.clause = Some(Clause::GroupBy);
+        let mut group_by = Vec::new();
+        loop {
+            match self.bump()? {
+                Token::Identifier(name) => group_by.push(name),
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+
+            if self.first() != Some(&Token::Comma) {
+                break;
+            }
+            self.bump()?;
+        }
+
+        Ok(group_by)
     }
 
-    fn parse_delete(&mut self) -> anyhow::Result<Query> {
-        match self.bump()? {
-            Token::From => (),
-            token => return Err(anyhow!("expected FROM, found {token:?}")),
-        };
+    fn parse_having(&mut self) -> anyhow::Result<Option<Having>> {
+        if self.first() != Some(&Token::Having) {
+            return Ok(None);
+        }
+        self.bump()?;
+        self.clause = Some(Clause::Having);
 
-        let table = match self.bump()? {
-            Token::Identifier(name) => name,
-            token => return Err(anyhow!("expected table name, found {token:?}")),
-        };
+        self.parse_having_or().map(Some)
+    }
 
-        let filter = self.parse_filter()?;
+    fn parse_having_or(&mut self) -> anyhow::Result<Having> {
+        let mut left = self.parse_having_and()?;
+        while self.first() == Some(&Token::Or) {
+            self.bump()?;
+            let right = self.parse_having_and()?;
+            left = Having::Or(Box::new(left), Box::new(right));
+        }
 
-        let zones = self.parse_zones()?;
+        Ok(left)
+    }
 
-        match self.bump()? {
-            Token::Semicolon => (),
-            token => return Err(anyhow!("expected ';', found {token:?}")),
-        };
+    fn parse_having_and(&mut self) -> anyhow::Result<Having> {
+        let mut left = self.parse_having_not()?;
+        while self.first() == Some(&Token::And) {
+            self.bump()?;
+            let right = self.parse_having_not()?;
+            left = Having::And(Box::new(left), Box::new(right));
+        }
 
-        Ok(Query::Delete {
-            table,
-            filter,
-            zones,
-        })
+        Ok(left)
     }
 
-    fn parse_filter(&mut self) -> anyhow::Result<Option<Where>> {
-        match self.first() {
-            Some(token) if *token == Token::Semicolon => return Ok(None),
-            Some(token) if *token == Token::Zone => return Ok(None),
-            Some(token) if *token == Token::Where => self.bump()?,
-            Some(token) => return Err(anyhow!("expected 'WHERE' found {token:?}")),
-            None => return Ok(None),
-        };
+    fn parse_having_not(&mut self) -> anyhow::Result<Having> {
+        if self.first() == Some(&Token::Not) {
+            self.bump()?;
+            return Ok(Having::Not(Box::new(self.parse_having_not()?)));
+        }
 
-        let column = match self.bump()? {
-            Token::Identifier(name) => name,
-            token => return Err(anyhow!("expected column name, found {token:?}")),
+        let item = match self.bump()? {
+            Token::LeftParen => {
+                let having = self.parse_having_or()?;
+                match self.bump()? {
+                    Token::RightParen => (),
+                    token => return Err(anyhow!("expected ')', found {token:?}")),
+                };
+                return Ok(having);
+            }
+            Token::Identifier(ident) if self.first() == Some(&Token::LeftParen) => {
+                self.parse_aggregate(&ident)?
+            }
+            Token::Identifier(ident) => SelectItem::Column(ident),
+            token => return Err(anyhow!("expected aggregate or column, found {token:?}")),
         };
 
         let op = match self.bump()? {
             Token::Equal => "=".to_string(),
-            // Token::Neq => "!=".to_string(),
-            // Token::Lt => "<".to_string(),
-            // Token::Gt => ">".to_string(),
-            // Token::Le => "<=".to_string(),
-            // Token::Ge => ">=".to_string(),
+            Token::Neq => "!=".to_string(),
+            Token::Lt => "<".to_string(),
+            Token::Gt => ">".to_string(),
+            Token::Le => "<=".to_string(),
+            Token::Ge => ">=".to_string(),
             token => return Err(anyhow!("expected operator, found {token:?}")),
         };
 
-        let value = match self.bump()? {
-            Token::Str(val) => val,
-            Token::Number(num) => num.to_string(),
-            token => return Err(anyhow!("expected literal value, found {token:?}")),
+        let value = match self.parse_value()? {
+            Literal::Placeholder(_) => {
+                return Err(anyhow!("placeholders are not supported in HAVING"));
+            }
+            value => value,
+        };
+
+        Ok(Having::Comparison { item, op, value })
+    }
+
+    fn parse_order_by(&mut self) -> anyhow::Result<Vec<OrderBy>> {
+        if self.first() != Some(&Token::Order) {
+            return Ok(Vec::new());
+        }
+        self.bump()?;
+
+        match self.bump()? {
+            Token::By => (),
+            token => return Err(anyhow!("expected BY, found {token:?}")),
         };
 
-        Ok(Some(Where { column, op, value }))
+        self.clause = Some(Clause::OrderBy);
+        let mut order_by = Vec::new();
+        loop {
+            let column = match self.bump()? {
+                Token::Identifier(name) => name,
+                token => return Err(anyhow!("expected column name, found {token:?}")),
+            };
+
+            let descending = match self.first() {
+                Some(Token::Asc) => {
+                    self.bump()?;
+                    false
+                }
+                Some(Token::Desc) => {
+                    self.bump()?;
+                    true
+                }
+                _ => false,
+            };
+
+            order_by.push(OrderBy { column, descending });
+
+            if self.first() != Some(&Token::Comma) {
+                break;
+            }
+            self.bump()?;
+        }
+
+        Ok(order_by)
+    }
+
+    fn parse_count(&mut self, keyword: Token) -> anyhow::Result<Option<u64>> {
+        if self.first() != Some(&keyword) {
+            return Ok(None);
+        }
+        self.bump()?;
+
+        match self.bump()? {
+            // Fragments take their counts as i64.
+            Token::Int(num) if i64::try_from(num).is_ok() => Ok(Some(num)),
+            token => Err(anyhow!(
+                "expected a non-negative integer after {keyword:?}, found {token:?}"
+            )),
+        }
     }
 
     fn parse_zones(&mut self) -> anyhow::Result<Option<Vec<String>>> {
         match self.first() {
             Some(token) if *token == Token::Semicolon => return Ok(None),
+            Some(token) if *token == Token::RightParen => return Ok(None),
             Some(token) if *token == Token::Zone => self.bump()?,
-            Some(token) => return Err(anyhow!("expected 'ZONE' found {token:?}")),
+            Some(_) => {
+                let token = self.bump()?;
+                return Err(anyhow!("expected 'ZONE' found {token:?}"));
+            }
             None => return Ok(None),
         };
 
@@ -285,23 +1948,424 @@ impl Parser {
         };
 
         let mut zones = Vec::new();
+        if self.first() == Some(&Token::RightBracket) {
+            self.bump()?;
+            return Ok(Some(zones));
+        }
         loop {
             match self.bump()? {
                 Token::Str(val) => zones.push(val),
-                Token::Number(num) => zones.push(num.to_string()),
-                Token::RightBracket => break,
+                Token::Int(num) => zones.push(num.to_string()),
                 token => return Err(anyhow!("expected literal value, found {token:?}")),
             };
+
+            match self.bump()? {
+                Token::Comma => (),
+                Token::RightBracket => break,
+                token => return Err(anyhow!("expected ',' or ']', found {token:?}")),
+            }
         }
 
         Ok(Some(zones))
     }
 
     fn bump(&mut self) -> anyhow::Result<Token> {
-        self.tokens.pop().ok_or(anyhow!("bad query"))
+        let (token, span) = self
+            .tokens
+            .pop()
+            .ok_or(anyhow!("unexpected end of query"))?;
+        self.span = span;
+        match &token {
+            Token::Identifier(name) => self.name(name.clone(), span),
+            Token::Placeholder(idx) => self.name(format!("${idx}"), span),
+            _ => (),
+        }
+
+        Ok(token)
+    }
+
+    fn name(&mut self, name: String, span: Span) {
+        self.names.push(Occurrence {
+            name,
+            clause: self.clause,
+            span,
+        });
     }
 
     fn first(&mut self) -> Option<&Token> {
-        self.tokens.last()
+        self.tokens.last().map(|(token, _)| token)
+    }
+
+    fn second(&self) -> Option<&Token> {
+        self.tokens.iter().nth_back(1).map(|(token, _)| token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds random statements from a fixed seed, so a failure shows the
+    /// same SQL on every run.
+    struct Generator(u64);
+
+    impl Generator {
+        fn below(&mut self, n: usize) -> usize {
+            // xorshift64
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn chance(&mut self) -> bool {
+            self.below(2) == 0
+        }
+
+        fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+            items[self.below(items.len())].clone()
+        }
+
+        fn list<T>(&mut self, max: usize, mut item: impl FnMut(&mut Self) -> T) -> Vec<T> {
+            let len = 1 + self.below(max);
+            (0..len).map(|_| item(self)).collect()
+        }
+
+        /// Plain names and names that only read back quoted.
+        fn name(&mut self) -> String {
+            self.pick(&[
+                "Nombre",
+                "clientes",
+                "c2",
+                "_id",
+                "t.Estado",
+                "first name",
+                "select",
+                "Order",
+                "say \"hi\"",
+                "año",
+            ])
+            .to_string()
+        }
+
+        fn zones(&mut self) -> Option<Vec<String>> {
+            self.chance()
+                .then(|| self.list(3, |g| g.pick(&["Norte", "Centro", "Sur"]).to_string()))
+        }
+
+        /// A value written in the query, which HAVING needs.
+        fn constant(&mut self) -> Literal {
+            match self.below(4) {
+                0 => Literal::Int(self.pick(&[0, 1, -1, 42, i64::MAX, i64::MIN])),
+                1 => Literal::Float(self.pick(&[0.5, -0.5, 1.0, -2.0, 1e300, -1e-300, 3.25])),
+                2 => Literal::Str(self.pick(&["", "Sonora", "it's", "50%", "--"]).to_string()),
+                _ => Literal::Bool(self.chance()),
+            }
+        }
+
+        /// A value to compare with, which is never NULL.
+        fn literal(&mut self) -> Literal {
+            if self.below(5) == 0 {
+                Literal::Placeholder(1 + self.below(3))
+            } else {
+                self.constant()
+            }
+        }
+
+        fn value(&mut self) -> Literal {
+            if self.below(6) == 0 {
+                Literal::Null
+            } else {
+                self.literal()
+            }
+        }
+
+        fn expr(&mut self, depth: usize) -> Expr {
+            match self.below(if depth == 0 { 2 } else { 4 }) {
+                0 => Expr::Column(self.name()),
+                1 => Expr::Literal(self.value()),
+                _ => Expr::Binary {
+                    op: self.pick(&[
+                        BinaryOp::Add,
+                        BinaryOp::Sub,
+                        BinaryOp::Mul,
+                        BinaryOp::Div,
+                        BinaryOp::Concat,
+                    ]),
+                    left: Box::new(self.expr(depth - 1)),
+                    right: Box::new(self.expr(depth - 1)),
+                },
+            }
+        }
+
+        fn aggregate(&mut self) -> SelectItem {
+            let function = self.pick(&[
+                Aggregate::Count,
+                Aggregate::Sum,
+                Aggregate::Avg,
+                Aggregate::Min,
+                Aggregate::Max,
+            ]);
+            let column = if function == Aggregate::Count && self.chance() {
+                None
+            } else {
+                Some(self.name())
+            };
+            SelectItem::Aggregate { function, column }
+        }
+
+        fn select_item(&mut self) -> SelectItem {
+            match self.below(4) {
+                0 => SelectItem::Wildcard,
+                1 => SelectItem::Column(self.name()),
+                2 => self.aggregate(),
+                // A lone column reads back as a column item.
+                _ => match self.expr(2) {
+                    Expr::Column(column) => SelectItem::Column(column),
+                    expr => SelectItem::Expression(expr),
+                },
+            }
+        }
+
+        fn op(&mut self) -> String {
+            self.pick(&["=", "!=", "<", ">", "<=", ">="]).to_string()
+        }
+
+        fn filter(&mut self, depth: usize) -> Where {
+            let column = self.name();
+            match self.below(if depth == 0 { 6 } else { 11 }) {
+                0 => Where::Comparison {
+                    column,
+                    op: self.op(),
+                    value: Operand::Column(self.name()),
+                },
+                1 => Where::Comparison {
+                    column,
+                    op: self.op(),
+                    value: Operand::Value(self.literal()),
+                },
+                2 => Where::In {
+                    column,
+                    values: self.list(3, Self::literal),
+                },
+                3 => Where::Between {
+                    column,
+                    low: self.literal(),
+                    high: self.literal(),
+                },
+                4 => Where::Like {
+                    column,
+                    pattern: Literal::Str(self.pick(&["A%", "_b", "it''s"]).to_string()),
+                },
+                5 => Where::IsNull { column },
+                6 => Where::And(
+                    Box::new(self.filter(depth - 1)),
+                    Box::new(self.filter(depth - 1)),
+                ),
+                7 => Where::Or(
+                    Box::new(self.filter(depth - 1)),
+                    Box::new(self.filter(depth - 1)),
+                ),
+                8 => Where::Not(Box::new(self.filter(depth - 1))),
+                9 => Where::InSelect {
+                    column,
+                    query: Box::new(self.select(depth - 1)),
+                },
+                _ => Where::Exists(Box::new(self.select(depth - 1))),
+            }
+        }
+
+        fn having(&mut self, depth: usize) -> Having {
+            match self.below(if depth == 0 { 1 } else { 4 }) {
+                0 => Having::Comparison {
+                    item: if self.chance() {
+                        self.aggregate()
+                    } else {
+                        SelectItem::Column(self.name())
+                    },
+                    op: self.op(),
+                    value: self.constant(),
+                },
+                1 => Having::And(
+                    Box::new(self.having(depth - 1)),
+                    Box::new(self.having(depth - 1)),
+                ),
+                2 => Having::Or(
+                    Box::new(self.having(depth - 1)),
+                    Box::new(self.having(depth - 1)),
+                ),
+                _ => Having::Not(Box::new(self.having(depth - 1))),
+            }
+        }
+
+        fn select(&mut self, depth: usize) -> Query {
+            let fields = self.list(3, Self::select_item);
+            let aliases = fields
+                .iter()
+                .map(|item| (*item != SelectItem::Wildcard && self.chance()).then(|| self.name()))
+                .collect();
+            Query::Select {
+                table: self.name(),
+                joins: if self.chance() {
+                    Vec::new()
+                } else {
+                    self.list(2, |g| Join {
+                        table: g.name(),
+                        left: g.name(),
+                        right: g.name(),
+                    })
+                },
+                fields,
+                aliases,
+                distinct: self.chance(),
+                filter: self.chance().then(|| self.filter(depth)),
+                group_by: if self.chance() {
+                    Vec::new()
+                } else {
+                    self.list(2, Self::name)
+                },
+                having: self.chance().then(|| self.having(depth)),
+                order_by: if self.chance() {
+                    Vec::new()
+                } else {
+                    self.list(2, |g| OrderBy {
+                        column: g.name(),
+                        descending: g.chance(),
+                    })
+                },
+                limit: self.chance().then(|| self.pick(&[0, 10, i64::MAX as u64])),
+                offset: self.chance().then(|| self.pick(&[0, 5])),
+                zones: self.zones(),
+            }
+        }
+
+        fn write(&mut self, depth: usize) -> Query {
+            match self.below(4) {
+                0 => self.select(depth),
+                1 => {
+                    let columns = self.list(3, Self::name);
+                    let values = self.list(2, |g| (0..columns.len()).map(|_| g.value()).collect());
+                    Query::Insert {
+                        table: self.name(),
+                        columns,
+                        values,
+                    }
+                }
+                2 => Query::Update {
+                    table: self.name(),
+                    assignments: self.list(2, |g| (g.name(), g.expr(2))),
+                    filter: self.chance().then(|| self.filter(depth)),
+                    zones: self.zones(),
+                },
+                _ => Query::Delete {
+                    table: self.name(),
+                    filter: self.chance().then(|| self.filter(depth)),
+                    zones: self.zones(),
+                },
+            }
+        }
+
+        fn r#type(&mut self) -> String {
+            self.pick(&["int", "float", "string", "bool"]).to_string()
+        }
+
+        fn query(&mut self) -> Query {
+            match self.below(10) {
+                0..=3 => self.write(2),
+                4 => Query::CreateTable {
+                    table: self.name(),
+                    columns: self.list(3, |g| (g.name(), g.r#type())),
+                    fragments: self.list(2, |g| FragmentDef {
+                        name: g.name(),
+                        manager: g.pick(&["postgres", "mongo", "neo4j"]).to_string(),
+                        zone: g.pick(&["Norte", "Centro", "Sur"]).to_string(),
+                        columns: g.list(3, |g| (g.name(), g.name())),
+                    }),
+                },
+                5 => Query::AlterTable {
+                    table: self.name(),
+                    alteration: match self.below(3) {
+                        0 => Alteration::AddColumn {
+                            column: self.name(),
+                            r#type: self.r#type(),
+                        },
+                        1 => Alteration::DropColumn {
+                            column: self.name(),
+                        },
+                        _ => Alteration::RenameColumn {
+                            column: self.name(),
+                            name: self.name(),
+                        },
+                    },
+                },
+                6 => Query::DropTable { table: self.name() },
+                7 => Query::Explain {
+                    query: Box::new(self.write(1)),
+                    analyze: self.chance(),
+                },
+                8 => Query::Catalog(match self.below(4) {
+                    0 => Catalog::Tables,
+                    1 => Catalog::Columns { table: self.name() },
+                    2 => Catalog::Fragments {
+                        table: self.chance().then(|| self.name()),
+                    },
+                    _ => Catalog::Zones,
+                }),
+                _ => self.pick(&[Query::Begin, Query::Commit, Query::Rollback]),
+            }
+        }
+    }
+
+    #[test]
+    fn printed_statements_parse_back() {
+        let mut generator = Generator(0x5eed);
+        for _ in 0..5000 {
+            let query = generator.query();
+            let sql = query.to_string();
+            match crate::parse_query(&sql) {
+                Ok(parsed) => assert_eq!(parsed, query, "{sql}"),
+                Err(e) => panic!("{sql}\n{e}"),
+            }
+        }
+    }
+
+    #[test]
+    fn negative_numbers_are_one_literal() {
+        let filter = |sql: &str| match crate::parse_query(sql) {
+            Ok(Query::Select { filter, .. }) => filter,
+            result => panic!("{result:?}"),
+        };
+        let value = |literal| {
+            Some(Where::Comparison {
+                column: "a".to_string(),
+                op: "=".to_string(),
+                value: Operand::Value(literal),
+            })
+        };
+
+        assert_eq!(
+            filter("SELECT * FROM t WHERE a = -9223372036854775808;"),
+            value(Literal::Int(i64::MIN))
+        );
+        assert_eq!(
+            filter("SELECT * FROM t WHERE a = -1.5;"),
+            value(Literal::Float(-1.5))
+        );
+        assert!(crate::parse_query("SELECT * FROM t WHERE a = 9223372036854775808;").is_err());
+        assert!(crate::parse_query("SELECT * FROM t WHERE a = -9223372036854775809;").is_err());
+
+        let Ok(Query::Select { fields, .. }) =
+            crate::parse_query("SELECT -9223372036854775808 * a FROM t;")
+        else {
+            panic!()
+        };
+        assert_eq!(
+            fields,
+            [SelectItem::Expression(Expr::Binary {
+                op: BinaryOp::Mul,
+                left: Box::new(Expr::Literal(Literal::Int(i64::MIN))),
+                right: Box::new(Expr::Column("a".to_string())),
+            })]
+        );
     }
 }
@@ -0,0 +1,168 @@
+use anyhow::anyhow;
+
+use super::{Expr, Literal, Operand, Query, SelectItem, Value, Where};
+
+/// A statement parsed and checked once, run again with new values for its
+/// placeholders.
+#[derive(Debug, Clone)]
+pub struct Prepared {
+    query: Query,
+    types: Vec<String>,
+}
+
+impl Prepared {
+    pub(crate) fn new(query: Query, types: Vec<String>) -> Self {
+        Self { query, types }
+    }
+
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Types of the placeholders, `$1` first.
+    pub fn types(&self) -> &[String] {
+        &self.types
+    }
+
+    /// The statement with each placeholder replaced by its value in `params`.
+    ///
+    /// NULL can only be bound as a whole INSERT value or SET value, a
+    /// comparison with NULL is never true and needs `IS NULL` instead.
+    pub fn bind(&self, params: &[Value]) -> anyhow::Result<Query> {
+        if params.len() != self.types.len() {
+            return Err(anyhow!(
+                "expected {} parameters found {}",
+                self.types.len(),
+                params.len()
+            ));
+        }
+
+        for (idx, (r#type, value)) in self.types.iter().zip(params).enumerate() {
+            let matches = match (r#type.as_str(), value) {
+                (_, Value::Null) => true,
+                ("int", Value::Int(_)) => true,
+                ("float", Value::Int(_) | Value::Float(_)) => true,
+                ("string", Value::Str(_)) => true,
+                ("bool", Value::Bool(_)) => true,
+                _ => false,
+            };
+            if !matches {
+                return Err(anyhow!(
+                    "placeholder ${} expects {type}, found {value:?}",
+                    idx + 1
+                ));
+            }
+        }
+
+        let mut query = self.query.clone();
+        bind_query(&mut query, params)?;
+        Ok(query)
+    }
+}
+
+fn bind_query(query: &mut Query, params: &[Value]) -> anyhow::Result<()> {
+    match query {
+        Query::Select { fields, filter, .. } => {
+            for item in fields {
+                if let SelectItem::Expression(expr) = item {
+                    bind_expr(expr, params)?;
+                }
+            }
+            bind_filter(filter, params)?;
+        }
+        Query::Insert { values, .. } => {
+            for value in values.iter_mut().flatten() {
+                if let Literal::Placeholder(idx) = value {
+                    *value = Literal::from_value(&params[*idx - 1]);
+                }
+            }
+        }
+        Query::Update {
+            assignments,
+            filter,
+            ..
+        } => {
+            for (_, value) in assignments {
+                match value {
+                    Expr::Literal(Literal::Placeholder(idx)) if params[*idx - 1] == Value::Null => {
+                        *value = Expr::Literal(Literal::Null);
+                    }
+                    expr => bind_expr(expr, params)?,
+                }
+            }
+            bind_filter(filter, params)?;
+        }
+        Query::Delete { filter, .. } => bind_filter(filter, params)?,
+        Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } => (),
+        Query::Explain { query, .. } => bind_query(query, params)?,
+        Query::Catalog(_) | Query::Begin | Query::Commit | Query::Rollback => (),
+    }
+
+    Ok(())
+}
+
+fn bind_filter(filter: &mut Option<Where>, params: &[Value]) -> anyhow::Result<()> {
+    match filter {
+        Some(filter) => bind_where(filter, params),
+        None => Ok(()),
+    }
+}
+
+fn bind_where(filter: &mut Where, params: &[Value]) -> anyhow::Result<()> {
+    match filter {
+        Where::Comparison {
+            value: Operand::Value(value),
+            ..
+        } => bind_literal(value, params)?,
+        Where::Comparison { .. } | Where::IsNull { .. } => (),
+        Where::In { values, .. } => {
+            for value in values {
+                bind_literal(value, params)?;
+            }
+        }
+        Where::Between { low, high, .. } => {
+            bind_literal(low, params)?;
+            bind_literal(high, params)?;
+        }
+        Where::Like { pattern, .. } => bind_literal(pattern, params)?,
+        Where::And(left, right) | Where::Or(left, right) => {
+            bind_where(left, params)?;
+            bind_where(right, params)?;
+        }
+        Where::Not(inner) => bind_where(inner, params)?,
+        Where::InSelect { query, .. } | Where::Exists(query) => bind_query(query, params)?,
+    }
+
+    Ok(())
+}
+
+fn bind_literal(literal: &mut Literal, params: &[Value]) -> anyhow::Result<()> {
+    if let Literal::Placeholder(idx) = literal {
+        *literal = match &params[*idx - 1] {
+            Value::Null => return Err(anyhow!("can't bind NULL to ${idx}, use IS NULL")),
+            param => Literal::from_value(param),
+        };
+    }
+
+    Ok(())
+}
+
+fn bind_expr(expr: &mut Expr, params: &[Value]) -> anyhow::Result<()> {
+    match expr {
+        Expr::Literal(Literal::Placeholder(idx)) => {
+            *expr = match &params[*idx - 1] {
+                Value::Null => {
+                    return Err(anyhow!("can't bind NULL to ${idx} inside an expression"));
+                }
+                param => Expr::Literal(Literal::from_value(param)),
+            };
+        }
+        Expr::Binary { left, right, .. } => {
+            bind_expr(left, params)?;
+            bind_expr(right, params)?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,185 @@
+use super::{Literal, Query, Value, Where};
+
+enum Resolved {
+    Constant(bool),
+    Filter(Where),
+}
+
+/// `query` with every subquery of its filter replaced by the rows `run`
+/// returns for it, `None` when the filter can never match.
+///
+/// `IN (SELECT ...)` becomes an IN list the fragments can evaluate and
+/// `EXISTS` becomes a constant that is folded away.
+pub fn resolve_subqueries<F>(query: &Query, mut run: F) -> anyhow::Result<Option<Query>>
+where
+    F: FnMut(&Query) -> anyhow::Result<Vec<Vec<Value>>>,
+{
+    let mut query = query.clone();
+    let (Query::Select { filter, .. }
+    | Query::Update { filter, .. }
+    | Query::Delete { filter, .. }) = &mut query
+    else {
+        return Ok(Some(query));
+    };
+
+    if let Some(condition) = filter {
+        match resolve(condition, false, &mut run)? {
+            Resolved::Constant(true) => *filter = None,
+            Resolved::Constant(false) => return Ok(None),
+            Resolved::Filter(condition) => *filter = Some(condition),
+        }
+    }
+
+    Ok(Some(query))
+}
+
+/// `negated` tells whether `filter` sits under an odd number of NOTs.
+///
+/// A subquery returning NULL leaves its predicate UNKNOWN when nothing else
+/// matches. The row is only kept when the whole filter is true, so UNKNOWN
+/// acts as false outside a NOT and as true inside one.
+fn resolve<F>(filter: &Where, negated: bool, run: &mut F) -> anyhow::Result<Resolved>
+where
+    F: FnMut(&Query) -> anyhow::Result<Vec<Vec<Value>>>,
+{
+    Ok(match filter {
+        Where::InSelect { column, query } => {
+            let mut values = Vec::new();
+            let mut null = false;
+            for row in run(query)? {
+                match row.into_iter().next() {
+                    Some(Value::Null) | None => null = true,
+                    Some(value) => values.push(value),
+                }
+            }
+            values.sort();
+            values.dedup();
+
+            if null && negated {
+                Resolved::Constant(true)
+            } else if values.is_empty() {
+                Resolved::Constant(false)
+            } else {
+                Resolved::Filter(Where::In {
+                    column: column.clone(),
+                    values: values.iter().map(Literal::from_value).collect(),
+                })
+            }
+        }
+        Where::Exists(query) => {
+            // One row is enough to know the answer.
+            let mut query = (**query).clone();
+            if let Query::Select { limit, .. } = &mut query {
+                *limit = Some(limit.map_or(1, |limit| limit.min(1)));
+            }
+            Resolved::Constant(!run(&query)?.is_empty())
+        }
+        Where::And(left, right) => {
+            match (resolve(left, negated, run)?, resolve(right, negated, run)?) {
+                (Resolved::Constant(false), _) | (_, Resolved::Constant(false)) => {
+                    Resolved::Constant(false)
+                }
+                (Resolved::Constant(true), other) | (other, Resolved::Constant(true)) => other,
+                (Resolved::Filter(left), Resolved::Filter(right)) => {
+                    Resolved::Filter(Where::And(Box::new(left), Box::new(right)))
+                }
+            }
+        }
+        Where::Or(left, right) => {
+            match (resolve(left, negated, run)?, resolve(right, negated, run)?) {
+                (Resolved::Constant(true), _) | (_, Resolved::Constant(true)) => {
+                    Resolved::Constant(true)
+                }
+                (Resolved::Constant(false), other) | (other, Resolved::Constant(false)) => other,
+                (Resolved::Filter(left), Resolved::Filter(right)) => {
+                    Resolved::Filter(Where::Or(Box::new(left), Box::new(right)))
+                }
+            }
+        }
+        Where::Not(inner) => match resolve(inner, !negated, run)? {
+            Resolved::Constant(value) => Resolved::Constant(!value),
+            Resolved::Filter(inner) => Resolved::Filter(Where::Not(Box::new(inner))),
+        },
+        filter => Resolved::Filter(filter.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Resolves the subqueries of `sql` as if each of them returned `rows`.
+    fn resolve_with(sql: &str, rows: Vec<Vec<Value>>) -> Option<Query> {
+        let query = crate::parse_query(sql).unwrap();
+        resolve_subqueries(&query, |_| Ok(rows.clone())).unwrap()
+    }
+
+    fn filter(query: Option<Query>) -> Option<Where> {
+        match query {
+            Some(Query::Select { filter, .. }) => filter,
+            query => panic!("expected a SELECT, found {query:?}"),
+        }
+    }
+
+    #[test]
+    fn in_select_becomes_an_in_list_without_null() {
+        let query = resolve_with(
+            "SELECT Nombre FROM clientes WHERE IdCliente IN (SELECT IdCliente FROM clientes);",
+            vec![
+                vec![Value::Int(2)],
+                vec![Value::Null],
+                vec![Value::Int(1)],
+                vec![Value::Int(2)],
+            ],
+        );
+        assert_eq!(
+            filter(query),
+            Some(Where::In {
+                column: "IdCliente".into(),
+                values: vec![Literal::Int(1), Literal::Int(2)],
+            })
+        );
+    }
+
+    #[test]
+    fn not_in_a_subquery_returning_null_never_matches() {
+        let sql =
+            "SELECT Nombre FROM clientes WHERE IdCliente NOT IN (SELECT IdCliente FROM clientes);";
+        assert_eq!(
+            resolve_with(sql, vec![vec![Value::Int(1)], vec![Value::Null]]),
+            None
+        );
+
+        // Without NULL it is an ordinary NOT IN.
+        let query = resolve_with(sql, vec![vec![Value::Int(1)]]);
+        assert_eq!(
+            filter(query),
+            Some(Where::Not(Box::new(Where::In {
+                column: "IdCliente".into(),
+                values: vec![Literal::Int(1)],
+            })))
+        );
+    }
+
+    #[test]
+    fn not_in_an_empty_subquery_always_matches() {
+        let query = resolve_with(
+            "SELECT Nombre FROM clientes WHERE IdCliente NOT IN (SELECT IdCliente FROM clientes);",
+            Vec::new(),
+        );
+        assert_eq!(filter(query), None);
+    }
+
+    #[test]
+    fn exists_is_folded_into_the_filter() {
+        let sql = "SELECT Nombre FROM clientes \
+                   WHERE Deuda > 10 AND EXISTS (SELECT IdCliente FROM clientes);";
+        assert_eq!(resolve_with(sql, Vec::new()), None);
+
+        let query = resolve_with(sql, vec![vec![Value::Int(1)]]);
+        assert!(matches!(
+            filter(query),
+            Some(Where::Comparison { column, .. }) if column == "Deuda"
+        ));
+    }
+}
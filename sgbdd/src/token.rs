@@ -5,16 +5,33 @@ pub enum Token {
     LeftBracket,
     RightBracket,
     Semicolon,
+    Comma,
     Star,
+    Plus,
+    Minus,
+    Slash,
+    Concat,
     Equal,
+    Neq,
+    Lt,
+    Gt,
+    Le,
+    Ge,
 
     Identifier(String),
     Str(String),
-    Number(f64),
+    /// Unsigned, a '-' before it is applied by the parser so `i64::MIN` can
+    /// be written.
+    Int(u64),
+    Float(f64),
+    /// `$n` or `?`, numbered from 1.
+    Placeholder(usize),
 
     True,
     False,
     Select,
+    Distinct,
+    As,
     Insert,
     Into,
     Values,
@@ -22,8 +39,44 @@ pub enum Token {
     Set,
     Delete,
     From,
+    Join,
+    Inner,
+    On,
     Where,
     Zone,
+    And,
+    Or,
+    Not,
+    In,
+    Exists,
+    Between,
+    Like,
+    Is,
+    Null,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Limit,
+    Offset,
+    Group,
+    Having,
+    Create,
+    Table,
+    Fragment,
+    Alter,
+    Add,
+    Drop,
+    Rename,
+    Column,
+    To,
+    Explain,
+    Analyze,
+    Show,
+    Describe,
+    Begin,
+    Commit,
+    Rollback,
 
     Eof,
 }
@@ -1,5 +1,16 @@
-use super::{Config, Query, parser::Where};
-use std::collections::HashSet;
+use super::{
+    Config, Query,
+    config::{Field, Table},
+    ddl,
+    diagnostic::{Clause, clause_error, name_error},
+    join,
+    names::{resolve_names, same_name},
+    parser::{
+        Aggregate, BinaryOp, Catalog, Expr, Having, Join, Literal, Operand, OrderBy, SelectItem,
+        Where,
+    },
+};
+use std::collections::{BTreeMap, HashSet};
 
 use anyhow::anyhow;
 
@@ -27,6 +38,28 @@ pub fn validate_config(config: &Config) -> bool {
         })
         .collect();
 
+    // Names are case-insensitive, so they must differ in more than their case.
+    let clashes = |names: &[&str]| {
+        names
+            .iter()
+            .enumerate()
+            .any(|(idx, name)| names[idx + 1..].iter().any(|other| same_name(name, other)))
+    };
+    let tables: Vec<_> = config.tables.iter().map(|table| &*table.name).collect();
+    if clashes(&tables)
+        || config.tables.iter().any(|table| {
+            clashes(
+                &table
+                    .fields
+                    .iter()
+                    .map(|field| &*field.name)
+                    .collect::<Vec<_>>(),
+            )
+        })
+    {
+        return true;
+    }
+
     fields_per_table
         .iter()
         .zip(&fragments_fields_per_table)
@@ -37,17 +70,43 @@ pub fn validate_config(config: &Config) -> bool {
         })
 }
 
-pub fn check_query(query: &Query, config: &Config) -> anyhow::Result<()> {
+/// Writes the names of `query` the way the schema does, see
+/// [`resolve_names`], and checks it against the schema.
+pub fn check_query(query: &mut Query, config: &Config) -> anyhow::Result<()> {
+    resolve_names(query, config);
+    let query = &*query;
+    check_unbound(query, config)?;
+    if !placeholder_types(query, config)?.is_empty() {
+        return Err(name_error("$1", "placeholders need a prepared statement"));
+    }
+
+    Ok(())
+}
+
+/// Checks `query` leaving its placeholders unbound.
+pub(crate) fn check_unbound(query: &Query, config: &Config) -> anyhow::Result<()> {
     match query {
+        Query::Select { joins, .. } if !joins.is_empty() => check_join(query, config)?,
         Query::Select {
             table,
             fields,
             filter,
+            group_by,
+            having,
+            order_by,
             ..
         } => {
             check_table(table, config)?;
-            check_fields(table, fields, config)?;
-            check_filter(table, filter, config)?;
+            let table = config
+                .tables
+                .iter()
+                .find(|t| t.name == *table)
+                .ok_or_else(|| name_error(table, format!("table '{table}' not found")))?;
+            check_select(table, fields, group_by, having)?;
+            if let Some(filter) = filter {
+                check_predicate(table, filter, config)?;
+            }
+            check_order_by(table, fields, order_by)?;
         }
         Query::Insert {
             table,
@@ -63,6 +122,7 @@ pub fn check_query(query: &Query, config: &Config) -> anyhow::Result<()> {
                     values.len()
                 ));
             }
+            check_values(table, columns, values, config)?;
         }
         Query::Update {
             table,
@@ -78,21 +138,267 @@ pub fn check_query(query: &Query, config: &Config) -> anyhow::Result<()> {
             check_table(table, config)?;
             check_filter(table, filter, config)?;
         }
+        Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } => {
+            ddl::new_schema(query, config)?;
+        }
+        Query::Explain { query, .. } => match **query {
+            Query::Select { .. }
+            | Query::Insert { .. }
+            | Query::Update { .. }
+            | Query::Delete { .. } => check_unbound(query, config)?,
+            _ => return Err(anyhow!("EXPLAIN takes a SELECT, INSERT, UPDATE or DELETE")),
+        },
+        Query::Catalog(Catalog::Columns { table })
+        | Query::Catalog(Catalog::Fragments { table: Some(table) }) => {
+            check_table(table, config)?;
+        }
+        Query::Catalog(_) | Query::Begin | Query::Commit | Query::Rollback => (),
+    };
+
+    Ok(())
+}
+
+/// Types the placeholders of a checked `query` are bound with, `$1` first.
+pub(crate) fn placeholder_types(query: &Query, config: &Config) -> anyhow::Result<Vec<String>> {
+    let mut types = BTreeMap::new();
+    query_placeholders(query, config, &mut types)?;
+
+    let mut ordered = Vec::new();
+    for (idx, r#type) in types {
+        if idx != ordered.len() + 1 {
+            return Err(anyhow!("placeholder ${} is never used", ordered.len() + 1));
+        }
+        ordered.push(r#type);
+    }
+
+    Ok(ordered)
+}
+
+fn query_placeholders(
+    query: &Query,
+    config: &Config,
+    types: &mut BTreeMap<usize, String>,
+) -> anyhow::Result<()> {
+    let find_table = |name: &str| {
+        config
+            .tables
+            .iter()
+            .find(|table| table.name == name)
+            .ok_or_else(|| name_error(name, format!("table '{name}' not found")))
+    };
+
+    match query {
+        Query::Select { joins, .. } if !joins.is_empty() => {
+            let query = join::qualify(query, config)?;
+            let Query::Select {
+                table,
+                joins,
+                fields,
+                filter,
+                ..
+            } = &query
+            else {
+                unreachable!()
+            };
+            let table = join::joined_table(table, joins, config)?;
+            select_placeholders(&table, fields, filter, config, types)?;
+        }
+        Query::Select {
+            table,
+            fields,
+            filter,
+            ..
+        } => select_placeholders(find_table(table)?, fields, filter, config, types)?,
+        Query::Insert {
+            table,
+            columns,
+            values,
+        } => {
+            let table = find_table(table)?;
+            for row in values {
+                for (column, value) in columns.iter().zip(row) {
+                    if let Literal::Placeholder(idx) = value {
+                        add_placeholder(
+                            types,
+                            *idx,
+                            &find_field(table, column, Clause::Columns)?.r#type,
+                        )?;
+                    }
+                }
+            }
+        }
+        Query::Update {
+            table,
+            assignments,
+            filter,
+            ..
+        } => {
+            let table = find_table(table)?;
+            for (column, value) in assignments {
+                match value {
+                    Expr::Literal(Literal::Placeholder(idx)) => {
+                        add_placeholder(
+                            types,
+                            *idx,
+                            &find_field(table, column, Clause::Set)?.r#type,
+                        )?;
+                    }
+                    expr => expr_placeholders(table, expr, Clause::Set, types)?,
+                }
+            }
+            if let Some(filter) = filter {
+                where_placeholders(table, filter, config, types)?;
+            }
+        }
+        Query::Delete { table, filter, .. } => {
+            if let Some(filter) = filter {
+                where_placeholders(find_table(table)?, filter, config, types)?;
+            }
+        }
+        Query::CreateTable { .. } | Query::AlterTable { .. } | Query::DropTable { .. } => (),
+        Query::Explain { query, .. } => query_placeholders(query, config, types)?,
+        Query::Catalog(_) | Query::Begin | Query::Commit | Query::Rollback => (),
+    }
+
+    Ok(())
+}
+
+fn select_placeholders(
+    table: &Table,
+    fields: &[SelectItem],
+    filter: &Option<Where>,
+    config: &Config,
+    types: &mut BTreeMap<usize, String>,
+) -> anyhow::Result<()> {
+    for item in fields {
+        if let SelectItem::Expression(expr) = item {
+            expr_placeholders(table, expr, Clause::Select, types)?;
+        }
+    }
+    if let Some(filter) = filter {
+        where_placeholders(table, filter, config, types)?;
+    }
+
+    Ok(())
+}
+
+fn where_placeholders(
+    table: &Table,
+    filter: &Where,
+    config: &Config,
+    types: &mut BTreeMap<usize, String>,
+) -> anyhow::Result<()> {
+    match filter {
+        Where::Comparison {
+            column,
+            value: Operand::Value(Literal::Placeholder(idx)),
+            ..
+        } => add_placeholder(
+            types,
+            *idx,
+            &find_field(table, column, Clause::Where)?.r#type,
+        )?,
+        Where::Comparison { .. } | Where::IsNull { .. } => (),
+        Where::In { column, values } => {
+            for value in values {
+                if let Literal::Placeholder(idx) = value {
+                    add_placeholder(
+                        types,
+                        *idx,
+                        &find_field(table, column, Clause::Where)?.r#type,
+                    )?;
+                }
+            }
+        }
+        Where::Between { column, low, high } => {
+            for value in [low, high] {
+                if let Literal::Placeholder(idx) = value {
+                    add_placeholder(
+                        types,
+                        *idx,
+                        &find_field(table, column, Clause::Where)?.r#type,
+                    )?;
+                }
+            }
+        }
+        Where::Like { pattern, .. } => {
+            if let Literal::Placeholder(idx) = pattern {
+                add_placeholder(types, *idx, "string")?;
+            }
+        }
+        Where::And(left, right) | Where::Or(left, right) => {
+            where_placeholders(table, left, config, types)?;
+            where_placeholders(table, right, config, types)?;
+        }
+        Where::Not(inner) => where_placeholders(table, inner, config, types)?,
+        Where::InSelect { query, .. } | Where::Exists(query) => {
+            query_placeholders(query, config, types)?
+        }
+    }
+
+    Ok(())
+}
+
+fn expr_placeholders(
+    table: &Table,
+    expr: &Expr,
+    clause: Clause,
+    types: &mut BTreeMap<usize, String>,
+) -> anyhow::Result<()> {
+    match expr {
+        Expr::Literal(Literal::Placeholder(idx)) => Err(clause_error(
+            &format!("${idx}"),
+            clause,
+            format!("can't infer the type of placeholder ${idx}"),
+        )),
+        Expr::Binary { op, left, right } => {
+            for (operand, other) in [(left, right), (right, left)] {
+                match &**operand {
+                    Expr::Literal(Literal::Placeholder(idx)) => {
+                        let r#type = operand_type(table, *op, operand, other, clause)?;
+                        add_placeholder(types, *idx, r#type)?;
+                    }
+                    operand => expr_placeholders(table, operand, clause, types)?,
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A placeholder used as both int and float is bound as an int, which fits both.
+fn add_placeholder(
+    types: &mut BTreeMap<usize, String>,
+    idx: usize,
+    r#type: &str,
+) -> anyhow::Result<()> {
+    let r#type = match types.get(&idx).map(String::as_str) {
+        None => r#type,
+        Some(previous) if previous == r#type => return Ok(()),
+        Some("int" | "float") if matches!(r#type, "int" | "float") => "int",
+        Some(previous) => {
+            return Err(name_error(
+                &format!("${idx}"),
+                format!("placeholder ${idx} is used as both {previous} and {type}"),
+            ));
+        }
     };
+    types.insert(idx, r#type.to_string());
 
     Ok(())
 }
 
 fn check_assignments(
     table_name: &str,
-    assignments: &[(String, String)],
+    assignments: &[(String, Expr)],
     config: &Config,
 ) -> anyhow::Result<()> {
     let table = config
         .tables
         .iter()
         .find(|table| table.name == table_name)
-        .ok_or_else(|| anyhow!("table '{table_name}' not found"))?;
+        .ok_or_else(|| name_error(table_name, format!("table '{table_name}' not found")))?;
 
     let fields: Vec<_> = assignments.iter().map(|assignment| &assignment.0).collect();
     let table_fields: Vec<_> = table.fields.iter().map(|field| &field.name).collect();
@@ -102,14 +408,37 @@ fn check_assignments(
         .filter(|field| !table_fields.contains(field))
         .collect();
 
-    if missing.is_empty() {
-        Ok(())
-    } else {
-        Err(anyhow::anyhow!(
-            "missing fields {:?} in table '{table_name}'",
-            missing
-        ))
+    if let Some(first) = missing.first() {
+        return Err(clause_error(
+            first,
+            Clause::Set,
+            format!("missing fields {missing:?} in table '{table_name}'"),
+        ));
+    }
+
+    for (column, value) in assignments {
+        // NULL fits any column and a lone placeholder is bound with the type of the column.
+        if matches!(
+            value,
+            Expr::Literal(Literal::Null | Literal::Placeholder(_))
+        ) {
+            continue;
+        }
+        let field = find_field(table, column, Clause::Set)?;
+        let r#type = check_expr(table, value, Clause::Set)?;
+        if r#type != field.r#type && !(r#type == "int" && field.r#type == "float") {
+            return Err(clause_error(
+                column,
+                Clause::Set,
+                format!(
+                    "can't assign a {type} expression to {} field '{column}'",
+                    field.r#type
+                ),
+            ));
+        }
     }
+
+    Ok(())
 }
 
 fn check_filter(table_name: &str, filter: &Option<Where>, config: &Config) -> anyhow::Result<()> {
@@ -118,12 +447,516 @@ fn check_filter(table_name: &str, filter: &Option<Where>, config: &Config) -> an
             .tables
             .iter()
             .find(|table| table.name == table_name)
-            .ok_or_else(|| anyhow!("table '{table_name}' not found"))?;
+            .ok_or_else(|| name_error(table_name, format!("table '{table_name}' not found")))?;
+
+        check_predicate(table, filter, config)?;
+    }
+
+    Ok(())
+}
+
+fn check_predicate(table: &Table, filter: &Where, config: &Config) -> anyhow::Result<()> {
+    match filter {
+        Where::Comparison { column, op, value } => {
+            let field = find_field(table, column, Clause::Where)?;
+
+            let value = match value {
+                Operand::Column(other) => {
+                    find_field(table, other, Clause::Where)?;
+                    return Ok(());
+                }
+                Operand::Value(value) => value,
+            };
+
+            if op != "=" && op != "!=" {
+                if field.r#type == "bool" {
+                    return Err(clause_error(
+                        column,
+                        Clause::Where,
+                        format!("operator '{op}' not supported for bool field '{column}'"),
+                    ));
+                }
+            }
+            check_literal(field, value)?;
+        }
+        Where::And(left, right) | Where::Or(left, right) => {
+            check_predicate(table, left, config)?;
+            check_predicate(table, right, config)?;
+        }
+        Where::Not(inner) => check_predicate(table, inner, config)?,
+        Where::In { column, values } => {
+            let field = find_field(table, column, Clause::Where)?;
+            for value in values {
+                check_literal(field, value)?;
+            }
+        }
+        Where::Between { column, low, high } => {
+            let field = find_field(table, column, Clause::Where)?;
+            if field.r#type == "bool" {
+                return Err(clause_error(
+                    column,
+                    Clause::Where,
+                    format!("BETWEEN not supported for bool field '{column}'"),
+                ));
+            }
+            check_literal(field, low)?;
+            check_literal(field, high)?;
+        }
+        Where::IsNull { column } => {
+            find_field(table, column, Clause::Where)?;
+        }
+        Where::InSelect { column, query } => {
+            let field = find_field(table, column, Clause::Where)?;
+            check_unbound(query, config)?;
+            let r#type = subquery_type(query, config)?;
+            if !comparable(&field.r#type, &r#type) {
+                return Err(clause_error(
+                    column,
+                    Clause::Where,
+                    format!(
+                        "can't compare {} field '{column}' with a subquery returning {type}",
+                        field.r#type
+                    ),
+                ));
+            }
+        }
+        Where::Exists(query) => check_unbound(query, config)?,
+        Where::Like { column, .. } => {
+            let field = find_field(table, column, Clause::Where)?;
+            if field.r#type != "string" {
+                return Err(clause_error(
+                    column,
+                    Clause::Where,
+                    format!(
+                        "LIKE requires a string field, '{column}' is {}",
+                        field.r#type
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn comparable(left: &str, right: &str) -> bool {
+    let numeric = |r#type: &str| matches!(r#type, "int" | "float");
+    left == right || numeric(left) && numeric(right)
+}
+
+/// Type of the single column an `IN (SELECT ...)` subquery returns.
+fn subquery_type(query: &Query, config: &Config) -> anyhow::Result<String> {
+    let Query::Select {
+        table,
+        joins,
+        fields,
+        ..
+    } = query
+    else {
+        unreachable!("subqueries are always SELECT")
+    };
+    if fields.len() != 1 || fields[0] == SelectItem::Wildcard {
+        return Err(anyhow!("a subquery in IN must select exactly one column"));
+    }
+
+    let (table, item) = if joins.is_empty() {
+        let table = config
+            .tables
+            .iter()
+            .find(|t| t.name == *table)
+            .ok_or_else(|| name_error(table, format!("table '{table}' not found")))?
+            .clone();
+        (table, fields[0].clone())
+    } else {
+        let Query::Select { fields, .. } = join::qualify(query, config)? else {
+            unreachable!()
+        };
+        (join::joined_table(table, joins, config)?, fields[0].clone())
+    };
+
+    item_type(&table, &item, Clause::Select)
+}
+
+/// Type of the values a checked select item produces.
+fn item_type(table: &Table, item: &SelectItem, clause: Clause) -> anyhow::Result<String> {
+    Ok(match item {
+        SelectItem::Aggregate {
+            function: Aggregate::Count,
+            ..
+        } => "int",
+        SelectItem::Aggregate {
+            function: Aggregate::Avg,
+            ..
+        } => "float",
+        SelectItem::Column(column)
+        | SelectItem::Aggregate {
+            column: Some(column),
+            ..
+        } => &find_field(table, column, clause)?.r#type,
+        SelectItem::Expression(expr) => check_expr(table, expr, clause)?,
+        SelectItem::Aggregate { column: None, .. } | SelectItem::Wildcard => unreachable!(),
+    }
+    .to_string())
+}
+
+/// A literal compared with `field` in WHERE must have a comparable type, placeholders
+/// are checked when they are bound.
+fn check_literal(field: &Field, value: &Literal) -> anyhow::Result<()> {
+    match value.r#type() {
+        Some(r#type) if !comparable(&field.r#type, r#type) => Err(clause_error(
+            &field.name,
+            Clause::Where,
+            format!(
+                "expected {} to compare with '{}', found {value}",
+                field.r#type, field.name
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// INSERT values must fit their column, an int is widened into a float column.
+fn check_values(
+    table_name: &str,
+    columns: &[String],
+    values: &[Vec<Literal>],
+    config: &Config,
+) -> anyhow::Result<()> {
+    let table = config
+        .tables
+        .iter()
+        .find(|table| table.name == table_name)
+        .ok_or_else(|| name_error(table_name, format!("table '{table_name}' not found")))?;
+
+    for row in values {
+        for (column, value) in columns.iter().zip(row) {
+            let field = find_field(table, column, Clause::Columns)?;
+            match value.r#type() {
+                Some(r#type)
+                    if r#type != field.r#type && !(r#type == "int" && field.r#type == "float") =>
+                {
+                    return Err(clause_error(
+                        column,
+                        Clause::Columns,
+                        format!(
+                            "can't insert {value} into {} field '{column}'",
+                            field.r#type
+                        ),
+                    ));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The field `column` of `table`, an error names the part of the statement
+/// it's written in, like "where clause".
+fn find_field<'a>(table: &'a Table, column: &str, clause: Clause) -> anyhow::Result<&'a Field> {
+    table
+        .fields
+        .iter()
+        .find(|field| field.name == column)
+        .ok_or_else(|| {
+            clause_error(
+                column,
+                clause,
+                format!("field '{column}' not found in {clause}"),
+            )
+        })
+}
+
+fn check_select(
+    table: &Table,
+    fields: &[SelectItem],
+    group_by: &[String],
+    having: &Option<Having>,
+) -> anyhow::Result<()> {
+    for column in group_by {
+        if !table.fields.iter().any(|field| field.name == *column) {
+            return Err(clause_error(
+                column,
+                Clause::GroupBy,
+                format!("field '{column}' not found in {}", Clause::GroupBy),
+            ));
+        }
+    }
+
+    let aggregated = !group_by.is_empty()
+        || having.is_some()
+        || fields
+            .iter()
+            .any(|item| matches!(item, SelectItem::Aggregate { .. }));
+
+    for item in fields {
+        check_item(table, item, group_by, aggregated, Clause::Select)?;
+    }
 
-        if !table.fields.iter().any(|field| field.name == filter.column) {
-            return Err(anyhow!(
-                "field '{}' not found in where clause",
-                filter.column
+    if let Some(having) = having {
+        check_having(table, having, group_by)?;
+    }
+
+    Ok(())
+}
+
+/// Joins are resolved by the coordinator, which only filters, projects and
+/// sorts the joined rows.
+fn check_join(query: &Query, config: &Config) -> anyhow::Result<()> {
+    let Query::Select {
+        fields,
+        group_by,
+        having,
+        ..
+    } = query
+    else {
+        unreachable!("only SELECT can join tables")
+    };
+    if !group_by.is_empty()
+        || having.is_some()
+        || fields
+            .iter()
+            .any(|item| matches!(item, SelectItem::Aggregate { .. }))
+    {
+        return Err(anyhow!("aggregates are not supported over joins"));
+    }
+
+    let query = join::qualify(query, config)?;
+    let Query::Select {
+        table,
+        joins,
+        fields,
+        filter,
+        order_by,
+        ..
+    } = &query
+    else {
+        unreachable!()
+    };
+    let table = join::joined_table(table, joins, config)?;
+
+    for Join { left, right, .. } in joins {
+        let left_type = &find_field(&table, left, Clause::Join)?.r#type;
+        let right_type = &find_field(&table, right, Clause::Join)?.r#type;
+        if !comparable(left_type, right_type) {
+            return Err(clause_error(
+                right,
+                Clause::Join,
+                format!("can't join {left} ({left_type}) with {right} ({right_type})"),
+            ));
+        }
+    }
+
+    check_select(&table, fields, &[], &None)?;
+    if let Some(filter) = filter {
+        check_predicate(&table, filter, config)?;
+    }
+    check_order_by(&table, fields, order_by)
+}
+
+fn check_having(table: &Table, having: &Having, group_by: &[String]) -> anyhow::Result<()> {
+    match having {
+        Having::Comparison { item, value, .. } => {
+            check_item(table, item, group_by, true, Clause::Having)?;
+            let r#type = item_type(table, item, Clause::Having)?;
+            match value.r#type() {
+                Some(value_type) if !comparable(&r#type, value_type) => Err(anyhow!(
+                    "expected {type} to compare with {item}, found {value}"
+                )),
+                _ => Ok(()),
+            }
+        }
+        Having::And(left, right) | Having::Or(left, right) => {
+            check_having(table, left, group_by)?;
+            check_having(table, right, group_by)
+        }
+        Having::Not(inner) => check_having(table, inner, group_by),
+    }
+}
+
+fn check_item(
+    table: &Table,
+    item: &SelectItem,
+    group_by: &[String],
+    aggregated: bool,
+    clause: Clause,
+) -> anyhow::Result<()> {
+    let table_name = &table.name;
+    match item {
+        SelectItem::Wildcard if aggregated => {
+            return Err(anyhow!("'*' can't be selected together with aggregates"));
+        }
+        SelectItem::Wildcard => (),
+        SelectItem::Column(column) => {
+            if !table.fields.iter().any(|field| field.name == *column) {
+                return Err(clause_error(
+                    column,
+                    clause,
+                    format!("missing fields {column:?} in table '{table_name}'"),
+                ));
+            }
+            if aggregated && !group_by.contains(column) {
+                return Err(clause_error(
+                    column,
+                    clause,
+                    format!(
+                        "field '{column}' must appear in the group by clause or be used inside an aggregate function"
+                    ),
+                ));
+            }
+        }
+        SelectItem::Expression(expr) => {
+            check_expr(table, expr, clause)?;
+            if aggregated {
+                check_grouped(expr, group_by, clause)?;
+            }
+        }
+        SelectItem::Aggregate { column: None, .. } => (),
+        SelectItem::Aggregate {
+            function,
+            column: Some(column),
+        } => {
+            let field = table
+                .fields
+                .iter()
+                .find(|field| field.name == *column)
+                .ok_or_else(|| {
+                    clause_error(
+                        column,
+                        clause,
+                        format!("missing fields {column:?} in table '{table_name}'"),
+                    )
+                })?;
+
+            match (function, field.r#type.as_str()) {
+                (Aggregate::Sum | Aggregate::Avg, "int" | "float") => (),
+                (Aggregate::Sum | Aggregate::Avg, r#type) => {
+                    return Err(clause_error(
+                        column,
+                        clause,
+                        format!("{function:?} requires a numeric field, '{column}' is {type}"),
+                    ));
+                }
+                (Aggregate::Min | Aggregate::Max, "bool") => {
+                    return Err(clause_error(
+                        column,
+                        clause,
+                        format!("{function:?} not supported for bool field '{column}'"),
+                    ));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the type of the values `expr` produces.
+fn check_expr<'a>(table: &'a Table, expr: &Expr, clause: Clause) -> anyhow::Result<&'a str> {
+    match expr {
+        Expr::Column(column) => table
+            .fields
+            .iter()
+            .find(|field| field.name == *column)
+            .map(|field| field.r#type.as_str())
+            .ok_or_else(|| {
+                clause_error(
+                    column,
+                    clause,
+                    format!("missing fields {column:?} in table '{}'", table.name),
+                )
+            }),
+        Expr::Literal(Literal::Placeholder(idx)) => Err(clause_error(
+            &format!("${idx}"),
+            clause,
+            format!("can't infer the type of placeholder ${idx}"),
+        )),
+        Expr::Literal(Literal::Null) => Err(anyhow!("NULL can't be used inside an expression")),
+        Expr::Literal(literal) => Ok(literal.r#type().unwrap()),
+        Expr::Binary { op, left, right } => {
+            let (left, right) = (
+                operand_type(table, *op, left, right, clause)?,
+                operand_type(table, *op, right, left, clause)?,
+            );
+            let numeric = |r#type| matches!(r#type, "int" | "float");
+            match op {
+                BinaryOp::Concat if left == "string" && right == "string" => Ok("string"),
+                BinaryOp::Concat => Err(anyhow!(
+                    "'||' requires string operands, found {left} and {right}"
+                )),
+                _ if !numeric(left) || !numeric(right) => Err(anyhow!(
+                    "'{op}' requires numeric operands, found {left} and {right}"
+                )),
+                BinaryOp::Div => Ok("float"),
+                _ if left == "float" || right == "float" => Ok("float"),
+                _ => Ok("int"),
+            }
+        }
+    }
+}
+
+/// Type of an operand of `op`, a placeholder takes the type of the other
+/// operand, or float when both of them are placeholders.
+fn operand_type<'a>(
+    table: &'a Table,
+    op: BinaryOp,
+    operand: &Expr,
+    other: &Expr,
+    clause: Clause,
+) -> anyhow::Result<&'a str> {
+    match (operand, other) {
+        (Expr::Literal(Literal::Placeholder(_)), _) if op == BinaryOp::Concat => Ok("string"),
+        (Expr::Literal(Literal::Placeholder(_)), Expr::Literal(Literal::Placeholder(_))) => {
+            Ok("float")
+        }
+        (Expr::Literal(Literal::Placeholder(_)), other) => check_expr(table, other, clause),
+        (operand, _) => check_expr(table, operand, clause),
+    }
+}
+
+fn check_grouped(expr: &Expr, group_by: &[String], clause: Clause) -> anyhow::Result<()> {
+    match expr {
+        Expr::Column(column) if !group_by.contains(column) => Err(clause_error(
+            column,
+            clause,
+            format!(
+                "field '{column}' must appear in the group by clause or be used inside an aggregate function"
+            ),
+        )),
+        Expr::Binary { left, right, .. } => {
+            check_grouped(left, group_by, clause)?;
+            check_grouped(right, group_by, clause)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_order_by(
+    table: &Table,
+    fields: &[SelectItem],
+    order_by: &[OrderBy],
+) -> anyhow::Result<()> {
+    for OrderBy { column, .. } in order_by {
+        if !table.fields.iter().any(|field| field.name == *column) {
+            return Err(clause_error(
+                column,
+                Clause::OrderBy,
+                format!("field '{column}' not found in {}", Clause::OrderBy),
+            ));
+        }
+        // Fragment results are merged on the returned columns.
+        let selected = fields.iter().any(|item| match item {
+            SelectItem::Wildcard => true,
+            SelectItem::Column(name) => name == column,
+            SelectItem::Aggregate { .. } | SelectItem::Expression(_) => false,
+        });
+        if !selected {
+            return Err(clause_error(
+                column,
+                Clause::OrderBy,
+                format!("order by field '{column}' must appear in the select list"),
             ));
         }
     }
@@ -136,7 +969,7 @@ fn check_fields(table_name: &str, fields: &[String], config: &Config) -> anyhow:
         .tables
         .iter()
         .find(|table| table.name == table_name)
-        .ok_or_else(|| anyhow!("table '{table_name}' not found"))?;
+        .ok_or_else(|| name_error(table_name, format!("table '{table_name}' not found")))?;
 
     let table_fields: Vec<_> = table.fields.iter().map(|field| &field.name).collect();
     let missing: Vec<_> = fields
@@ -147,9 +980,10 @@ fn check_fields(table_name: &str, fields: &[String], config: &Config) -> anyhow:
     if missing.is_empty() || missing.len() == 1 && missing[0] == "*" {
         Ok(())
     } else {
-        Err(anyhow::anyhow!(
-            "missing fields {:?} in table '{table_name}'",
-            missing
+        Err(clause_error(
+            missing[0],
+            Clause::Columns,
+            format!("missing fields {missing:?} in table '{table_name}'"),
         ))
     }
 }
@@ -158,6 +992,9 @@ fn check_table(table_name: &str, config: &Config) -> anyhow::Result<()> {
     if config.tables.iter().any(|table| table.name == table_name) {
         Ok(())
     } else {
-        Err(anyhow!("table '{table_name}' not found"))
+        Err(name_error(
+            table_name,
+            format!("table '{table_name}' not found"),
+        ))
     }
 }